@@ -14,11 +14,15 @@ pub use crate::removal::{Removal, rm_rf};
 
 // Re-export our custom caching utilities
 pub use crate::cache_key::{CacheKey, CacheKeyHasher, cache_digest};
+pub use crate::lock::{CacheLock, write_atomic};
+pub use crate::prune::{PrunePolicy, touch};
 pub use crate::timestamp::Timestamp;
 
 mod cache_key;
 #[cfg(feature = "clap")]
 mod cli;
+mod lock;
+mod prune;
 mod removal;
 mod timestamp;
 
@@ -78,6 +82,26 @@ impl AsRef<Utf8Path> for CacheEntry {
 pub struct CacheShard(Utf8PathBuf);
 
 impl CacheShard {
+    /// Create a new [`CacheShard`] from a path.
+    pub fn from_path(path: impl Into<Utf8PathBuf>) -> Self {
+        Self(path.into())
+    }
+
+    /// Wait for exclusive access to this shard, e.g. to update several of its files together.
+    pub fn lock(&self) -> Result<CacheLock, io::Error> {
+        CacheLock::exclusive(self.lock_path())
+    }
+
+    /// Wait until nobody is updating this shard, and keep them from starting until dropped.
+    pub fn lock_shared(&self) -> Result<CacheLock, io::Error> {
+        CacheLock::shared(self.lock_path())
+    }
+
+    /// The lock is next to the shard rather than in it, so it isn't mistaken for its contents.
+    pub fn lock_path(&self) -> Utf8PathBuf {
+        Utf8PathBuf::from(format!("{}.{LOCK_EXTENSION}", self.0))
+    }
+
     /// Return a [`CacheEntry`] within this shard.
     pub fn entry(&self, file: impl AsRef<Utf8Path>) -> CacheEntry {
         CacheEntry::new(&self.0, file)
@@ -109,6 +133,9 @@ impl Deref for CacheShard {
     }
 }
 
+/// The extension of the files [`CacheShard::lock`] locks.
+const LOCK_EXTENSION: &str = "lock";
+
 /// The main cache abstraction.
 #[derive(Debug, Clone)]
 pub struct Cache {
@@ -202,6 +229,11 @@ impl Cache {
 
     /// Run the garbage collector on the cache, removing any unused entries.
     pub fn prune(&self) -> Result<Removal, io::Error> {
+        self.prune_with(&PrunePolicy::default())
+    }
+
+    /// Run the garbage collector on the cache, then evict the entries `policy` says to.
+    pub fn prune_with(&self, policy: &PrunePolicy) -> Result<Removal, io::Error> {
         let mut summary = Removal::default();
 
         if !&self.root.exists() {
@@ -239,6 +271,8 @@ impl Cache {
             }
         }
 
+        summary += prune::evict(&self.root, policy)?;
+
         Ok(summary)
     }
 }
@@ -266,6 +300,8 @@ pub enum CacheBucket {
     Gemspec,
     /// Getting all transitive dependencies of a gem
     GemDeps,
+    /// Compiled native extensions, shared between projects.
+    Extension,
 }
 
 impl CacheBucket {
@@ -276,12 +312,22 @@ impl CacheBucket {
             Self::Git => "git-v0",
             Self::Gemspec => "gemspec-v0",
             Self::GemDeps => "gemdeps-v0",
+            Self::Extension => "extension-v0",
         }
     }
 
     /// Return an iterator over all cache buckets.
     pub fn iter() -> impl Iterator<Item = Self> {
-        [Self::Ruby, Self::Gem].iter().copied()
+        [
+            Self::Ruby,
+            Self::Gem,
+            Self::Git,
+            Self::Gemspec,
+            Self::GemDeps,
+            Self::Extension,
+        ]
+        .iter()
+        .copied()
     }
 }
 
@@ -336,8 +382,10 @@ mod tests {
     #[test]
     fn test_cache_bucket_iteration() {
         let buckets: Vec<_> = CacheBucket::iter().collect();
-        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets.len(), 6);
         assert!(buckets.contains(&CacheBucket::Ruby));
+        assert!(buckets.contains(&CacheBucket::Git));
+        assert!(buckets.contains(&CacheBucket::GemDeps));
     }
 
     #[test]
@@ -489,6 +537,8 @@ mod tests {
         let valid_bucket = cache_path.join("ruby-v0");
         fs_err::create_dir(&valid_bucket).unwrap();
         fs_err::write(valid_bucket.join("test.json"), "{}").unwrap();
+        let git_bucket = cache_path.join("git-v0");
+        fs_err::create_dir(&git_bucket).unwrap();
 
         // Create an invalid bucket directory (old version)
         let invalid_bucket = cache_path.join("ruby-v-0");
@@ -500,8 +550,9 @@ mod tests {
 
         let removal = cache.prune().unwrap();
 
-        // Valid bucket should remain
+        // Valid buckets should remain
         assert!(valid_bucket.exists());
+        assert!(git_bucket.exists());
 
         // Invalid bucket should be removed
         assert!(!invalid_bucket.exists());
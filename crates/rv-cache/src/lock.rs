@@ -0,0 +1,184 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use camino::Utf8Path;
+use tracing::debug;
+
+/// An advisory lock shared with every process using the cache, released when dropped.
+///
+/// Only rv processes that take the lock respect it, so it guards updates that touch several
+/// files, or take a while, like fetching a git repo. Single files are written with
+/// [`write_atomic`] instead, so readers never see them half-written.
+#[derive(Debug)]
+pub struct CacheLock(fs_err::File);
+
+impl CacheLock {
+    /// Wait until nobody else holds the lock at `path`, then hold it alone.
+    pub fn exclusive(path: impl AsRef<Path>) -> Result<Self, io::Error> {
+        let file = Self::open(path.as_ref())?;
+        if file.try_lock().is_err() {
+            debug!("Waiting for the cache lock at {}", file.path().display());
+            file.lock()?;
+        }
+        Ok(Self(file))
+    }
+
+    /// Wait until nobody holds the lock at `path` alone, then hold it alongside other readers.
+    pub fn shared(path: impl AsRef<Path>) -> Result<Self, io::Error> {
+        let file = Self::open(path.as_ref())?;
+        if file.try_lock_shared().is_err() {
+            debug!("Waiting for the cache lock at {}", file.path().display());
+            file.lock_shared()?;
+        }
+        Ok(Self(file))
+    }
+
+    /// Hold the lock at `path` alone, or return `None` right away if anybody else holds it.
+    pub fn try_exclusive(path: impl AsRef<Path>) -> Result<Option<Self>, io::Error> {
+        let file = Self::open(path.as_ref())?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(Self(file))),
+            Err(std::fs::TryLockError::WouldBlock) => Ok(None),
+            Err(std::fs::TryLockError::Error(err)) => Err(err),
+        }
+    }
+
+    fn open(path: &Path) -> Result<fs_err::File, io::Error> {
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        fs_err::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+    }
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        // Closing the file releases the lock anyway, so failing to unlock early is harmless.
+        if let Err(err) = self.0.unlock() {
+            debug!("Failed to release the cache lock: {err}");
+        }
+    }
+}
+
+/// Write `contents` to `path` all at once: other processes see either the old file or the new
+/// one, never a partly written one.
+pub fn write_atomic(
+    path: impl AsRef<Utf8Path>,
+    contents: impl AsRef<[u8]>,
+) -> Result<(), io::Error> {
+    let path = path.as_ref();
+    let parent = path.parent().expect("cache files have a parent directory");
+    fs_err::create_dir_all(parent)?;
+
+    let mut file = tempfile::NamedTempFile::new_in(parent)?;
+    file.write_all(contents.as_ref())?;
+    file.as_file().sync_all()?;
+    file.persist(path).map_err(|err| err.error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exclusive_lock_excludes_others() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("shard.lock");
+
+        let lock = CacheLock::exclusive(&path).unwrap();
+        assert!(CacheLock::try_exclusive(&path).unwrap().is_none());
+        drop(lock);
+        assert!(CacheLock::try_exclusive(&path).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_shared_locks_exclude_writers() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("nested/shard.lock");
+
+        let first = CacheLock::shared(&path).unwrap();
+        let second = CacheLock::shared(&path).unwrap();
+        assert!(CacheLock::try_exclusive(&path).unwrap().is_none());
+        drop((first, second));
+        assert!(CacheLock::try_exclusive(&path).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = camino::Utf8PathBuf::try_from(temp_dir.path().to_path_buf()).unwrap();
+        let path = dir.join("gems/a.gem");
+
+        write_atomic(&path, "one").unwrap();
+        write_atomic(&path, "two").unwrap();
+        assert_eq!(fs_err::read_to_string(&path).unwrap(), "two");
+        // No temporary files are left behind.
+        assert_eq!(fs_err::read_dir(dir.join("gems")).unwrap().count(), 1);
+    }
+
+    const STRESS_DIR: &str = "RV_CACHE_STRESS_DIR";
+    const STRESS_WORKERS: u8 = 4;
+    const STRESS_ROUNDS: usize = 50;
+    const STRESS_GEM_SIZE: usize = 256 * 1024;
+
+    /// Runs itself in several processes at once, which all write the same gem and bump the same
+    /// counter. Readers must never see a truncated gem, and no increment may be lost.
+    #[test]
+    fn test_concurrent_processes() {
+        if let Ok(dir) = std::env::var(STRESS_DIR) {
+            return stress_worker(Utf8Path::new(&dir));
+        }
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = camino::Utf8PathBuf::try_from(temp_dir.path().to_path_buf()).unwrap();
+        let workers: Vec<_> = (0..STRESS_WORKERS)
+            .map(|_| {
+                std::process::Command::new(std::env::current_exe().unwrap())
+                    .args([
+                        "--exact",
+                        "lock::tests::test_concurrent_processes",
+                        "--quiet",
+                    ])
+                    .env(STRESS_DIR, &dir)
+                    .spawn()
+                    .unwrap()
+            })
+            .collect();
+        for mut worker in workers {
+            assert!(worker.wait().unwrap().success());
+        }
+
+        let count = fs_err::read_to_string(dir.join("counter")).unwrap();
+        assert_eq!(
+            count.parse::<usize>().unwrap(),
+            usize::from(STRESS_WORKERS) * STRESS_ROUNDS
+        );
+    }
+
+    fn stress_worker(dir: &Utf8Path) {
+        let gem = dir.join("gems/a.gem");
+        let counter = dir.join("counter");
+        let contents = vec![u8::try_from(std::process::id() % 256).unwrap(); STRESS_GEM_SIZE];
+
+        for _ in 0..STRESS_ROUNDS {
+            write_atomic(&gem, &contents).unwrap();
+            let read = fs_err::read(&gem).unwrap();
+            assert_eq!(read.len(), STRESS_GEM_SIZE);
+            assert!(read.iter().all(|byte| *byte == read[0]));
+
+            let _lock = CacheLock::exclusive(dir.join("counter.lock")).unwrap();
+            let count = match fs_err::read_to_string(&counter) {
+                Ok(count) => count.parse::<usize>().unwrap(),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+                Err(err) => panic!("{err}"),
+            };
+            write_atomic(&counter, (count + 1).to_string()).unwrap();
+        }
+    }
+}
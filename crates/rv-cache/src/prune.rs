@@ -0,0 +1,319 @@
+use std::io;
+use std::time::{Duration, SystemTime};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use tracing::debug;
+
+use crate::{CacheBucket, CacheLock, CacheShard, LOCK_EXTENSION, Removal, rm_rf};
+
+/// Which entries to evict from the cache, on top of the outdated buckets that are always removed.
+///
+/// Entries are evicted least recently used first, by the newest modification time of anything in
+/// them. Writing an entry sets it, and so does [`touch`] when an entry is reused.
+#[derive(Debug, Clone, Default)]
+pub struct PrunePolicy {
+    /// Evict entries that haven't been used for this long.
+    max_age: Option<Duration>,
+    /// Evict entries until the cache is at most this many bytes.
+    max_size: Option<u64>,
+    /// Never evict these paths, or the entries containing them.
+    keep: Vec<Utf8PathBuf>,
+}
+
+impl PrunePolicy {
+    #[must_use]
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    #[must_use]
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    #[must_use]
+    pub fn with_keep(mut self, paths: impl IntoIterator<Item = Utf8PathBuf>) -> Self {
+        self.keep.extend(paths);
+        self
+    }
+
+    /// Does this policy evict anything at all?
+    pub fn is_empty(&self) -> bool {
+        self.max_age.is_none() && self.max_size.is_none()
+    }
+
+    fn keeps(&self, entry: &Utf8Path) -> bool {
+        self.keep
+            .iter()
+            .any(|path| path.starts_with(entry) || entry.starts_with(path))
+    }
+}
+
+/// Record that `path`, a cache entry or a file in one, was just used, so that it's evicted
+/// after entries that weren't.
+pub fn touch(path: impl AsRef<Utf8Path>) -> Result<(), io::Error> {
+    let mut options = fs_err::OpenOptions::new();
+    #[cfg(unix)]
+    options.read(true);
+    #[cfg(windows)]
+    {
+        use fs_err::os::windows::fs::OpenOptionsExt;
+        // FILE_WRITE_ATTRIBUTES, and FILE_FLAG_BACKUP_SEMANTICS so directories can be opened too.
+        options.access_mode(0x100).custom_flags(0x0200_0000);
+    }
+    options.open(path.as_ref())?.set_modified(SystemTime::now())
+}
+
+/// One evictable thing in the cache, e.g. a gem, a git repo or a gem server's index.
+#[derive(Debug)]
+struct Entry {
+    path: Utf8PathBuf,
+    bytes: u64,
+    last_used: SystemTime,
+}
+
+/// Evict the entries of every bucket in `root` that `policy` says to.
+pub(crate) fn evict(root: &Utf8Path, policy: &PrunePolicy) -> Result<Removal, io::Error> {
+    let mut summary = Removal::default();
+    if policy.is_empty() {
+        return Ok(summary);
+    }
+
+    let mut entries = Vec::new();
+    for bucket in CacheBucket::iter() {
+        entries.extend(bucket_entries(&root.join(bucket.to_str()))?);
+    }
+    entries.sort_by_key(|entry| entry.last_used);
+
+    let now = SystemTime::now();
+    let mut size: u64 = entries.iter().map(|entry| entry.bytes).sum();
+    for entry in entries {
+        if policy.keeps(&entry.path) {
+            continue;
+        }
+        let age = now.duration_since(entry.last_used).unwrap_or_default();
+        let too_old = policy.max_age.is_some_and(|max_age| age > max_age);
+        let too_big = policy.max_size.is_some_and(|max_size| size > max_size);
+        if !too_old && !too_big {
+            continue;
+        }
+        // Leave entries another process is updating right now alone; they're in use after all.
+        let lock_path = CacheShard::from_path(&entry.path).lock_path();
+        let _lock = if lock_path.exists() {
+            let Some(lock) = CacheLock::try_exclusive(&lock_path)? else {
+                debug!("Not evicting cache entry in use: {}", entry.path);
+                continue;
+            };
+            Some(lock)
+        } else {
+            None
+        };
+        debug!(
+            "Evicting cache entry unused for {}s: {}",
+            age.as_secs(),
+            entry.path
+        );
+        summary += rm_rf(&entry.path)?;
+        size -= entry.bytes;
+    }
+
+    Ok(summary)
+}
+
+/// The entries of a bucket are the children of its shards, e.g. `gem-v0/gems/<digest>.gem`.
+fn bucket_entries(bucket: &Utf8Path) -> Result<Vec<Entry>, io::Error> {
+    let mut entries = Vec::new();
+    if !bucket.is_dir() {
+        return Ok(entries);
+    }
+    let is_lock = |path: &Utf8Path| path.extension() == Some(LOCK_EXTENSION);
+    for shard in read_dir(bucket)? {
+        if is_lock(&shard) {
+            continue;
+        }
+        if !shard.is_dir() {
+            entries.push(entry(shard)?);
+            continue;
+        }
+        for path in read_dir(&shard)? {
+            if !is_lock(&path) {
+                entries.push(entry(path)?);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn entry(path: Utf8PathBuf) -> Result<Entry, io::Error> {
+    let (bytes, last_used) = usage(&path)?;
+    Ok(Entry {
+        path,
+        bytes,
+        last_used,
+    })
+}
+
+/// The total size of `path`, and the newest modification time of anything in it.
+fn usage(path: &Utf8Path) -> Result<(u64, SystemTime), io::Error> {
+    let metadata = fs_err::symlink_metadata(path)?;
+    let mut bytes = metadata.len();
+    let mut last_used = metadata.modified()?;
+    if metadata.is_dir() {
+        bytes = 0;
+        for child in read_dir(path)? {
+            let (child_bytes, child_used) = usage(&child)?;
+            bytes += child_bytes;
+            last_used = last_used.max(child_used);
+        }
+    }
+    Ok((bytes, last_used))
+}
+
+fn read_dir(path: &Utf8Path) -> Result<Vec<Utf8PathBuf>, io::Error> {
+    fs_err::read_dir(path)?
+        .map(|entry| {
+            Utf8PathBuf::try_from(entry?.path())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid UTF-8 path"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_with_gems(names: &[&str]) -> (tempfile::TempDir, Utf8PathBuf) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(temp_dir.path().to_path_buf()).unwrap();
+        let gems = root.join("gem-v0/gems");
+        fs_err::create_dir_all(&gems).unwrap();
+        // Each gem was last used a day after the one before it, and the last one just now.
+        let now = SystemTime::now();
+        for (days, name) in (0..names.len() as u64).rev().zip(names) {
+            let path = gems.join(name);
+            fs_err::write(&path, [0; 100]).unwrap();
+            fs_err::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(now - Duration::from_secs(24 * 3600 * days))
+                .unwrap();
+        }
+        (temp_dir, root)
+    }
+
+    #[test]
+    fn test_evict_nothing_without_limits() {
+        let (_temp_dir, root) = cache_with_gems(&["a.gem"]);
+        let removal = evict(&root, &PrunePolicy::default()).unwrap();
+        assert!(removal.is_empty());
+        assert!(root.join("gem-v0/gems/a.gem").exists());
+    }
+
+    #[test]
+    fn test_evict_by_size_least_recently_used_first() {
+        let (_temp_dir, root) = cache_with_gems(&["a.gem", "b.gem", "c.gem"]);
+        let removal = evict(&root, &PrunePolicy::default().with_max_size(250)).unwrap();
+        assert_eq!(removal.bytes, 100);
+        assert!(!root.join("gem-v0/gems/a.gem").exists());
+        assert!(root.join("gem-v0/gems/b.gem").exists());
+        assert!(root.join("gem-v0/gems/c.gem").exists());
+    }
+
+    #[test]
+    fn test_evict_keeps_recently_used_entries() {
+        let (_temp_dir, root) = cache_with_gems(&["a.gem", "b.gem", "c.gem"]);
+        // The oldest gem was just used again.
+        touch(root.join("gem-v0/gems/a.gem")).unwrap();
+
+        let removal = evict(&root, &PrunePolicy::default().with_max_size(250)).unwrap();
+        assert_eq!(removal.bytes, 100);
+        assert!(root.join("gem-v0/gems/a.gem").exists());
+        assert!(!root.join("gem-v0/gems/b.gem").exists());
+        assert!(root.join("gem-v0/gems/c.gem").exists());
+
+        let policy = PrunePolicy::default().with_max_age(Duration::from_secs(3600));
+        assert!(evict(&root, &policy).unwrap().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_touch_directory() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(temp_dir.path().to_path_buf()).unwrap();
+        let repo = root.join("git-v0/gits/abc");
+        fs_err::create_dir_all(&repo).unwrap();
+        fs_err::File::open(&repo)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH)
+            .unwrap();
+
+        touch(&repo).unwrap();
+        let modified = fs_err::metadata(&repo).unwrap().modified().unwrap();
+        assert!(modified > SystemTime::now() - Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn test_evict_by_age() {
+        let (_temp_dir, root) = cache_with_gems(&["a.gem", "b.gem"]);
+        let removal = evict(&root, &PrunePolicy::default().with_max_age(Duration::ZERO)).unwrap();
+        assert_eq!(removal.bytes, 200);
+        assert!(!root.join("gem-v0/gems/a.gem").exists());
+
+        let (_temp_dir, root) = cache_with_gems(&["a.gem"]);
+        let policy = PrunePolicy::default().with_max_age(Duration::from_secs(3600));
+        assert!(evict(&root, &policy).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_evict_keeps_paths() {
+        let (_temp_dir, root) = cache_with_gems(&["a.gem", "b.gem"]);
+        let index = root.join("gemdeps-v0/compact_index/server");
+        fs_err::create_dir_all(index.join("info")).unwrap();
+        fs_err::write(index.join("info/rack"), "---\n").unwrap();
+
+        let policy = PrunePolicy::default()
+            .with_max_size(0)
+            .with_keep([root.join("gem-v0/gems/a.gem"), index.join("info/rack")]);
+        evict(&root, &policy).unwrap();
+        assert!(root.join("gem-v0/gems/a.gem").exists());
+        assert!(!root.join("gem-v0/gems/b.gem").exists());
+        assert!(index.join("info/rack").exists());
+    }
+
+    #[test]
+    fn test_evict_directories_whole() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(temp_dir.path().to_path_buf()).unwrap();
+        let repo = root.join("git-v0/gits/abc");
+        fs_err::create_dir_all(repo.join("lib")).unwrap();
+        fs_err::write(repo.join("lib/a.rb"), "a").unwrap();
+
+        let removal = evict(&root, &PrunePolicy::default().with_max_size(0)).unwrap();
+        assert_eq!(removal.bytes, 1);
+        assert_eq!(removal.dirs, 2);
+        assert!(!repo.exists());
+        assert!(root.join("git-v0/gits").exists());
+    }
+
+    #[test]
+    fn test_evict_skips_locked_entries() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(temp_dir.path().to_path_buf()).unwrap();
+        let repo = CacheShard::from_path(root.join("git-v0/gits/abc"));
+        fs_err::create_dir_all(&*repo).unwrap();
+        fs_err::write(repo.join("HEAD"), "ref: refs/heads/main").unwrap();
+
+        let lock = repo.lock().unwrap();
+        evict(&root, &PrunePolicy::default().with_max_size(0)).unwrap();
+        assert!(repo.exists());
+        // The lock file itself is never evicted.
+        assert!(repo.lock_path().exists());
+
+        drop(lock);
+        evict(&root, &PrunePolicy::default().with_max_size(0)).unwrap();
+        assert!(!repo.exists());
+    }
+}
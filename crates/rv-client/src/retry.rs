@@ -0,0 +1,247 @@
+//! Retrying requests that failed for reasons that usually go away by themselves, like a reset
+//! connection, a 502 from an overloaded server, or a 429 from a rate limiter.
+
+use std::time::{Duration, SystemTime};
+
+use bytes::Bytes;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use tracing::debug;
+
+/// How many times, and how patiently, to retry a failed request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Don't retry at all.
+    pub fn none() -> Self {
+        Self::default().with_max_retries(0)
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// The delay before the first retry. Each retry after that waits twice as long.
+    pub fn with_base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// The longest to wait before a retry. If the server asks (with `Retry-After`) for a
+    /// longer wait than this, the request fails instead.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// Start counting attempts, for callers that need to retry more than just sending the
+    /// request, e.g. streaming a large response body to disk.
+    pub fn start(&self) -> Attempts<'_> {
+        Attempts {
+            policy: self,
+            retries: 0,
+        }
+    }
+
+    /// Send the request built by `request`, retrying if it fails or the server responds with
+    /// a status that's worth retrying. The last response is returned, even if it's an error.
+    pub async fn send(&self, request: impl Fn() -> RequestBuilder) -> reqwest::Result<Response> {
+        self.start().send(request).await
+    }
+
+    /// Send the request built by `request` and read the whole response body, retrying if
+    /// either fails.
+    pub async fn bytes(&self, request: impl Fn() -> RequestBuilder) -> reqwest::Result<Bytes> {
+        let mut attempts = self.start();
+        loop {
+            let response = attempts.send(&request).await?.error_for_status()?;
+            match response.bytes().await {
+                Err(err) if attempts.retry_after_error(&err).await => continue,
+                result => return result,
+            }
+        }
+    }
+
+    /// How long to wait before the given retry (counting from 0), if nothing else says.
+    fn backoff(&self, retry: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay)
+    }
+}
+
+/// Retries of a single request, so everything that can fail while handling it shares one
+/// budget of retries.
+#[derive(Debug)]
+pub struct Attempts<'a> {
+    policy: &'a RetryPolicy,
+    retries: u32,
+}
+
+impl Attempts<'_> {
+    /// Like [`RetryPolicy::send`], counting against this request's retries.
+    pub async fn send(
+        &mut self,
+        request: impl Fn() -> RequestBuilder,
+    ) -> reqwest::Result<Response> {
+        loop {
+            match request().send().await {
+                Ok(response) => {
+                    if !is_retryable_status(response.status()) || !self.has_retries_left() {
+                        return Ok(response);
+                    }
+                    let delay = match retry_after(response.headers()) {
+                        Some(delay) if delay > self.policy.max_delay => {
+                            debug!(
+                                "{} asked to retry after {delay:?}, which is too long to wait",
+                                response.url()
+                            );
+                            return Ok(response);
+                        }
+                        Some(delay) => delay,
+                        None => self.policy.backoff(self.retries),
+                    };
+                    debug!("{} responded with {}", response.url(), response.status());
+                    self.wait(delay).await;
+                }
+                Err(err) if self.retry_after_error(&err).await => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// If this error is worth retrying and there are retries left, wait before the next
+    /// attempt and return true.
+    pub async fn retry_after_error(&mut self, err: &reqwest::Error) -> bool {
+        if !is_retryable_error(err) || !self.has_retries_left() {
+            return false;
+        }
+        debug!("Request failed: {err}");
+        self.wait(self.policy.backoff(self.retries)).await;
+        true
+    }
+
+    fn has_retries_left(&self) -> bool {
+        self.retries < self.policy.max_retries
+    }
+
+    async fn wait(&mut self, delay: Duration) {
+        self.retries += 1;
+        debug!(
+            "Retrying in {delay:?} (retry {} of {})",
+            self.retries, self.policy.max_retries
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Statuses a server uses when it's overloaded, restarting, or rate limiting us.
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::REQUEST_TIMEOUT
+            | StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Errors from the network rather than from the request itself, e.g. a refused or reset
+/// connection, a timeout, or a response body cut off partway through.
+fn is_retryable_error(err: &reqwest::Error) -> bool {
+    if let Some(status) = err.status() {
+        return is_retryable_status(status);
+    }
+    !err.is_builder()
+        && !err.is_redirect()
+        && (err.is_connect()
+            || err.is_timeout()
+            || err.is_request()
+            || err.is_body()
+            || err.is_decode())
+}
+
+/// The wait the server asked for, given either as seconds or as an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::default()
+            .with_base_delay(Duration::from_secs(1))
+            .with_max_delay(Duration::from_secs(5));
+        let delays: Vec<_> = (0..5)
+            .map(|retry| policy.backoff(retry).as_secs())
+            .collect();
+        assert_eq!(delays, vec![1, 2, 4, 5, 5]);
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+
+        let later = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(600));
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(&later).unwrap());
+        let delay = retry_after(&headers).unwrap();
+        assert!(delay > Duration::from_secs(590) && delay <= Duration::from_secs(600));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn test_is_retryable_status() {
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable_status(StatusCode::NOT_FOUND));
+        assert!(!is_retryable_status(StatusCode::UNAUTHORIZED));
+        assert!(!is_retryable_status(StatusCode::OK));
+    }
+}
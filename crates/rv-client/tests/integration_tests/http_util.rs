@@ -2,7 +2,7 @@
 // https://github.com/astral-sh/uv/blob/7924ba5b1419345dc5b9a9a16e6bcba2b59a41a6/crates/uv-client/tests/it/http_util.rs
 
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use anyhow::{Context, Result};
 use futures::future;
@@ -19,6 +19,7 @@ use rcgen::{
 };
 use rustls::ServerConfig;
 use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpListener;
 use tokio::task::JoinHandle;
 use tokio_rustls::TlsAcceptor;
@@ -175,3 +176,39 @@ pub(crate) async fn start_https_user_agent_server(
 ) -> Result<(JoinHandle<Result<()>>, SocketAddr)> {
     TestServerBuilder { server_cert }.start().await
 }
+
+/// Plain HTTP server that answers each connection with the next of the given raw responses,
+/// then closes it. Raw responses make it possible to send ones that are cut off partway.
+/// Returns the server's address and a list of the requests it received.
+pub(crate) async fn start_scripted_http_server(
+    responses: Vec<String>,
+) -> Result<(SocketAddr, Arc<Mutex<Vec<String>>>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let received = requests.clone();
+    tokio::spawn(async move {
+        for response in responses {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                return;
+            };
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+            received
+                .lock()
+                .unwrap()
+                .push(String::from_utf8_lossy(&request).into_owned());
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        }
+    });
+
+    Ok((addr, requests))
+}
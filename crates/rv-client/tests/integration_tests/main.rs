@@ -1,2 +1,3 @@
 mod http_util;
+mod retry;
 mod ssl_certs;
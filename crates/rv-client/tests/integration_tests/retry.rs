@@ -0,0 +1,136 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use reqwest::StatusCode;
+use rv_client::retry::RetryPolicy;
+
+use crate::http_util::start_scripted_http_server;
+
+fn response(status: &str, headers: &[&str], body: &str) -> String {
+    let mut response = format!("HTTP/1.1 {status}\r\nConnection: close\r\n");
+    for header in headers {
+        response.push_str(header);
+        response.push_str("\r\n");
+    }
+    if !headers.iter().any(|h| h.starts_with("Content-Length")) {
+        response.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    response.push_str("\r\n");
+    response.push_str(body);
+    response
+}
+
+fn fast_policy() -> RetryPolicy {
+    RetryPolicy::default().with_base_delay(Duration::from_millis(10))
+}
+
+#[tokio::test]
+async fn test_retries_server_errors_then_succeeds() -> Result<()> {
+    let (addr, requests) = start_scripted_http_server(vec![
+        response("502 Bad Gateway", &[], ""),
+        response("503 Service Unavailable", &["Retry-After: 0"], ""),
+        response("200 OK", &[], "hello"),
+    ])
+    .await?;
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{addr}/gems/foo-1.0.0.gem");
+    let body = fast_policy().bytes(|| client.get(&url)).await?;
+
+    assert_eq!(body, "hello");
+    assert_eq!(requests.lock().unwrap().len(), 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_gives_up_after_max_retries() -> Result<()> {
+    let (addr, requests) = start_scripted_http_server(vec![
+        response("429 Too Many Requests", &[], ""),
+        response("429 Too Many Requests", &[], ""),
+        response("429 Too Many Requests", &[], ""),
+        response("200 OK", &[], "too late"),
+    ])
+    .await?;
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{addr}/");
+    let response = fast_policy()
+        .with_max_retries(2)
+        .send(|| client.get(&url))
+        .await?;
+
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(requests.lock().unwrap().len(), 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_does_not_retry_client_errors() -> Result<()> {
+    let (addr, requests) = start_scripted_http_server(vec![
+        response("404 Not Found", &[], ""),
+        response("200 OK", &[], "unreachable"),
+    ])
+    .await?;
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{addr}/");
+    let response = fast_policy().send(|| client.get(&url)).await?;
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(requests.lock().unwrap().len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_does_not_wait_longer_than_max_delay() -> Result<()> {
+    let (addr, requests) = start_scripted_http_server(vec![
+        response("503 Service Unavailable", &["Retry-After: 3600"], ""),
+        response("200 OK", &[], "unreachable"),
+    ])
+    .await?;
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{addr}/");
+    let response = fast_policy().send(|| client.get(&url)).await?;
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(requests.lock().unwrap().len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_retries_truncated_body() -> Result<()> {
+    let (addr, requests) = start_scripted_http_server(vec![
+        response("200 OK", &["Content-Length: 100"], "cut off"),
+        response("200 OK", &[], "complete"),
+    ])
+    .await?;
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{addr}/");
+    let body = fast_policy().bytes(|| client.get(&url)).await?;
+
+    assert_eq!(body, "complete");
+    assert_eq!(requests.lock().unwrap().len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_no_retries() -> Result<()> {
+    let (addr, requests) = start_scripted_http_server(vec![
+        response("500 Internal Server Error", &[], ""),
+        response("200 OK", &[], "unreachable"),
+    ])
+    .await?;
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{addr}/");
+    let err = RetryPolicy::none()
+        .bytes(|| client.get(&url))
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.status(), Some(StatusCode::INTERNAL_SERVER_ERROR));
+    assert_eq!(requests.lock().unwrap().len(), 1);
+    Ok(())
+}
@@ -450,6 +450,12 @@ mod tests {
         assert!(req("~> 1.0.0").satisfied_by(&v("1.0.1")));
         assert!(req("~> 1.0.0").satisfied_by(&v("1")));
         assert!(!req("~> 1.0.0").satisfied_by(&v("1.1")));
+
+        // ~> 3.0.0.beta.1 matches 3.0.0.beta.1 up to 3.0.x, like in Mastodon's lockfile
+        assert!(req("~> 3.0.0.beta.1").satisfied_by(&v("3.0.0.beta.1")));
+        assert!(req("~> 3.0.0.beta.1").satisfied_by(&v("3.0.0")));
+        assert!(req("~> 3.0.0.beta.1").satisfied_by(&v("3.0.9")));
+        assert!(!req("~> 3.0.0.beta.1").satisfied_by(&v("3.1.0")));
     }
 
     #[test]
@@ -540,6 +546,7 @@ mod tests {
             os: "macos".into(),
             gem_root: None,
             rubygems_platform: "arm64-darwin-23".to_string(),
+            abi: None,
         }
     }
 
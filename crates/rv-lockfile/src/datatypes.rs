@@ -1,5 +1,9 @@
-//! Most of the types in this module borrow a string from their input,
+//! Most of the types in this module can borrow a string from their input,
 //! so they have a lifetime 'i, which is short for 'input.
+//! Lockfiles built in memory instead of parsed can own their strings,
+//! and use the `'static` lifetime, see [`GemfileDotLock::into_owned`].
+
+use std::borrow::Cow;
 
 use rv_gem_types::requirement::Requirement;
 use rv_gem_types::{Platform, ProjectDependency, ReleaseTuple};
@@ -33,6 +37,84 @@ pub struct GemfileDotLock<'i> {
     pub checksums: Option<Vec<Checksum<'i>>>,
 }
 
+impl<'i> GemfileDotLock<'i> {
+    /// Copies every borrowed string, so the lockfile no longer borrows from its input.
+    pub fn into_owned(self) -> GemfileDotLock<'static> {
+        GemfileDotLock {
+            git: self.git.into_iter().map(GitSection::into_owned).collect(),
+            gem: self.gem.into_iter().map(GemSection::into_owned).collect(),
+            path: self.path.into_iter().map(PathSection::into_owned).collect(),
+            platforms: self.platforms,
+            dependencies: (self.dependencies.into_iter())
+                .map(GemRange::into_owned)
+                .collect(),
+            ruby_version: self.ruby_version,
+            bundled_with: self.bundled_with,
+            checksums: self
+                .checksums
+                .map(|checksums| checksums.into_iter().map(Checksum::into_owned).collect()),
+        }
+    }
+
+    /// Adds a spec from the given gem server, to the GEM section for that server.
+    /// The section is created if it doesn't exist yet.
+    pub fn with_gem(mut self, remote: impl Into<Cow<'i, str>>, spec: Spec) -> Self {
+        let remote = Some(remote.into());
+        match self.gem.iter_mut().find(|section| section.remote == remote) {
+            Some(section) => section.specs.push(spec),
+            None => self.gem.push(GemSection {
+                remote,
+                specs: vec![spec],
+            }),
+        }
+        self
+    }
+
+    /// Adds a GIT section.
+    pub fn with_git_source(mut self, section: GitSection<'i>) -> Self {
+        self.git.push(section);
+        self
+    }
+
+    /// Adds a PATH section.
+    pub fn with_path_source(mut self, section: PathSection<'i>) -> Self {
+        self.path.push(section);
+        self
+    }
+
+    /// Adds a platform, unless it's already in PLATFORMS.
+    pub fn with_platform(mut self, platform: Platform) -> Self {
+        if !self.platforms.contains(&platform) {
+            self.platforms.push(platform);
+        }
+        self
+    }
+
+    /// Adds a gem to DEPENDENCIES.
+    pub fn with_dependency(mut self, dependency: GemRange<'i>) -> Self {
+        self.dependencies.push(dependency);
+        self
+    }
+
+    /// Adds a checksum, creating the CHECKSUMS section if it doesn't exist yet.
+    pub fn with_checksum(mut self, checksum: Checksum<'i>) -> Self {
+        self.checksums.get_or_insert_default().push(checksum);
+        self
+    }
+
+    /// Sets the RUBY VERSION section.
+    pub fn with_ruby_version(mut self, ruby_version: RubyVersionSection) -> Self {
+        self.ruby_version = Some(ruby_version);
+        self
+    }
+
+    /// Sets the BUNDLED WITH section.
+    pub fn with_bundled_with(mut self, bundled_with: BundledWithSection) -> Self {
+        self.bundled_with = Some(bundled_with);
+        self
+    }
+}
+
 impl GemfileDotLock<'_> {
     /// Returns the total number of gem specs from RubyGems server sources.
     pub fn gem_spec_count(&self) -> usize {
@@ -98,39 +180,55 @@ impl std::fmt::Display for GemfileDotLock<'_> {
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct GitSection<'i> {
     /// Location of the Git repo.
-    pub remote: &'i str,
+    pub remote: Cow<'i, str>,
     /// Commit used from the Git repo.
-    pub revision: &'i str,
+    pub revision: Cow<'i, str>,
     /// Branch used from the Git repo.
-    pub branch: Option<&'i str>,
+    pub branch: Option<Cow<'i, str>>,
     /// Ref used from the Git repo.
-    pub git_ref: Option<&'i str>,
+    pub git_ref: Option<Cow<'i, str>>,
     /// Tag used from the Git repo.
-    pub tag: Option<&'i str>,
+    pub tag: Option<Cow<'i, str>>,
     /// Includes git submodules, or not.
     /// Optional, defaults to false.
     pub submodules: Option<bool>,
     /// Optional gemspec glob
-    pub glob: Option<&'i str>,
+    pub glob: Option<Cow<'i, str>>,
     /// All gems which came from this source in particular.
     pub specs: Vec<Spec>,
 }
 
+impl GitSection<'_> {
+    /// Copies every borrowed string, so the section no longer borrows from its input.
+    pub fn into_owned(self) -> GitSection<'static> {
+        GitSection {
+            remote: Cow::Owned(self.remote.into_owned()),
+            revision: Cow::Owned(self.revision.into_owned()),
+            branch: self.branch.map(|s| Cow::Owned(s.into_owned())),
+            git_ref: self.git_ref.map(|s| Cow::Owned(s.into_owned())),
+            tag: self.tag.map(|s| Cow::Owned(s.into_owned())),
+            submodules: self.submodules,
+            glob: self.glob.map(|s| Cow::Owned(s.into_owned())),
+            specs: self.specs,
+        }
+    }
+}
+
 impl std::fmt::Display for GitSection<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "GIT")?;
         writeln!(f, "  remote: {}", self.remote)?;
         writeln!(f, "  revision: {}", self.revision)?;
-        if let Some(git_ref) = self.git_ref {
+        if let Some(git_ref) = &self.git_ref {
             writeln!(f, "  ref: {git_ref}")?;
         }
-        if let Some(branch) = self.branch {
+        if let Some(branch) = &self.branch {
             writeln!(f, "  branch: {branch}")?;
         }
-        if let Some(glob) = self.glob {
+        if let Some(glob) = &self.glob {
             writeln!(f, "  glob: {glob}")?;
         }
-        if let Some(tag) = self.tag {
+        if let Some(tag) = &self.tag {
             writeln!(f, "  tag: {tag}")?;
         }
         if let Some(submodules) = self.submodules {
@@ -149,15 +247,25 @@ impl std::fmt::Display for GitSection<'_> {
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct GemSection<'i> {
     /// Location of the RubyGems server.
-    pub remote: Option<&'i str>,
+    pub remote: Option<Cow<'i, str>>,
     /// All gems which came from this source in particular.
     pub specs: Vec<Spec>,
 }
 
+impl GemSection<'_> {
+    /// Copies every borrowed string, so the section no longer borrows from its input.
+    pub fn into_owned(self) -> GemSection<'static> {
+        GemSection {
+            remote: self.remote.map(|s| Cow::Owned(s.into_owned())),
+            specs: self.specs,
+        }
+    }
+}
+
 impl std::fmt::Display for GemSection<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "GEM")?;
-        if let Some(remote) = self.remote {
+        if let Some(remote) = &self.remote {
             writeln!(f, "  remote: {remote}")?;
         }
         writeln!(f, "  specs:")?;
@@ -173,11 +281,21 @@ impl std::fmt::Display for GemSection<'_> {
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct PathSection<'i> {
     /// The filesystem path that sourced these dependencies.
-    pub remote: &'i str,
+    pub remote: Cow<'i, str>,
     /// All gems which came from this source in particular.
     pub specs: Vec<Spec>,
 }
 
+impl PathSection<'_> {
+    /// Copies every borrowed string, so the section no longer borrows from its input.
+    pub fn into_owned(self) -> PathSection<'static> {
+        PathSection {
+            remote: Cow::Owned(self.remote.into_owned()),
+            specs: self.specs,
+        }
+    }
+}
+
 impl std::fmt::Display for PathSection<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "PATH")?;
@@ -194,13 +312,24 @@ impl std::fmt::Display for PathSection<'_> {
 /// A range of possible versions of a certain gem.
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct GemRange<'i> {
-    pub name: &'i str,
+    pub name: Cow<'i, str>,
     pub requirement: Requirement,
     /// Dependencies specified with a source other than the main Rubygems index (e.g., git dependencies, path-based, dependencies) have a ! which means they are "pinned" to that source.
     /// According to <https://stackoverflow.com/questions/7517524/understanding-the-gemfile-lock-file>.
     pub nonstandard: bool,
 }
 
+impl GemRange<'_> {
+    /// Copies the gem name, so the range no longer borrows from its input.
+    pub fn into_owned(self) -> GemRange<'static> {
+        GemRange {
+            name: Cow::Owned(self.name.into_owned()),
+            requirement: self.requirement,
+            nonstandard: self.nonstandard,
+        }
+    }
+}
+
 impl std::fmt::Display for GemRange<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.name)?;
@@ -302,6 +431,23 @@ pub struct Checksum<'i> {
     pub value: Vec<u8>,
 }
 
+impl Checksum<'_> {
+    /// Copies the algorithm name, so the checksum no longer borrows from its input.
+    pub fn into_owned(self) -> Checksum<'static> {
+        Checksum {
+            release_tuple: self.release_tuple,
+            algorithm: match self.algorithm {
+                ChecksumAlgorithm::None => ChecksumAlgorithm::None,
+                ChecksumAlgorithm::Unknown(algo) => {
+                    ChecksumAlgorithm::Unknown(Cow::Owned(algo.into_owned()))
+                }
+                ChecksumAlgorithm::SHA256 => ChecksumAlgorithm::SHA256,
+            },
+            value: self.value,
+        }
+    }
+}
+
 impl std::fmt::Display for Checksum<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self.algorithm {
@@ -320,7 +466,7 @@ impl std::fmt::Display for Checksum<'_> {
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
 pub enum ChecksumAlgorithm<'i> {
     None,
-    Unknown(&'i str),
+    Unknown(Cow<'i, str>),
     #[default]
     SHA256,
 }
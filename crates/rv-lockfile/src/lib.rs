@@ -1,4 +1,5 @@
 pub mod datatypes;
+pub mod lint;
 mod parser;
 #[cfg(test)]
 mod tests;
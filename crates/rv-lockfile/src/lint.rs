@@ -0,0 +1,671 @@
+//! Checks that a Gemfile.lock is in the canonical form Bundler writes, and rewrites it if not.
+//!
+//! Bundler always writes lockfiles in the same order, so a lockfile that isn't in this form
+//! has usually been edited by hand (e.g. while resolving a merge conflict), and may confuse
+//! Bundler later.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use miette::{Diagnostic, SourceSpan};
+use rv_gem_types::ReleaseTuple;
+
+use crate::datatypes::{Checksum, ChecksumAlgorithm, GemfileDotLock, SerializeGemfileLock, Spec};
+use crate::parser::{CHECKSUMS, DEPENDENCIES, GEM, GIT, PATH, PLATFORMS, RUBY_VERSION};
+
+/// A rule that canonical lockfiles follow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LintRule {
+    /// Specs in each source are sorted by their full name.
+    UnsortedSpecs,
+    /// Each spec is only listed once per source.
+    DuplicateSpec,
+    /// The dependencies of each spec are sorted.
+    UnsortedSpecDependencies,
+    /// DEPENDENCIES is sorted by gem name.
+    UnsortedDependencies,
+    /// Each gem is only listed once in DEPENDENCIES.
+    DuplicateDependency,
+    /// A gem listed more than once in DEPENDENCIES has the same requirement each time.
+    ConflictingDependency,
+    /// PLATFORMS is sorted.
+    UnsortedPlatforms,
+    /// Each platform is only listed once in PLATFORMS.
+    DuplicatePlatform,
+    /// CHECKSUMS is sorted by the specs' full names.
+    UnsortedChecksums,
+    /// Each spec only has one CHECKSUMS entry.
+    DuplicateChecksum,
+    /// Every spec has a CHECKSUMS entry, if the lockfile has CHECKSUMS.
+    MissingChecksum,
+    /// Every CHECKSUMS entry belongs to a spec.
+    StaleChecksum,
+    /// RUBY VERSION and BUNDLED WITH use the same indentation.
+    InconsistentIndentation,
+    /// Each DEPENDENCIES requirement is satisfied by the spec locked for it.
+    UnsatisfiedDependency,
+    /// Gems locked from a GIT or PATH source are marked with `!` in DEPENDENCIES.
+    MissingSourceMarker,
+}
+
+impl std::fmt::Display for LintRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::UnsortedSpecs => "unsorted-specs",
+            Self::DuplicateSpec => "duplicate-spec",
+            Self::UnsortedSpecDependencies => "unsorted-spec-dependencies",
+            Self::UnsortedDependencies => "unsorted-dependencies",
+            Self::DuplicateDependency => "duplicate-dependency",
+            Self::ConflictingDependency => "conflicting-dependency",
+            Self::UnsortedPlatforms => "unsorted-platforms",
+            Self::DuplicatePlatform => "duplicate-platform",
+            Self::UnsortedChecksums => "unsorted-checksums",
+            Self::DuplicateChecksum => "duplicate-checksum",
+            Self::MissingChecksum => "missing-checksum",
+            Self::StaleChecksum => "stale-checksum",
+            Self::InconsistentIndentation => "inconsistent-indentation",
+            Self::UnsatisfiedDependency => "unsatisfied-dependency",
+            Self::MissingSourceMarker => "missing-source-marker",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, thiserror::Error, Diagnostic)]
+#[error("Lockfile is not in canonical form")]
+#[diagnostic(help("Run `rv lock --normalize` to fix what can be fixed automatically"))]
+pub struct LintErrors {
+    /// The Gemfile.lock contents
+    #[source_code]
+    lockfile_contents: String,
+
+    /// Every rule violation found.
+    #[related]
+    pub violations: Vec<Violation>,
+}
+
+#[derive(Debug, thiserror::Error, Diagnostic)]
+#[error("[{rule}] {msg}")]
+#[diagnostic()]
+pub struct Violation {
+    /// Which rule was violated.
+    pub rule: LintRule,
+
+    /// Where the violation is.
+    #[label("here")]
+    span: SourceSpan,
+
+    /// Error message
+    msg: String,
+}
+
+/// Checks the parsed `lockfile` against every [`LintRule`], reporting violations
+/// with spans into `contents`, which `lockfile` was parsed from.
+pub fn lint(contents: &str, lockfile: &GemfileDotLock<'_>) -> Result<(), LintErrors> {
+    let spans = Spans::locate(contents, lockfile);
+    let mut violations = Vec::new();
+    let mut report =
+        |rule, span: SourceSpan, msg: String| violations.push(Violation { rule, span, msg });
+
+    // Specs in every source, and their dependencies.
+    let sections = lockfile
+        .gem
+        .iter()
+        .map(|s| &s.specs)
+        .zip(&spans.gem)
+        .chain(lockfile.git.iter().map(|s| &s.specs).zip(&spans.git))
+        .chain(lockfile.path.iter().map(|s| &s.specs).zip(&spans.path));
+    for (specs, spec_spans) in sections {
+        check_order(
+            specs,
+            |spec| spec.release_tuple.full_name(),
+            |i| spec_spans[i].0,
+            (LintRule::UnsortedSpecs, LintRule::DuplicateSpec),
+            &mut report,
+        );
+        for (spec, (_, dep_spans)) in specs.iter().zip(spec_spans) {
+            check_order(
+                &spec.deps,
+                |dep| dep.to_string(),
+                |i| dep_spans[i],
+                (
+                    LintRule::UnsortedSpecDependencies,
+                    LintRule::UnsortedSpecDependencies,
+                ),
+                &mut report,
+            );
+        }
+    }
+
+    check_order(
+        &lockfile.platforms,
+        |platform| platform.to_string(),
+        |i| spans.platforms[i],
+        (LintRule::UnsortedPlatforms, LintRule::DuplicatePlatform),
+        &mut report,
+    );
+
+    // A gem listed twice with different requirements can't just be deduplicated, since it's
+    // unclear which requirement was meant.
+    for (i, pair) in lockfile.dependencies.windows(2).enumerate() {
+        let (prev, this) = (&pair[0], &pair[1]);
+        let span = spans.dependencies[i + 1];
+        if this.name < prev.name {
+            report(
+                LintRule::UnsortedDependencies,
+                span,
+                format!("{} should come before {}", this.name, prev.name),
+            );
+        } else if this.name == prev.name && this.requirement == prev.requirement {
+            report(
+                LintRule::DuplicateDependency,
+                span,
+                format!("{} is listed twice", this.name),
+            );
+        } else if this.name == prev.name {
+            report(
+                LintRule::ConflictingDependency,
+                span,
+                format!("{} is listed as both {prev} and {this}", this.name),
+            );
+        }
+    }
+
+    let all_specs: Vec<&Spec> = all_specs(lockfile).collect();
+    let sourced_specs: HashSet<&str> = lockfile
+        .git
+        .iter()
+        .flat_map(|s| &s.specs)
+        .chain(lockfile.path.iter().flat_map(|s| &s.specs))
+        .map(|spec| spec.release_tuple.name.as_str())
+        .collect();
+    for (dep, span) in lockfile.dependencies.iter().zip(&spans.dependencies) {
+        let mut locked = all_specs
+            .iter()
+            .filter(|spec| spec.release_tuple.name == dep.name)
+            .peekable();
+        if locked.peek().is_some()
+            && !locked.any(|spec| dep.requirement.satisfied_by(&spec.release_tuple.version))
+        {
+            report(
+                LintRule::UnsatisfiedDependency,
+                *span,
+                format!("no locked version of {} satisfies {dep}", dep.name),
+            );
+        }
+        if !dep.nonstandard && sourced_specs.contains(dep.name.as_ref()) {
+            report(
+                LintRule::MissingSourceMarker,
+                *span,
+                format!(
+                    "{} is locked from a GIT or PATH source, so it should be written as {}!",
+                    dep.name, dep
+                ),
+            );
+        }
+    }
+
+    if let Some(checksums) = &lockfile.checksums {
+        check_order(
+            checksums,
+            |checksum| checksum.release_tuple.full_name(),
+            |i| spans.checksums[i],
+            (LintRule::UnsortedChecksums, LintRule::DuplicateChecksum),
+            &mut report,
+        );
+
+        let checksummed: HashSet<&ReleaseTuple> =
+            checksums.iter().map(|c| &c.release_tuple).collect();
+        let spec_tuples: HashSet<&ReleaseTuple> =
+            all_specs.iter().map(|spec| &spec.release_tuple).collect();
+
+        for spec in &all_specs {
+            if !checksummed.contains(&spec.release_tuple) {
+                report(
+                    LintRule::MissingChecksum,
+                    spans.checksums_header,
+                    format!(
+                        "{} has no CHECKSUMS entry",
+                        spec.release_tuple.to_gemfile_lock()
+                    ),
+                );
+            }
+        }
+        for (checksum, span) in checksums.iter().zip(&spans.checksums) {
+            if !spec_tuples.contains(&checksum.release_tuple) {
+                report(
+                    LintRule::StaleChecksum,
+                    *span,
+                    format!(
+                        "{} is not locked, so it should not have a checksum",
+                        checksum.release_tuple.to_gemfile_lock()
+                    ),
+                );
+            }
+        }
+    }
+
+    if let (Some(ruby_version), Some(bundled_with)) =
+        (&lockfile.ruby_version, &lockfile.bundled_with)
+        && ruby_version.indentation != bundled_with.indentation
+    {
+        report(
+            LintRule::InconsistentIndentation,
+            spans.ruby_version,
+            "RUBY VERSION should be indented like BUNDLED WITH".to_owned(),
+        );
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(LintErrors {
+            lockfile_contents: contents.to_owned(),
+            violations,
+        })
+    }
+}
+
+/// Rewrites the lockfile into canonical form. This fixes violations of every [`LintRule`],
+/// except for [`LintRule::UnsatisfiedDependency`] and [`LintRule::ConflictingDependency`],
+/// which need the lockfile to be resolved again.
+/// Specs without checksums get an empty CHECKSUMS entry, like Bundler writes when a gem server
+/// doesn't provide checksums.
+pub fn normalize(lockfile: &mut GemfileDotLock<'_>) {
+    let sections = lockfile
+        .gem
+        .iter_mut()
+        .map(|s| &mut s.specs)
+        .chain(lockfile.git.iter_mut().map(|s| &mut s.specs))
+        .chain(lockfile.path.iter_mut().map(|s| &mut s.specs));
+    for specs in sections {
+        specs.sort_by_cached_key(|spec| spec.release_tuple.full_name());
+        specs.dedup_by(|a, b| a.release_tuple == b.release_tuple);
+        for spec in specs {
+            spec.deps.sort_by_cached_key(|dep| dep.to_string());
+            spec.deps.dedup();
+        }
+    }
+
+    lockfile.platforms.sort_by_cached_key(|p| p.to_string());
+    lockfile.platforms.dedup();
+
+    let sourced_specs: HashSet<String> = lockfile
+        .git
+        .iter()
+        .flat_map(|s| &s.specs)
+        .chain(lockfile.path.iter().flat_map(|s| &s.specs))
+        .map(|spec| spec.release_tuple.name.clone())
+        .collect();
+    for dep in &mut lockfile.dependencies {
+        if sourced_specs.contains(dep.name.as_ref()) {
+            dep.nonstandard = true;
+        }
+    }
+    lockfile.dependencies.sort_by(|a, b| {
+        a.name
+            .cmp(&b.name)
+            .then_with(|| a.requirement.cmp(&b.requirement))
+    });
+    lockfile
+        .dependencies
+        .dedup_by(|a, b| a.name == b.name && a.requirement == b.requirement);
+
+    let spec_tuples: Vec<ReleaseTuple> = all_specs(lockfile)
+        .map(|spec| spec.release_tuple.clone())
+        .collect();
+    if let Some(checksums) = &mut lockfile.checksums {
+        checksums.retain(|c| spec_tuples.contains(&c.release_tuple));
+        for release_tuple in spec_tuples {
+            if !checksums.iter().any(|c| c.release_tuple == release_tuple) {
+                checksums.push(Checksum {
+                    release_tuple,
+                    algorithm: ChecksumAlgorithm::None,
+                    value: Vec::new(),
+                });
+            }
+        }
+        checksums.sort_by_cached_key(|c| c.release_tuple.full_name());
+        checksums.dedup_by(|a, b| a.release_tuple == b.release_tuple);
+    }
+
+    if let (Some(ruby_version), Some(bundled_with)) =
+        (&mut lockfile.ruby_version, &lockfile.bundled_with)
+    {
+        ruby_version.indentation = bundled_with.indentation.clone();
+    }
+}
+
+fn all_specs<'a>(lockfile: &'a GemfileDotLock<'_>) -> impl Iterator<Item = &'a Spec> {
+    lockfile
+        .gem
+        .iter()
+        .flat_map(|s| &s.specs)
+        .chain(lockfile.git.iter().flat_map(|s| &s.specs))
+        .chain(lockfile.path.iter().flat_map(|s| &s.specs))
+}
+
+/// Reports every item which is out of order, or equal to the previous one, according to `key`.
+fn check_order<T, K: Ord + std::fmt::Display>(
+    items: &[T],
+    key: impl Fn(&T) -> K,
+    span: impl Fn(usize) -> SourceSpan,
+    (unsorted, duplicate): (LintRule, LintRule),
+    report: &mut impl FnMut(LintRule, SourceSpan, String),
+) {
+    let keys: Vec<K> = items.iter().map(key).collect();
+    for (i, pair) in keys.windows(2).enumerate() {
+        let (prev, this) = (&pair[0], &pair[1]);
+        if this == prev {
+            report(duplicate, span(i + 1), format!("{this} is listed twice"));
+        } else if this < prev {
+            report(
+                unsorted,
+                span(i + 1),
+                format!("{this} should come before {prev}"),
+            );
+        }
+    }
+}
+
+/// Where each item of a parsed lockfile is in the original text.
+struct Spans {
+    /// For each GEM section, the span of each spec, and of each of that spec's dependencies.
+    gem: Vec<Vec<(SourceSpan, Vec<SourceSpan>)>>,
+    git: Vec<Vec<(SourceSpan, Vec<SourceSpan>)>>,
+    path: Vec<Vec<(SourceSpan, Vec<SourceSpan>)>>,
+    platforms: Vec<SourceSpan>,
+    dependencies: Vec<SourceSpan>,
+    checksums: Vec<SourceSpan>,
+    checksums_header: SourceSpan,
+    ruby_version: SourceSpan,
+}
+
+impl Spans {
+    fn locate(contents: &str, lockfile: &GemfileDotLock<'_>) -> Self {
+        let sections = Sections::new(contents);
+        let spec_spans = |header: &str, nth: usize, specs: &[Spec]| {
+            let mut lines = sections.lines(header, nth);
+            specs
+                .iter()
+                .map(|spec| {
+                    let spec_span = lines.find(&spec.release_tuple.to_gemfile_lock());
+                    let dep_spans = spec
+                        .deps
+                        .iter()
+                        .map(|dep| lines.find(&dep.to_gemfile_lock()))
+                        .collect();
+                    (spec_span, dep_spans)
+                })
+                .collect()
+        };
+
+        let mut platform_lines = sections.lines(PLATFORMS, 0);
+        let mut dependency_lines = sections.lines(DEPENDENCIES, 0);
+        let mut checksum_lines = sections.lines(CHECKSUMS, 0);
+        let mut ruby_version_lines = sections.lines(RUBY_VERSION, 0);
+
+        Spans {
+            gem: (lockfile.gem.iter().enumerate())
+                .map(|(nth, s)| spec_spans(GEM, nth, &s.specs))
+                .collect(),
+            git: (lockfile.git.iter().enumerate())
+                .map(|(nth, s)| spec_spans(GIT, nth, &s.specs))
+                .collect(),
+            path: (lockfile.path.iter().enumerate())
+                .map(|(nth, s)| spec_spans(PATH, nth, &s.specs))
+                .collect(),
+            platforms: (lockfile.platforms.iter())
+                .map(|platform| platform_lines.find(&platform.to_string()))
+                .collect(),
+            dependencies: (lockfile.dependencies.iter())
+                .map(|dep| dependency_lines.find(&dep.to_string()))
+                .collect(),
+            checksums: (lockfile.checksums.iter().flatten())
+                .map(|checksum| checksum_lines.find(&checksum.to_string()))
+                .collect(),
+            checksums_header: checksum_lines.header,
+            ruby_version: match &lockfile.ruby_version {
+                Some(ruby_version) => ruby_version_lines.find(&ruby_version.to_string()),
+                None => ruby_version_lines.header,
+            },
+        }
+    }
+}
+
+/// The sections of a lockfile, i.e. each header and the text until the next header.
+struct Sections<'a> {
+    contents: &'a str,
+    headers: Vec<(&'a str, Range<usize>)>,
+}
+
+impl<'a> Sections<'a> {
+    fn new(contents: &'a str) -> Self {
+        let mut headers: Vec<(&str, Range<usize>)> = Vec::new();
+        let mut offset = 0;
+        for line in contents.split_inclusive('\n') {
+            let trimmed = line.trim_end();
+            if !trimmed.is_empty() && !trimmed.starts_with(' ') {
+                if let Some((_, range)) = headers.last_mut() {
+                    range.end = offset;
+                }
+                headers.push((trimmed, offset..contents.len()));
+            }
+            offset += line.len();
+        }
+        Self { contents, headers }
+    }
+
+    /// The lines of the `nth` section with the given header.
+    fn lines(&self, header: &str, nth: usize) -> Lines<'a> {
+        let range = self
+            .headers
+            .iter()
+            .filter(|(h, _)| *h == header)
+            .nth(nth)
+            .map_or(0..0, |(_, range)| range.clone());
+        Lines {
+            contents: self.contents,
+            header: (range.start, header.len()).into(),
+            pos: range.start,
+            end: range.end,
+        }
+    }
+}
+
+/// Finds lines in a section, in order.
+struct Lines<'a> {
+    contents: &'a str,
+    header: SourceSpan,
+    pos: usize,
+    end: usize,
+}
+
+impl Lines<'_> {
+    /// Finds the next line whose content (ignoring indentation) is `expected`.
+    /// Falls back to the section header, which shouldn't happen for anything that was parsed
+    /// from this section.
+    fn find(&mut self, expected: &str) -> SourceSpan {
+        let expected = expected.trim();
+        let mut offset = self.pos;
+        for line in self.contents[self.pos..self.end].split_inclusive('\n') {
+            let line_start = offset;
+            offset += line.len();
+            let indent = line.len() - line.trim_start().len();
+            if line.trim() == expected {
+                self.pos = offset;
+                return (line_start + indent, expected.len()).into();
+            }
+        }
+        self.header
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn violations(input: &str) -> Vec<(LintRule, String)> {
+        let lockfile = crate::parse(input).unwrap();
+        match lint(input, &lockfile) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors
+                .violations
+                .into_iter()
+                .map(|v| {
+                    let start = v.span.offset();
+                    (v.rule, input[start..start + v.span.len()].to_owned())
+                })
+                .collect(),
+        }
+    }
+
+    /// Normalizing a lockfile should fix everything except unsatisfied and conflicting
+    /// dependencies.
+    fn assert_normalizes(input: &str) {
+        let mut lockfile = crate::parse(input).unwrap();
+        normalize(&mut lockfile);
+        let normalized = lockfile.to_string();
+        let remaining: Vec<_> = violations(&normalized)
+            .into_iter()
+            .filter(|(rule, _)| {
+                ![
+                    LintRule::UnsatisfiedDependency,
+                    LintRule::ConflictingDependency,
+                ]
+                .contains(rule)
+            })
+            .collect();
+        assert_eq!(remaining, Vec::new(), "{normalized}");
+    }
+
+    #[test]
+    fn test_lint_rules() {
+        use LintRule::*;
+
+        for (input, expected) in [
+            (
+                include_str!("../tests/inputs/lint/Gemfile.unsorted-specs.lock"),
+                vec![(UnsortedSpecs, "rack (3.2.3)")],
+            ),
+            (
+                include_str!("../tests/inputs/lint/Gemfile.duplicate-spec.lock"),
+                vec![(DuplicateSpec, "rake (13.3.0)")],
+            ),
+            (
+                include_str!("../tests/inputs/lint/Gemfile.unsorted-spec-dependencies.lock"),
+                vec![(UnsortedSpecDependencies, "rspec-core (~> 3.13.0)")],
+            ),
+            (
+                include_str!("../tests/inputs/lint/Gemfile.unsorted-dependencies.lock"),
+                vec![(UnsortedDependencies, "rack")],
+            ),
+            (
+                include_str!("../tests/inputs/lint/Gemfile.duplicate-dependency.lock"),
+                vec![(DuplicateDependency, "rake")],
+            ),
+            (
+                include_str!("../tests/inputs/lint/Gemfile.conflicting-dependency.lock"),
+                vec![(ConflictingDependency, "rake (>= 13.0)")],
+            ),
+            (
+                include_str!("../tests/inputs/lint/Gemfile.unsorted-platforms.lock"),
+                vec![(UnsortedPlatforms, "arm64-darwin")],
+            ),
+            (
+                include_str!("../tests/inputs/lint/Gemfile.duplicate-platform.lock"),
+                vec![(DuplicatePlatform, "ruby")],
+            ),
+            (
+                include_str!("../tests/inputs/lint/Gemfile.unsorted-checksums.lock"),
+                vec![(
+                    UnsortedChecksums,
+                    "rack (3.2.3) sha256=1111111111111111111111111111111111111111111111111111111111111111",
+                )],
+            ),
+            (
+                include_str!("../tests/inputs/lint/Gemfile.duplicate-checksum.lock"),
+                vec![(
+                    DuplicateChecksum,
+                    "rake (13.3.0) sha256=2222222222222222222222222222222222222222222222222222222222222222",
+                )],
+            ),
+            (
+                include_str!("../tests/inputs/lint/Gemfile.missing-checksum.lock"),
+                vec![(MissingChecksum, "CHECKSUMS")],
+            ),
+            (
+                include_str!("../tests/inputs/lint/Gemfile.stale-checksum.lock"),
+                vec![(
+                    StaleChecksum,
+                    "rails (8.0.0) sha256=3333333333333333333333333333333333333333333333333333333333333333",
+                )],
+            ),
+            (
+                include_str!("../tests/inputs/lint/Gemfile.inconsistent-indentation.lock"),
+                vec![(InconsistentIndentation, "ruby 3.4.1p0")],
+            ),
+            (
+                include_str!("../tests/inputs/lint/Gemfile.unsatisfied-dependency.lock"),
+                vec![(UnsatisfiedDependency, "rake (~> 12.0)")],
+            ),
+            (
+                include_str!("../tests/inputs/lint/Gemfile.missing-source-marker.lock"),
+                vec![(MissingSourceMarker, "pathgem")],
+            ),
+        ] {
+            let expected: Vec<_> = expected
+                .into_iter()
+                .map(|(rule, text)| (rule, text.to_owned()))
+                .collect();
+            assert_eq!(violations(input), expected, "{input}");
+            assert_normalizes(input);
+        }
+    }
+
+    #[test]
+    fn test_normalize_fixes_everything_fixable() {
+        let input = include_str!("../tests/inputs/lint/Gemfile.everything-wrong.lock");
+        assert_eq!(violations(input).len(), 12);
+
+        let mut lockfile = crate::parse(input).unwrap();
+        normalize(&mut lockfile);
+        assert_eq!(
+            lockfile.to_string(),
+            include_str!("../tests/inputs/lint/Gemfile.everything-wrong.normalized.lock")
+        );
+    }
+
+    #[test]
+    fn test_normalize_keeps_conflicting_dependencies() {
+        let input = include_str!("../tests/inputs/lint/Gemfile.conflicting-dependency.lock");
+        let mut lockfile = crate::parse(input).unwrap();
+        normalize(&mut lockfile);
+
+        let dependencies: Vec<_> = lockfile
+            .dependencies
+            .iter()
+            .map(|dep| dep.to_string())
+            .collect();
+        assert_eq!(dependencies, ["rack", "rake (>= 13.0)", "rake (~> 13.3)"]);
+        assert_eq!(
+            violations(&lockfile.to_string()),
+            vec![(LintRule::ConflictingDependency, "rake (~> 13.3)".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_lint_bundler_lockfiles() {
+        // Lockfiles written by Bundler are already canonical.
+        for input in [
+            include_str!("../tests/inputs/Gemfile.tapioca.lock"),
+            include_str!("../tests/inputs/Gemfile.twosources.lock"),
+            include_str!("../tests/inputs/Gemfile.git-rails.lock"),
+            include_str!("../tests/inputs/Gemfile.discourse.lock"),
+            include_str!("../tests/inputs/Gemfile.minimal-ruby-project.lock"),
+            include_str!("../tests/inputs/Gemfile.mastodon.lock"),
+        ] {
+            assert_eq!(violations(input), Vec::new());
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use crate::{ParseError, ParseErrors, datatypes::*};
 use miette::SourceSpan;
 use winnow::{
@@ -14,14 +16,14 @@ use rv_gem_types::{Platform, ProjectDependency, ReleaseTuple};
 use rv_ruby::version::RubyVersion;
 use rv_version::{Version, VersionSegment};
 
-const GIT: &str = "GIT";
-const GEM: &str = "GEM";
-const PATH: &str = "PATH";
-const PLATFORMS: &str = "PLATFORMS";
-const DEPENDENCIES: &str = "DEPENDENCIES";
-const CHECKSUMS: &str = "CHECKSUMS";
-const RUBY_VERSION: &str = "RUBY VERSION";
-const BUNDLED_WITH: &str = "BUNDLED WITH";
+pub(crate) const GIT: &str = "GIT";
+pub(crate) const GEM: &str = "GEM";
+pub(crate) const PATH: &str = "PATH";
+pub(crate) const PLATFORMS: &str = "PLATFORMS";
+pub(crate) const DEPENDENCIES: &str = "DEPENDENCIES";
+pub(crate) const CHECKSUMS: &str = "CHECKSUMS";
+pub(crate) const RUBY_VERSION: &str = "RUBY VERSION";
+pub(crate) const BUNDLED_WITH: &str = "BUNDLED WITH";
 
 pub type Input<'a> = LocatingSlice<&'a str>;
 
@@ -210,7 +212,7 @@ fn parse_dependency<'i>(i: &mut Input<'i>) -> Res<GemRange<'i>> {
     let requirement = parse_requirement.parse_next(i)?;
     let nonstandard = opt('!').parse_next(i)?;
     Ok(GemRange {
-        name,
+        name: name.into(),
         requirement,
         nonstandard: nonstandard.is_some(),
     })
@@ -447,13 +449,13 @@ fn parse_git_section<'i>(i: &mut Input<'i>) -> Res<GitSection<'i>> {
     "  specs:\n".parse_next(i)?;
     let specs = repeat(0.., parse_spec).parse_next(i)?;
     Ok(GitSection {
-        branch,
-        git_ref,
-        tag,
-        remote,
-        revision,
+        branch: branch.map(Cow::Borrowed),
+        git_ref: git_ref.map(Cow::Borrowed),
+        tag: tag.map(Cow::Borrowed),
+        remote: remote.into(),
+        revision: revision.into(),
         submodules,
-        glob,
+        glob: glob.map(Cow::Borrowed),
         specs,
     })
 }
@@ -514,7 +516,10 @@ fn parse_gem<'i>(i: &mut Input<'i>) -> Res<GemSection<'i>> {
     let remote = opt(delimited("  remote: ", parse_remote, line_ending)).parse_next(i)?;
     "  specs:\n".parse_next(i)?;
     let specs = repeat(0.., parse_spec).parse_next(i)?;
-    Ok(GemSection { remote, specs })
+    Ok(GemSection {
+        remote: remote.map(Cow::Borrowed),
+        specs,
+    })
 }
 
 fn parse_path<'i>(i: &mut Input<'i>) -> Res<PathSection<'i>> {
@@ -522,7 +527,10 @@ fn parse_path<'i>(i: &mut Input<'i>) -> Res<PathSection<'i>> {
     let remote = delimited("  remote: ", parse_remote, line_ending).parse_next(i)?;
     "  specs:\n".parse_next(i)?;
     let specs = repeat(0.., parse_spec).parse_next(i)?;
-    Ok(PathSection { remote, specs })
+    Ok(PathSection {
+        remote: remote.into(),
+        specs,
+    })
 }
 
 fn parse_remote<'i>(i: &mut Input<'i>) -> Res<&'i str> {
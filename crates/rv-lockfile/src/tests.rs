@@ -234,3 +234,77 @@ fn test_spec_count_multiple_platforms() {
     assert_eq!(lockfile.spec_count(), 7);
     assert_eq!(lockfile.gem_spec_count(), 7);
 }
+
+#[test]
+fn test_into_owned() {
+    let input = include_str!("../tests/inputs/Gemfile.git-rails.lock").to_owned();
+    let owned = must_parse(&input).into_owned();
+    drop(input);
+    assert_eq!(
+        owned.to_string(),
+        include_str!("../tests/inputs/Gemfile.git-rails.lock")
+    );
+}
+
+#[test]
+fn test_build_lockfile() {
+    use crate::datatypes::*;
+    use rv_gem_types::{Platform, ProjectDependency, ReleaseTuple};
+
+    let spec = |name: &str, version: &str, deps: &[&str]| Spec {
+        release_tuple: ReleaseTuple::from((name.to_owned(), version.parse().unwrap(), None)),
+        deps: deps
+            .iter()
+            .map(|dep| ProjectDependency::new(dep.to_string(), Vec::<String>::new()).unwrap())
+            .collect(),
+    };
+    let checksum = |name: &str, version: &str, algorithm: ChecksumAlgorithm<'static>| Checksum {
+        release_tuple: ReleaseTuple::from((name.to_owned(), version.parse().unwrap(), None)),
+        value: if algorithm == ChecksumAlgorithm::None {
+            Vec::new()
+        } else {
+            vec![0xab; 32]
+        },
+        algorithm,
+    };
+    let dependency = |name: &str, nonstandard| GemRange {
+        name: name.to_owned().into(),
+        requirement: Default::default(),
+        nonstandard,
+    };
+
+    let lockfile: GemfileDotLock<'static> = GemfileDotLock::default()
+        .with_path_source(PathSection {
+            remote: String::from("vendor/mygem").into(),
+            specs: vec![spec("mygem", "0.1.0", &["rack"])],
+        })
+        .with_git_source(GitSection {
+            remote: String::from("https://github.com/rack/rack-session.git").into(),
+            revision: String::from("0123456789abcdef0123456789abcdef01234567").into(),
+            branch: Some(String::from("main").into()),
+            git_ref: None,
+            tag: None,
+            submodules: None,
+            glob: None,
+            specs: vec![spec("rack-session", "2.1.0", &["rack"])],
+        })
+        .with_gem("https://rubygems.org/", spec("rack", "3.2.3", &[]))
+        .with_gem("https://rubygems.org/", spec("rake", "13.3.0", &[]))
+        .with_platform(Platform::Ruby)
+        .with_platform(Platform::Ruby)
+        .with_dependency(dependency("mygem", true))
+        .with_dependency(dependency("rack-session", true))
+        .with_dependency(dependency("rake", false))
+        .with_checksum(checksum("mygem", "0.1.0", ChecksumAlgorithm::None))
+        .with_checksum(checksum("rack", "3.2.3", ChecksumAlgorithm::SHA256))
+        .with_checksum(checksum("rack-session", "2.1.0", ChecksumAlgorithm::None))
+        .with_checksum(checksum("rake", "13.3.0", ChecksumAlgorithm::SHA256));
+
+    // Both gems from the same server go in one GEM section, and platforms aren't duplicated.
+    assert_eq!(lockfile.gem.len(), 1);
+    assert_eq!(lockfile.platforms, vec![Platform::Ruby]);
+
+    let rendered = lockfile.to_string();
+    assert_eq!(must_parse(&rendered), lockfile);
+    assert!(crate::lint::lint(&rendered, &lockfile).is_ok());
+}
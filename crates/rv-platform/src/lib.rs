@@ -111,6 +111,24 @@ impl HostPlatform {
         }
     }
 
+    /// The platform string in TruffleRuby standalone asset filenames.
+    ///
+    /// For example, `truffleruby-community-24.2.1-linux-amd64.tar.gz` has platform
+    /// `"linux-amd64"`. TruffleRuby only builds standalones for glibc Linux and macOS, so other
+    /// platforms have none.
+    pub fn truffleruby_platform(&self) -> Option<&'static str> {
+        match self {
+            Self::MacosAarch64 => Some("macos-aarch64"),
+            Self::MacosX86_64 => Some("macos-amd64"),
+            Self::LinuxX86_64 => Some("linux-amd64"),
+            Self::LinuxAarch64 => Some("linux-aarch64"),
+            Self::LinuxMuslX86_64
+            | Self::LinuxMuslAarch64
+            | Self::WindowsX86_64
+            | Self::WindowsAarch64 => None,
+        }
+    }
+
     /// Whether this is a Windows platform.
     pub fn is_windows(&self) -> bool {
         matches!(self, Self::WindowsX86_64 | Self::WindowsAarch64)
@@ -267,6 +285,28 @@ mod tests {
         assert_eq!(HostPlatform::WindowsAarch64.archive_ext(), "7z");
     }
 
+    #[test]
+    fn test_truffleruby_platform() {
+        assert_eq!(
+            HostPlatform::MacosAarch64.truffleruby_platform(),
+            Some("macos-aarch64")
+        );
+        assert_eq!(
+            HostPlatform::MacosX86_64.truffleruby_platform(),
+            Some("macos-amd64")
+        );
+        assert_eq!(
+            HostPlatform::LinuxX86_64.truffleruby_platform(),
+            Some("linux-amd64")
+        );
+        assert_eq!(
+            HostPlatform::LinuxAarch64.truffleruby_platform(),
+            Some("linux-aarch64")
+        );
+        assert_eq!(HostPlatform::LinuxMuslX86_64.truffleruby_platform(), None);
+        assert_eq!(HostPlatform::WindowsX86_64.truffleruby_platform(), None);
+    }
+
     #[test]
     fn test_is_windows() {
         assert!(!HostPlatform::MacosAarch64.is_windows());
@@ -55,6 +55,9 @@ pub struct Release {
 pub struct Asset {
     pub name: String,
     pub browser_download_url: String,
+    /// The digest GitHub computed for this asset, like `sha256:<hex>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
 }
 
 pub trait Versioned {
@@ -126,6 +129,11 @@ pub struct Ruby {
 
     /// Rubygems platform string
     pub rubygems_platform: String,
+
+    /// ABI version, when it isn't the one `version` implies. JRuby and TruffleRuby report the
+    /// ABI of the CRuby they're compatible with, e.g. `3.3.0` for TruffleRuby 24.2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abi: Option<String>,
 }
 
 impl Versioned for Ruby {
@@ -183,7 +191,7 @@ impl Ruby {
                 .join("lib")
                 .join("ruby")
                 .join("gems")
-                .join(self.version.abi()),
+                .join(self.abi()),
         )
     }
 
@@ -216,7 +224,7 @@ impl Ruby {
 
     /// path scope for gems that can be safely shared with other rubies
     pub fn gem_scope(&self) -> String {
-        format!("{}/{}", self.version.engine.name(), self.version.abi())
+        format!("{}/{}", self.version.engine.name(), self.abi())
     }
 
     /// path scope for extensions
@@ -230,11 +238,16 @@ impl Ruby {
 
     fn extension_api_version(&self) -> String {
         if self.enable_shared {
-            self.version.abi()
+            self.abi()
         } else {
-            format!("{}-static", self.version.abi())
+            format!("{}-static", self.abi())
         }
     }
+
+    /// ABI compatibility version of this Ruby, which gems and their extensions are built for.
+    pub fn abi(&self) -> String {
+        self.abi.clone().unwrap_or_else(|| self.version.abi())
+    }
 }
 
 impl PartialOrd for Ruby {
@@ -303,6 +316,7 @@ fn extract_ruby_info(ruby_bin: &Utf8PathBuf) -> Result<Ruby, RubyError> {
         puts(Object.const_defined?(:RbConfig) && RbConfig::CONFIG['ENABLE_SHARED'] ? RbConfig::CONFIG['ENABLED_SHARED'] : 'no')
         puts(begin; Gem.default_dir; rescue ScriptError, NoMethodError; end)
         puts(Object.const_defined?(:RUBY_DESCRIPTION) ? RUBY_DESCRIPTION : '')
+        puts(Object.const_defined?(:RbConfig) && RbConfig::CONFIG['ruby_version'] ? RbConfig::CONFIG['ruby_version'] : '')
     "#;
 
     // On Windows, .cmd wrappers can't receive arguments containing special characters like (, ), ?
@@ -334,6 +348,7 @@ fn extract_ruby_info(ruby_bin: &Utf8PathBuf) -> Result<Ruby, RubyError> {
     let enable_shared = lines.next().unwrap_or("no");
     let gem_root = lines.next().unwrap_or_default();
     let description = lines.next().unwrap_or_default();
+    let abi = lines.next().unwrap_or_default();
     let ruby_description = parse_description(description);
 
     let host_cpu = if host_cpu != "unknown" {
@@ -363,6 +378,12 @@ fn extract_ruby_info(ruby_bin: &Utf8PathBuf) -> Result<Ruby, RubyError> {
         Some(Utf8PathBuf::from(gem_root))
     };
 
+    let abi = if abi.is_empty() || abi == version.abi() {
+        None
+    } else {
+        Some(abi.to_string())
+    };
+
     let key = format!("{version}-{os}-{arch}");
 
     Ok(Ruby {
@@ -374,6 +395,7 @@ fn extract_ruby_info(ruby_bin: &Utf8PathBuf) -> Result<Ruby, RubyError> {
         managed: false,
         enable_shared: enable_shared == "no",
         rubygems_platform: ruby_platform.to_string(),
+        abi,
         // path and symlink are replaced in the caller
         path: Default::default(),
         symlink: Default::default(),
@@ -456,6 +478,7 @@ fn ruby_049_version() -> Result<Ruby, RubyError> {
         managed: false,
         enable_shared: false,
         rubygems_platform,
+        abi: None,
         // path and symlink are replaced in the caller
         path: Default::default(),
         symlink: Default::default(),
@@ -503,6 +526,7 @@ mod tests {
             os: "macos".to_string(),
             gem_root: None,
             rubygems_platform: "arm64-darwin-23".to_string(),
+            abi: None,
         };
 
         let ruby2 = Ruby {
@@ -516,6 +540,7 @@ mod tests {
             os: "macos".to_string(),
             gem_root: None,
             rubygems_platform: "arm64-darwin-23".to_string(),
+            abi: None,
         };
 
         let ruby2_managed = Ruby {
@@ -529,6 +554,7 @@ mod tests {
             os: "macos".to_string(),
             gem_root: None,
             rubygems_platform: "arm64-darwin-23".to_string(),
+            abi: None,
         };
 
         let jruby = Ruby {
@@ -542,6 +568,7 @@ mod tests {
             os: "macos".to_string(),
             gem_root: None,
             rubygems_platform: "arm64-darwin-23".to_string(),
+            abi: None,
         };
 
         // Test version ordering within same implementation (higher versions last)
@@ -556,6 +583,33 @@ mod tests {
         assert!(ruby2_managed < jruby);
     }
 
+    #[test]
+    fn test_abi_reported_by_ruby() {
+        let mut truffleruby = Ruby {
+            key: "truffleruby-24.2.1-linux-x86_64".to_string(),
+            version: RubyVersion::from_str("truffleruby-24.2.1").unwrap(),
+            path: Utf8PathBuf::from("/tmp/truffleruby-24.2.1"),
+            managed: true,
+            enable_shared: true,
+            symlink: None,
+            arch: "x86_64".to_string(),
+            os: "linux".to_string(),
+            gem_root: None,
+            rubygems_platform: "x86_64-linux".to_string(),
+            abi: None,
+        };
+        assert_eq!(truffleruby.abi(), "24.2.0");
+
+        truffleruby.abi = Some("3.3.0".to_string());
+        assert_eq!(truffleruby.abi(), "3.3.0");
+        assert_eq!(truffleruby.gem_scope(), "truffleruby/3.3.0");
+        assert_eq!(truffleruby.extensions_scope(), "x86_64-linux/3.3.0");
+        assert_eq!(
+            truffleruby.gem_home(),
+            "/tmp/truffleruby-24.2.1/lib/ruby/gems/3.3.0"
+        );
+    }
+
     #[test]
     fn test_extract_ruby_info() {
         let ruby_path = Utf8PathBuf::from("/root/.local/share/rv/rubies/ruby-0.49/bin/ruby");
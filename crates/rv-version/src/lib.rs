@@ -216,9 +216,10 @@ impl Version {
     pub fn bump(&self) -> Version {
         let mut segments = self.segments.clone();
 
-        // Remove all trailing string segments (prerelease parts)
-        while segments.last().is_some_and(|s| s.is_string()) {
-            segments.pop();
+        // Remove the prerelease parts, i.e. everything from the first string segment on. Like
+        // RubyGems, this includes numbers after it, so `~> 3.0.0.beta.1` allows `3.0.x`.
+        if let Some(first_string) = segments.iter().position(|s| s.is_string()) {
+            segments.truncate(first_string);
         }
 
         // If there's more than one segment left, remove the last one
@@ -568,6 +569,8 @@ mod tests {
         assert_eq!(v("5.2.4").bump(), v("5.3"));
         assert_eq!(v("5.2.4.a").bump(), v("5.3"));
         assert_eq!(v("5.2.4.a10").bump(), v("5.3"));
+        assert_eq!(v("3.0.0.beta.1").bump(), v("3.1"));
+        assert_eq!(v("1.0.rc.2").bump(), v("2"));
         assert_eq!(v("5.0.0").bump(), v("5.1"));
         assert_eq!(v("5").bump(), v("6"));
     }
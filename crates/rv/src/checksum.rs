@@ -0,0 +1,36 @@
+use std::io::{self, Read};
+
+use camino::Utf8Path;
+use sha2::{Digest, Sha256};
+
+/// The SHA-256 digest of the file at `path`, in lowercase hex, read in chunks so large archives
+/// aren't loaded into memory.
+pub fn sha256_of(path: &Utf8Path) -> io::Result<String> {
+    let mut file = fs_err::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_of() {
+        let temp_dir = camino_tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("abc");
+        fs_err::write(&path, "abc").unwrap();
+        assert_eq!(
+            sha256_of(&path).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
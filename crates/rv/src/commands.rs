@@ -1,5 +1,6 @@
 pub mod cache;
 pub mod clean_install;
+pub mod lock;
 pub mod ruby;
 pub mod run;
 pub mod self_cmd;
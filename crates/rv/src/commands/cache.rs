@@ -6,6 +6,10 @@ use rv_cache::CleanReporter;
 
 use crate::{GlobalArgs, config::Config};
 
+pub mod bundle;
+pub mod prune;
+pub mod warm;
+
 #[derive(Args)]
 pub struct CacheCommandArgs {
     #[command(subcommand)]
@@ -16,10 +20,20 @@ pub struct CacheCommandArgs {
 pub enum CacheCommand {
     #[command(about = "Clear the cache")]
     Clean,
-    #[command(about = "Prune all unused entries from the cache")]
-    Prune,
+    #[command(
+        about = "Prune unused entries from the cache, optionally evicting old ones to save space"
+    )]
+    Prune(prune::PruneArgs),
     #[command(about = "Show the cache directory")]
     Dir,
+    #[command(
+        about = "Download what lockfiles, tools and Rubies need into the cache, without installing anything"
+    )]
+    Warm(warm::WarmArgs),
+    #[command(about = "Pack what lockfiles, tools and Rubies need from the cache into one archive")]
+    Export(bundle::ExportArgs),
+    #[command(about = "Unpack an archive from `rv cache export` into the cache")]
+    Import(bundle::ImportArgs),
 }
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
@@ -27,17 +41,26 @@ pub enum Error {
     IoError(#[from] std::io::Error),
     #[error(transparent)]
     Config(#[from] crate::config::Error),
+    #[error(transparent)]
+    Warm(#[from] warm::Error),
+    #[error(transparent)]
+    Bundle(#[from] bundle::Error),
+    #[error(transparent)]
+    Prune(#[from] prune::Error),
 }
 
 type Result<T> = miette::Result<T, Error>;
 
-pub(crate) fn cache(global_args: &GlobalArgs, args: CacheCommandArgs) -> Result<()> {
+pub(crate) async fn cache(global_args: &GlobalArgs, args: CacheCommandArgs) -> Result<()> {
     let config = &Config::new(global_args, None)?;
 
     match args.command {
         CacheCommand::Dir => cache_dir(config)?,
         CacheCommand::Clean => cache_clean(config)?,
-        CacheCommand::Prune => cache_prune(config)?,
+        CacheCommand::Prune(prune_args) => prune::prune(config, prune_args)?,
+        CacheCommand::Warm(warm_args) => warm::warm(global_args, warm_args).await?,
+        CacheCommand::Export(export_args) => bundle::export(global_args, export_args).await?,
+        CacheCommand::Import(import_args) => bundle::import(global_args, import_args)?,
     };
 
     Ok(())
@@ -62,14 +85,3 @@ fn cache_clean(config: &Config) -> Result<()> {
     );
     Ok(())
 }
-
-fn cache_prune(config: &Config) -> Result<()> {
-    let removal = config.cache.prune()?;
-    let num_bytes_cleaned = ByteSize::b(removal.bytes).display().iec_short();
-    println!(
-        "Removed {} directories, totalling {}",
-        removal.dirs.cyan(),
-        num_bytes_cleaned.cyan()
-    );
-    Ok(())
-}
@@ -0,0 +1,404 @@
+//! Moving the cache to machines without network access. `rv cache export` packs everything a
+//! project needs from the cache into one `.tar.zst` archive, and `rv cache import` unpacks it
+//! into another machine's cache, after checking every file against the archive's manifest.
+
+use std::collections::BTreeMap;
+use std::io;
+
+use anstream::println;
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use clap::Args;
+use owo_colors::OwoColorize;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use super::warm::{self, CacheTargets, Kind, ManifestEntry};
+use crate::{
+    GlobalArgs,
+    checksum::sha256_of,
+    config::{Config, RequestedRuby},
+};
+
+/// The name of the manifest inside a bundle.
+const MANIFEST: &str = "manifest.json";
+/// The version of the bundle format this rv writes and reads.
+const FORMAT: u32 = 1;
+
+#[derive(Args)]
+pub struct ExportArgs {
+    #[command(flatten)]
+    targets: CacheTargets,
+
+    /// Where to write the bundle, e.g. `bundle.tar.zst`
+    #[arg(short, long, value_name = "PATH")]
+    output: Utf8PathBuf,
+}
+
+#[derive(Args)]
+pub struct ImportArgs {
+    /// A bundle written by `rv cache export`
+    bundle: Utf8PathBuf,
+}
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    Config(#[from] crate::config::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+    #[error(transparent)]
+    Warm(#[from] warm::Error),
+    #[error("{path} is not in the cache at {root}")]
+    OutsideCache {
+        path: Utf8PathBuf,
+        root: Utf8PathBuf,
+    },
+    #[error("{bundle} is not a bundle written by `rv cache export`: {reason}")]
+    InvalidBundle { bundle: Utf8PathBuf, reason: String },
+    #[error("{path} in {bundle} doesn't match the checksum in its manifest")]
+    ChecksumMismatch { bundle: Utf8PathBuf, path: String },
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+/// The contents of a bundle.
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleManifest {
+    format: u32,
+    /// What's in the bundle, with paths relative to the cache root.
+    entries: Vec<ManifestEntry>,
+    /// The SHA-256 of every file in the bundle, by its path relative to the cache root.
+    files: BTreeMap<String, String>,
+}
+
+pub(crate) async fn export(global_args: &GlobalArgs, args: ExportArgs) -> Result<()> {
+    let config = &Config::with_settings(global_args, None)?;
+    let mut targets = args.targets;
+
+    // Without any Rubies asked for, include the one the project is pinned to.
+    if targets.rubies.is_empty()
+        && let RequestedRuby::Project((request, _)) = &config.requested_ruby
+    {
+        targets.rubies.push(request.clone());
+    }
+
+    let root = config.cache.root();
+    let mut entries = warm::warm_cache(global_args, config, targets).await?;
+    let mut files = BTreeMap::new();
+    for entry in &mut entries {
+        let mut paths = files_under(&entry.path)?;
+        // Keep the index's HTTP metadata, so it can still be updated incrementally.
+        if entry.kind == Kind::Index {
+            paths.extend(index_metadata_path(entry).filter(|path| path.exists()));
+        }
+        for path in paths {
+            files.insert(relative_to(&path, root)?, sha256_of(&path)?);
+        }
+        entry.path = relative_to(&entry.path, root)?.into();
+    }
+
+    let manifest = BundleManifest {
+        format: FORMAT,
+        entries,
+        files,
+    };
+    write_bundle(&args.output, root, &manifest)?;
+
+    warm::print_manifest(config, &manifest.entries, "Exported");
+    println!("Wrote {}", args.output.cyan());
+    Ok(())
+}
+
+pub(crate) fn import(global_args: &GlobalArgs, args: ImportArgs) -> Result<()> {
+    let config = &Config::new(global_args, None)?;
+    let root = config.cache.root();
+    let bundle = &args.bundle;
+
+    // Unpack next to the cache, so nothing is in it until every file has been checked, and
+    // then moving the files into place is just renaming them.
+    fs_err::create_dir_all(root)?;
+    let staging = camino_tempfile::tempdir_in(root)?;
+    let manifest = unpack_bundle(bundle, staging.path())?;
+
+    for relative in manifest.files.keys() {
+        let destination = root.join(relative);
+        debug!("Importing {destination}");
+        if let Some(parent) = destination.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        fs_err::rename(staging.path().join(relative), destination)?;
+    }
+
+    let entries: Vec<ManifestEntry> = manifest
+        .entries
+        .into_iter()
+        .map(|entry| ManifestEntry {
+            path: root.join(entry.path),
+            ..entry
+        })
+        .collect();
+    warm::print_manifest(config, &entries, "Imported");
+    Ok(())
+}
+
+/// Unpack `bundle` into `staging`, and check that it has exactly the files its manifest lists,
+/// with the right checksums. Anything but regular files and directories, like a symlink that
+/// could point outside the cache, is rejected before it's unpacked.
+fn unpack_bundle(bundle: &Utf8Path, staging: &Utf8Path) -> Result<BundleManifest> {
+    let invalid = |reason: String| Error::InvalidBundle {
+        bundle: bundle.to_owned(),
+        reason,
+    };
+
+    fs_err::create_dir_all(staging)?;
+    let decoder = zstd::Decoder::new(fs_err::File::open(bundle)?)?;
+    let mut archive = tar::Archive::new(decoder);
+    for entry in archive.entries().map_err(|err| invalid(err.to_string()))? {
+        let mut entry = entry.map_err(|err| invalid(err.to_string()))?;
+        let path = entry
+            .path()
+            .map_err(|err| invalid(err.to_string()))?
+            .display()
+            .to_string();
+        let entry_type = entry.header().entry_type();
+        if !entry_type.is_file() && !entry_type.is_dir() {
+            return Err(invalid(format!("{path} isn't a regular file or directory")));
+        }
+        if !entry
+            .unpack_in(staging)
+            .map_err(|err| invalid(err.to_string()))?
+        {
+            return Err(invalid(format!("{path} is outside the cache")));
+        }
+    }
+
+    let manifest: BundleManifest =
+        serde_json::from_str(&fs_err::read_to_string(staging.join(MANIFEST))?)
+            .map_err(|err| invalid(err.to_string()))?;
+    if manifest.format != FORMAT {
+        return Err(invalid(format!("unknown format {}", manifest.format)));
+    }
+
+    let unpacked = files_under(staging)?;
+    for path in &unpacked {
+        let relative = relative_to(path, staging)?;
+        if relative != MANIFEST && !manifest.files.contains_key(&relative) {
+            return Err(invalid(format!("{relative} isn't in its manifest")));
+        }
+    }
+    for (relative, sha256) in &manifest.files {
+        if !is_plain_relative(relative) {
+            return Err(invalid(format!("{relative} is outside the cache")));
+        }
+        let path = staging.join(relative);
+        if !fs_err::symlink_metadata(&path).is_ok_and(|metadata| metadata.is_file()) {
+            return Err(invalid(format!("{relative} is missing")));
+        }
+        if sha256_of(&path)? != *sha256 {
+            return Err(Error::ChecksumMismatch {
+                bundle: bundle.to_owned(),
+                path: relative.clone(),
+            });
+        }
+    }
+    Ok(manifest)
+}
+
+/// Pack `manifest` and every file it lists into a zstd-compressed tarball at `output`.
+fn write_bundle(output: &Utf8Path, root: &Utf8Path, manifest: &BundleManifest) -> Result<()> {
+    let encoder = zstd::Encoder::new(fs_err::File::create(output)?, 0)?;
+    let mut tar = tar::Builder::new(encoder);
+
+    let json = serde_json::to_vec_pretty(manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(json.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    tar.append_data(&mut header, MANIFEST, json.as_slice())?;
+
+    for relative in manifest.files.keys() {
+        tar.append_path_with_name(root.join(relative), relative)?;
+    }
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Every file in `path`, which is either a file or a directory, e.g. a git mirror. Symlinks
+/// aren't followed.
+fn files_under(path: &Utf8Path) -> Result<Vec<Utf8PathBuf>> {
+    if !fs_err::symlink_metadata(path)?.is_dir() {
+        return Ok(vec![path.to_owned()]);
+    }
+    let mut files = Vec::new();
+    for entry in fs_err::read_dir(path)? {
+        let path = Utf8PathBuf::try_from(entry?.path()).map_err(io::Error::other)?;
+        files.extend(files_under(&path)?);
+    }
+    Ok(files)
+}
+
+/// Where the HTTP metadata of a cached `info/` file is kept, next to the server's index.
+fn index_metadata_path(entry: &ManifestEntry) -> Option<Utf8PathBuf> {
+    let index_dir = entry.path.parent()?.parent()?;
+    Some(
+        index_dir
+            .join("metadata/info")
+            .join(format!("{}.json", entry.name)),
+    )
+}
+
+/// `path` relative to `root`, with `/` separators on every platform.
+fn relative_to(path: &Utf8Path, root: &Utf8Path) -> Result<String> {
+    let relative = path.strip_prefix(root).map_err(|_| Error::OutsideCache {
+        path: path.to_owned(),
+        root: root.to_owned(),
+    })?;
+    Ok(relative
+        .components()
+        .map(|component| component.as_str())
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+/// Is `path` relative, without any `..` that could take it outside the cache?
+fn is_plain_relative(path: &str) -> bool {
+    Utf8Path::new(path)
+        .components()
+        .all(|component| matches!(component, Utf8Component::Normal(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+
+    #[test]
+    fn test_is_plain_relative() {
+        assert!(is_plain_relative("gem-v0/gems/abc.gem"));
+        assert!(!is_plain_relative("../etc/passwd"));
+        assert!(!is_plain_relative("gems/../../etc/passwd"));
+        assert!(!is_plain_relative("/etc/passwd"));
+    }
+
+    /// Write a bundle of `files`, with a manifest listing `listed` and their checksums.
+    fn write_test_bundle(
+        bundle: &Utf8Path,
+        files: &[(&str, &[u8])],
+        listed: &[(&str, &[u8])],
+    ) -> tar::Builder<zstd::Encoder<'static, fs_err::File>> {
+        let manifest = BundleManifest {
+            format: FORMAT,
+            entries: Vec::new(),
+            files: listed
+                .iter()
+                .map(|(path, content)| (path.to_string(), hex::encode(Sha256::digest(content))))
+                .collect(),
+        };
+        let encoder = zstd::Encoder::new(fs_err::File::create(bundle).unwrap(), 0).unwrap();
+        let mut tar = tar::Builder::new(encoder);
+        let json = serde_json::to_vec(&manifest).unwrap();
+        for (path, content) in [(MANIFEST, json.as_slice())]
+            .into_iter()
+            .chain(files.iter().copied())
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(&mut header, path, content).unwrap();
+        }
+        tar
+    }
+
+    #[test]
+    fn test_unpack_bundle() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("bundle.tar.zst");
+        let gem: (&str, &[u8]) = ("gem-v0/gems/abc.gem", b"gem");
+        write_test_bundle(&bundle, &[gem], &[gem])
+            .into_inner()
+            .unwrap()
+            .finish()
+            .unwrap();
+
+        let staging = dir.path().join("staging");
+        let manifest = unpack_bundle(&bundle, &staging).unwrap();
+        assert_eq!(manifest.files.len(), 1);
+        assert_eq!(fs_err::read(staging.join(gem.0)).unwrap(), b"gem");
+    }
+
+    #[test]
+    fn test_unpack_bundle_rejects_unlisted_file() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("bundle.tar.zst");
+        let gem: (&str, &[u8]) = ("gem-v0/gems/abc.gem", b"gem");
+        let extra: (&str, &[u8]) = ("gem-v0/gems/evil.gem", b"evil");
+        write_test_bundle(&bundle, &[gem, extra], &[gem])
+            .into_inner()
+            .unwrap()
+            .finish()
+            .unwrap();
+
+        let err = unpack_bundle(&bundle, &dir.path().join("staging")).unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidBundle { reason, .. } if reason == "gem-v0/gems/evil.gem isn't in its manifest"),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn test_unpack_bundle_rejects_checksum_mismatch() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("bundle.tar.zst");
+        write_test_bundle(
+            &bundle,
+            &[("gem-v0/gems/abc.gem", b"tampered")],
+            &[("gem-v0/gems/abc.gem", b"gem")],
+        )
+        .into_inner()
+        .unwrap()
+        .finish()
+        .unwrap();
+
+        let err = unpack_bundle(&bundle, &dir.path().join("staging")).unwrap_err();
+        assert!(
+            matches!(&err, Error::ChecksumMismatch { path, .. } if path == "gem-v0/gems/abc.gem"),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn test_unpack_bundle_rejects_symlink() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("bundle.tar.zst");
+        let mut tar = write_test_bundle(&bundle, &[], &[("gem-v0/gems/abc.gem", b"gem")]);
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        tar.append_link(&mut header, "gem-v0/gems/abc.gem", "/etc/passwd")
+            .unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+
+        let staging = dir.path().join("staging");
+        let err = unpack_bundle(&bundle, &staging).unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidBundle { reason, .. } if reason == "gem-v0/gems/abc.gem isn't a regular file or directory"),
+            "{err:?}"
+        );
+        assert!(!staging.join("gem-v0").exists());
+    }
+
+    #[test]
+    fn test_relative_to() {
+        let root = Utf8Path::new("/cache");
+        assert_eq!(
+            relative_to(Utf8Path::new("/cache/gem-v0/gems/abc.gem"), root).unwrap(),
+            "gem-v0/gems/abc.gem"
+        );
+        assert!(relative_to(Utf8Path::new("/elsewhere/abc.gem"), root).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+//! Keeping the cache from growing without bound, e.g. on shared CI runners. Entries are evicted
+//! least recently used first, except those a lockfile given with `--keep-lockfile` needs.
+
+use std::time::Duration;
+
+use anstream::println;
+use bytesize::ByteSize;
+use camino::Utf8PathBuf;
+use clap::Args;
+use owo_colors::OwoColorize;
+use rv_cache::PrunePolicy;
+use tracing::debug;
+
+use crate::{commands::clean_install, config::Config};
+
+#[derive(Args)]
+pub struct PruneArgs {
+    /// Evict entries that haven't been used for this long, e.g. `30d`, `12h` or `2w`
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    max_age: Option<Duration>,
+
+    /// Evict the least recently used entries until the cache is at most this big, e.g. `10GiB`
+    #[arg(long, value_name = "SIZE")]
+    max_size: Option<ByteSize>,
+
+    /// Never evict what this Gemfile.lock needs. Can be given several times.
+    #[arg(long = "keep-lockfile", value_name = "PATH")]
+    keep_lockfiles: Vec<Utf8PathBuf>,
+}
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Parse(#[from] rv_lockfile::ParseErrors),
+    #[error(transparent)]
+    Gems(#[from] clean_install::Error),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+pub(crate) fn prune(config: &Config, args: PruneArgs) -> Result<()> {
+    let mut policy = PrunePolicy::default();
+    if let Some(max_age) = args.max_age {
+        policy = policy.with_max_age(max_age);
+    }
+    if let Some(max_size) = args.max_size {
+        policy = policy.with_max_size(max_size.as_u64());
+    }
+    for path in args.keep_lockfiles {
+        debug!("Keeping everything {path} needs");
+        let contents = fs_err::read_to_string(&path)?;
+        let contents = rv_lockfile::normalize_line_endings(&contents);
+        let lockfile = rv_lockfile::parse(&contents)?;
+        policy = policy.with_keep(clean_install::cached_paths(config, &lockfile)?);
+    }
+
+    let removal = config.cache.prune_with(&policy)?;
+    let num_bytes_cleaned = ByteSize::b(removal.bytes).display().iec_short();
+    println!(
+        "Removed {} directories, totalling {}",
+        removal.dirs.cyan(),
+        num_bytes_cleaned.cyan()
+    );
+    Ok(())
+}
+
+/// Parse a number of seconds, minutes, hours, days or weeks, e.g. `30d`.
+fn parse_duration(value: &str) -> std::result::Result<Duration, String> {
+    let invalid = || "expected a number followed by s, m, h, d or w, e.g. `30d`".to_owned();
+    let unit_at = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (amount, unit) = value.split_at(unit_at);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    Ok(Duration::from_secs(amount.saturating_mul(seconds)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("45s"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_duration("90m"), Ok(Duration::from_secs(90 * 60)));
+        assert_eq!(parse_duration("30d"), Ok(Duration::from_secs(30 * 86400)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * 86400)));
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3 days").is_err());
+    }
+}
@@ -0,0 +1,243 @@
+//! Filling the cache ahead of time, e.g. in a build stage with network access, so that a later
+//! stage can install everything with `--offline`. Nothing is installed.
+
+use std::collections::BTreeSet;
+
+use anstream::println;
+use camino::Utf8PathBuf;
+use clap::Args;
+use futures_util::{StreamExt, TryStreamExt};
+use owo_colors::OwoColorize;
+use rv_lockfile::datatypes::GemfileDotLock;
+use rv_ruby::request::RubyRequest;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use url::Url;
+
+use crate::{
+    GlobalArgs,
+    commands::{clean_install, ruby, tool::install::ToolRelease},
+    config::Config,
+    gemserver::{self, Gemserver},
+};
+
+/// What to put in the cache.
+#[derive(Args)]
+pub struct CacheTargets {
+    /// A Gemfile.lock to cache the gems and compact index entries of. Can be given several times.
+    #[arg(long = "lockfile", value_name = "PATH")]
+    pub lockfiles: Vec<Utf8PathBuf>,
+
+    /// A gem to cache as a tool, with everything it depends on, e.g. `rubocop` or
+    /// `rubocop@1.80.0`. Can be given several times.
+    #[arg(long = "tool", value_name = "GEM")]
+    pub tools: Vec<String>,
+
+    /// What gem server to look up tools on.
+    #[arg(long, default_value = "https://gem.coop/")]
+    pub gem_server: String,
+
+    /// A Ruby to cache the archive of, e.g. `3.4` or `3.4.5`. Can be given several times.
+    #[arg(long = "ruby", value_name = "VERSION")]
+    pub rubies: Vec<RubyRequest>,
+
+    /// Maximum number of downloads that can be in flight at once.
+    #[arg(long, hide = true, default_value = "10")]
+    pub max_concurrent_requests: usize,
+
+    /// Maximum number of git repos that can be cloned at once.
+    #[arg(long, hide = true, default_value = "20")]
+    pub max_concurrent_installs: usize,
+}
+
+#[derive(Args)]
+pub struct WarmArgs {
+    #[command(flatten)]
+    targets: CacheTargets,
+
+    /// Also write the manifest of what was cached to this file, as JSON
+    #[arg(long, value_name = "PATH")]
+    manifest: Option<Utf8PathBuf>,
+}
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    Config(#[from] crate::config::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+    #[error(transparent)]
+    Parse(#[from] rv_lockfile::ParseErrors),
+    #[error("Invalid remote URL {remote}")]
+    BadRemote {
+        remote: String,
+        err: url::ParseError,
+    },
+    #[error(transparent)]
+    Gemserver(#[from] gemserver::Error),
+    #[error(transparent)]
+    Gems(#[from] clean_install::Error),
+    #[error(transparent)]
+    Tool(#[from] crate::commands::tool::install::Error),
+    #[error(transparent)]
+    Ruby(#[from] ruby::install::Error),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+/// Something that was put in the cache.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub kind: Kind,
+    /// The Ruby's version, the gem's full name, or the git repo's remote and revision.
+    pub name: String,
+    pub path: Utf8PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Ruby,
+    Index,
+    Gem,
+    Git,
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            Kind::Ruby => "ruby",
+            Kind::Index => "index",
+            Kind::Gem => "gem",
+            Kind::Git => "git",
+        };
+        write!(f, "{kind}")
+    }
+}
+
+pub(crate) async fn warm(global_args: &GlobalArgs, args: WarmArgs) -> Result<()> {
+    let config = &Config::with_settings(global_args, None)?;
+    let manifest = warm_cache(global_args, config, args.targets).await?;
+
+    print_manifest(config, &manifest, "Cached");
+
+    if let Some(path) = args.manifest {
+        fs_err::write(path, serde_json::to_string_pretty(&manifest)?)?;
+    }
+
+    Ok(())
+}
+
+/// Download everything `targets` needs into the cache, and list what's there.
+pub(crate) async fn warm_cache(
+    global_args: &GlobalArgs,
+    config: &Config,
+    targets: CacheTargets,
+) -> Result<Vec<ManifestEntry>> {
+    let mut manifest = Vec::new();
+
+    for request in &targets.rubies {
+        let config = Config::with_settings(global_args, Some(request.clone()))?;
+        let (version, path) = ruby::install::cache_archive(&config).await?;
+        manifest.push(ManifestEntry {
+            kind: Kind::Ruby,
+            name: version,
+            path,
+        });
+    }
+
+    for path in &targets.lockfiles {
+        debug!("Caching everything in {path}");
+        let contents = fs_err::read_to_string(path)?;
+        let contents = rv_lockfile::normalize_line_endings(&contents);
+        let lockfile = rv_lockfile::parse(&contents)?;
+        cache_lockfile(config, lockfile, &targets, &mut manifest).await?;
+    }
+
+    for gem in &targets.tools {
+        debug!("Caching the tool {gem}");
+        let tool =
+            ToolRelease::find(config, gem.clone(), targets.gem_server.clone(), Vec::new()).await?;
+        let (_, lockfile) = tool.resolve(config).await?;
+        cache_lockfile(config, lockfile, &targets, &mut manifest).await?;
+    }
+
+    Ok(manifest)
+}
+
+/// Show what's in the cache now, e.g. "Cached 3 files in ~/.cache/rv".
+pub(crate) fn print_manifest(config: &Config, manifest: &[ManifestEntry], done: &str) {
+    for entry in manifest {
+        println!("{:>5} {} {}", entry.kind, entry.name.cyan(), entry.path);
+    }
+    println!(
+        "{done} {} files in {}",
+        manifest.len().cyan(),
+        config.cache.root().cyan()
+    );
+}
+
+/// Cache the compact index entries, gems and git repos `lockfile` needs.
+async fn cache_lockfile(
+    config: &Config,
+    lockfile: GemfileDotLock<'_>,
+    targets: &CacheTargets,
+    manifest: &mut Vec<ManifestEntry>,
+) -> Result<()> {
+    for gem_source in &lockfile.gem {
+        let Some(remote) = gem_source.remote.as_deref() else {
+            continue;
+        };
+        let url = Url::parse(remote).map_err(|err| Error::BadRemote {
+            remote: remote.to_owned(),
+            err,
+        })?;
+        let server = Gemserver::new(config, url).await?;
+        let names: BTreeSet<&str> = gem_source
+            .specs
+            .iter()
+            .map(|spec| spec.release_tuple.name.as_ref())
+            .collect();
+        let paths: Vec<_> =
+            futures_util::stream::iter(names.iter().map(|name| server.cache_info(name)))
+                .buffered(targets.max_concurrent_requests)
+                .try_collect()
+                .await?;
+        manifest.extend(
+            names
+                .into_iter()
+                .zip(paths)
+                .map(|(name, path)| ManifestEntry {
+                    kind: Kind::Index,
+                    name: name.to_owned(),
+                    path,
+                }),
+        );
+    }
+
+    let cached = clean_install::cache_lockfile(
+        config,
+        lockfile,
+        targets.max_concurrent_requests,
+        targets.max_concurrent_installs,
+    )
+    .await?;
+    manifest.extend(cached.gems.into_iter().map(|(name, path)| ManifestEntry {
+        kind: Kind::Gem,
+        name,
+        path,
+    }));
+    manifest.extend(
+        cached
+            .git_repos
+            .into_iter()
+            .map(|(name, path)| ManifestEntry {
+                kind: Kind::Git,
+                name,
+                path,
+            }),
+    );
+    Ok(())
+}
@@ -13,6 +13,7 @@ use rayon::ThreadPoolBuildError;
 use regex::Regex;
 use reqwest::Client;
 use rv_client::http_client::rv_http_client;
+use rv_gem_types::Platform;
 use rv_gem_types::ReleaseTuple;
 use rv_gem_types::Specification as GemSpecification;
 use rv_lockfile::datatypes::ChecksumAlgorithm;
@@ -21,6 +22,8 @@ use rv_lockfile::datatypes::GemfileDotLock;
 use rv_lockfile::datatypes::GitSection;
 use rv_lockfile::datatypes::PathSection;
 use rv_lockfile::datatypes::Spec;
+use rv_ruby::Ruby;
+use rv_ruby::engine::RubyEngine;
 use rv_ruby::request::RubyRequest;
 use sha2::Digest;
 use tracing::debug;
@@ -32,23 +35,32 @@ use url::Url;
 use crate::commands::clean_install::checksums::ArchiveChecksums;
 use crate::commands::clean_install::checksums::HashReader;
 use crate::commands::clean_install::checksums::Hashed;
+use crate::commands::clean_install::compile_failures::{CompileFailure, FailureReason};
+use crate::commands::clean_install::groups::GroupSelection;
 use crate::commands::ruby::install::install as ruby_install;
 use crate::commands::run::Invocation;
+use crate::config::credentials::{redact, redact_error};
 use crate::progress::WorkProgress;
 use crate::{GlobalArgs, config::Config};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io;
 use std::io::Read;
 use std::io::Write;
 use std::ops::Not;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Mutex;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 use std::time::Instant;
 use std::vec;
 
+mod cargo_builder;
 mod checksums;
+mod compile_failures;
+mod extension_cache;
+mod groups;
+mod rbconfig;
 
 #[derive(Debug, clap_derive::Args)]
 pub struct CleanInstallArgs {
@@ -73,6 +85,26 @@ pub struct CleanInstallArgs {
     /// Force installation of gems, whatever is installed or not.
     #[arg(long, default_value = "false")]
     pub force: bool,
+
+    /// Keep compiling other gems' native extensions after one fails,
+    /// then summarize every failure.
+    #[arg(long)]
+    pub keep_going: bool,
+
+    /// Don't install gems from these Gemfile groups, separated by colons or spaces.
+    /// Defaults to Bundler's BUNDLE_WITHOUT setting.
+    #[arg(long, value_name = "GROUPS")]
+    pub without: Option<String>,
+
+    /// Install gems from these optional Gemfile groups too, separated by colons or spaces.
+    /// Defaults to Bundler's BUNDLE_WITH setting.
+    #[arg(long, value_name = "GROUPS", conflicts_with = "only")]
+    pub with: Option<String>,
+
+    /// Only install gems from these Gemfile groups, separated by colons or spaces.
+    /// Defaults to Bundler's BUNDLE_ONLY setting.
+    #[arg(long, value_name = "GROUPS", conflicts_with = "without")]
+    pub only: Option<String>,
 }
 
 #[derive(Debug)]
@@ -83,8 +115,12 @@ struct CiInnerArgs {
     pub install_layout: InstallLayout,
     /// Full path to the Ruby executable, used for Windows .bat binstub wrappers
     pub ruby_executable_path: Utf8PathBuf,
+    /// The platform of the gems that Ruby can use
+    pub gem_platform: Platform,
     /// Will install already installed gems
     pub force: bool,
+    /// Keep compiling native extensions after one fails
+    pub keep_going: bool,
 }
 
 #[derive(Debug)]
@@ -115,7 +151,7 @@ impl InstallLayout {
     pub fn git_gem_path(&self, git_section: &GitSection) -> Utf8PathBuf {
         use std::path::Path;
 
-        let repo_path = Path::new(&git_section.remote);
+        let repo_path = Path::new(git_section.remote.as_ref());
         let repo_name = repo_path
             .file_stem()
             .expect("repo has no filename?")
@@ -180,6 +216,17 @@ pub enum Error {
     },
     #[error("Gem {gem} could not compile extensions")]
     CompileFailures { gem: String },
+    #[error("Some gems could not compile extensions: {}", .gems.join(", "))]
+    #[diagnostic(help("Each gem's build_ext.log and mkmf.log are listed above"))]
+    ManyCompileFailures { gems: Vec<String> },
+    #[error("Could not read Ruby's build configuration: {0}")]
+    RbConfig(String),
+    #[error("Building {extension} from gem {gemname} did not produce a shared library")]
+    MissingCargoDylib { extension: String, gemname: String },
+    #[error("Could not find the library crate in {manifest}: {reason}")]
+    CargoMetadata { manifest: String, reason: String },
+    #[error("Gemfile groups were chosen, but the Gemfile could not be read")]
+    GroupsNeedGemfile(#[source] crate::gemfile::ParseError),
     #[error(transparent)]
     Config(#[from] crate::config::Error),
     #[error(transparent)]
@@ -211,6 +258,10 @@ pub enum Error {
     },
     #[error("Could not download a git dependency: {error}")]
     Git { error: String },
+    #[error("These gems aren't in the cache, so they can't be installed offline: {}", .gems.join(", "))]
+    NotCached { gems: Vec<String> },
+    #[error("Revision {revision} of {remote} isn't in the cache, so it can't be installed offline")]
+    GitNotCached { remote: String, revision: String },
     #[error(
         "The gemfile path must be inside a directory with a parent, but it wasn't. Path was {0}"
     )]
@@ -235,7 +286,7 @@ pub(crate) async fn ci(global_args: &GlobalArgs, args: CleanInstallArgs) -> Resu
     // We need some Ruby installed, because we need to run Ruby code when installing
     // gems. Ensure Ruby is installed here so we can use it later.
     if config.current_ruby().is_none() {
-        ruby_install(global_args, None, None, None, false).await?;
+        ruby_install(global_args, None, None, None, false, false).await?;
     }
 
     // Now that it's installed, we can use Ruby to query various directories
@@ -255,7 +306,9 @@ pub(crate) async fn ci(global_args: &GlobalArgs, args: CleanInstallArgs) -> Resu
             extensions_scope,
         },
         ruby_executable_path: ruby.executable_path(),
+        gem_platform: gem_platform(&ruby),
         force: args.force,
+        keep_going: args.keep_going,
     };
 
     // Terminal progress indicator (OSC 9;4) for supported terminals
@@ -271,7 +324,20 @@ pub(crate) async fn ci(global_args: &GlobalArgs, args: CleanInstallArgs) -> Resu
         // Normalize Windows line endings (CRLF) to Unix (LF) for the parser
         rv_lockfile::normalize_line_endings(&raw_contents).into_owned()
     };
-    let lockfile = rv_lockfile::parse(&lockfile_contents)?;
+    let mut lockfile = rv_lockfile::parse(&lockfile_contents)?;
+
+    let groups = GroupSelection::new(
+        args.without.as_deref(),
+        args.with.as_deref(),
+        args.only.as_deref(),
+        &config.bundler_settings,
+    );
+    // Group membership isn't in the lockfile, only in the Gemfile it was locked from.
+    let gemfile_path = args
+        .gemfile
+        .clone()
+        .unwrap_or_else(|| lockfile_path.with_file_name("Gemfile"));
+    retain_selected_groups(&mut lockfile, &gemfile_path, &groups)?;
 
     drop(span);
 
@@ -290,12 +356,12 @@ pub(crate) async fn install_tool_lockfile(
     lockfile: GemfileDotLock<'_>,
     install_path: Utf8PathBuf,
 ) -> Result<InstallStats> {
-    let config = &Config::new(global_args, request.clone())?;
+    let config = &Config::with_settings(global_args, request.clone())?;
 
     // We need some Ruby installed, because we need to run Ruby code when installing
     // gems. Ensure Ruby is installed here so we can use it later.
     if config.current_ruby().is_none() {
-        ruby_install(global_args, None, request, None, false).await?;
+        ruby_install(global_args, None, request, None, false, false).await?;
     }
 
     let ruby = config
@@ -311,7 +377,9 @@ pub(crate) async fn install_tool_lockfile(
             extensions_scope,
         },
         ruby_executable_path: ruby.executable_path(),
+        gem_platform: gem_platform(&ruby),
         force: true,
+        keep_going: false,
     };
 
     // Terminal progress indicator (OSC 9;4) for supported terminals
@@ -321,6 +389,115 @@ pub(crate) async fn install_tool_lockfile(
     ci_inner_work(config, &inner_args, &progress, lockfile).await
 }
 
+/// What [`cache_lockfile`] put in the cache.
+pub(crate) struct CachedLockfile {
+    /// Each gem's full name, and where it's cached.
+    pub gems: Vec<(String, Utf8PathBuf)>,
+    /// Each git repo's remote and revision, and where it's cached.
+    pub git_repos: Vec<(String, Utf8PathBuf)>,
+}
+
+/// Downloads the gems and git repos `lockfile` needs on this platform into the cache, without
+/// installing them, so that `rv ci --offline` can install them later.
+pub(crate) async fn cache_lockfile(
+    config: &Config,
+    mut lockfile: GemfileDotLock<'_>,
+    max_concurrent_requests: usize,
+    max_concurrent_installs: usize,
+) -> Result<CachedLockfile> {
+    let platform = config
+        .current_ruby()
+        .map_or_else(Platform::local, |ruby| gem_platform(&ruby));
+    retain_gems_to_be_installed(&mut lockfile, &platform);
+
+    let git_repos = download_git_repos(&lockfile.git, config, max_concurrent_installs)?
+        .into_iter()
+        .map(|repo| (format!("{}@{}", repo.remote(), repo.sha()), repo.path))
+        .collect();
+
+    let progress = WorkProgress::new();
+    let stats = DownloadStats::default();
+    download_gems(
+        config,
+        &lockfile,
+        max_concurrent_requests,
+        true,
+        &progress,
+        &stats,
+    )
+    .await?;
+    let mut gems = Vec::new();
+    for gem_source in &lockfile.gem {
+        let Some(remote) = gem_source.remote.as_deref() else {
+            continue;
+        };
+        for spec in &gem_source.specs {
+            gems.push((
+                spec.release_tuple.full_name(),
+                gem_cache_path(config, remote, spec)?,
+            ));
+        }
+    }
+
+    Ok(CachedLockfile { gems, git_repos })
+}
+
+/// Everything in the cache that installing `lockfile` could use, on any platform: gem server
+/// indexes, gems, git repos, gemspecs and compiled extensions. Nothing has to be cached yet.
+pub(crate) fn cached_paths(
+    config: &Config,
+    lockfile: &GemfileDotLock<'_>,
+) -> Result<Vec<Utf8PathBuf>> {
+    let gemspecs_dir = config
+        .cache
+        .shard(rv_cache::CacheBucket::Gemspec, "gemspecs")
+        .into_path_buf();
+    let git_clone_dir = config
+        .cache
+        .shard(rv_cache::CacheBucket::Git, "gits")
+        .into_path_buf();
+    let extensions = |full_name: &str| {
+        config
+            .cache
+            .shard(rv_cache::CacheBucket::Extension, full_name)
+            .into_path_buf()
+    };
+
+    let mut paths = Vec::new();
+    for gem_source in &lockfile.gem {
+        let Some(remote) = gem_source.remote.as_deref() else {
+            continue;
+        };
+        let url = Url::parse(remote).map_err(|err| Error::BadRemote {
+            remote: remote.to_owned(),
+            err,
+        })?;
+        paths.push(crate::gemserver::Gemserver::cache_dir(config, &url));
+        for spec in &gem_source.specs {
+            paths.push(gem_cache_path(config, remote, spec)?);
+            paths.push(extensions(&spec.release_tuple.full_name()));
+        }
+    }
+    for git_source in &lockfile.git {
+        let (remote, revision) = (git_source.remote.as_ref(), git_source.revision.as_ref());
+        paths.push(git_clone_dir.join(rv_cache::cache_digest((remote, revision))));
+        for spec in &git_source.specs {
+            let full_name = spec.release_tuple.full_name();
+            paths.push(gemspecs_dir.join(format!("{revision}-{full_name}.gemspec")));
+            paths.push(extensions(&full_name));
+        }
+    }
+    for path_source in &lockfile.path {
+        let path_key = rv_cache::cache_digest(path_source.remote.as_ref());
+        for spec in &path_source.specs {
+            let full_name = spec.release_tuple.full_name();
+            paths.push(gemspecs_dir.join(format!("{path_key}-{full_name}.gemspec")));
+            paths.push(extensions(&full_name));
+        }
+    }
+    Ok(paths)
+}
+
 async fn ci_inner_work(
     config: &Config,
     args: &CiInnerArgs,
@@ -335,11 +512,11 @@ async fn ci_inner_work(
     // over generic "ruby" platform gems. This ensures we use prebuilt binaries
     // (like libv8-node-24.1.0.0-x86_64-linux.gem) instead of compiling from
     // source (libv8-node-24.1.0.0.gem).
-    retain_gems_to_be_installed(&mut lockfile);
+    retain_gems_to_be_installed(&mut lockfile, &args.gem_platform);
 
     if !args.force {
         let original_count = lockfile.spec_count();
-        discard_installed_gems(&mut lockfile, install_layout);
+        discard_installed_gems(config, &mut lockfile, install_layout);
         let filtered_count = lockfile.spec_count();
 
         let already_installed = original_count.saturating_sub(filtered_count);
@@ -379,7 +556,15 @@ async fn ci_inner_work(
 
     let gem_fetch_start = Instant::now();
     let stats = DownloadStats::default();
-    let downloaded = download_gems(config, &lockfile, args, progress, &stats).await?;
+    let downloaded = download_gems(
+        config,
+        &lockfile,
+        args.max_concurrent_requests,
+        args.validate_checksums,
+        progress,
+        &stats,
+    )
+    .await?;
     let downloaded_count = downloaded.len();
     let gem_fetch_elapsed = gem_fetch_start.elapsed();
 
@@ -437,7 +622,18 @@ async fn ci_inner_work(
     })
 }
 
-fn retain_gems_to_be_installed(lockfile: &mut GemfileDotLock) {
+/// The platform of the gems `ruby` can use. JRuby runs on the JVM, so it uses `java` gems
+/// whatever the host is. TruffleRuby can't load the native extensions precompiled for CRuby,
+/// so like Bundler, it only uses `ruby` gems and compiles their extensions itself.
+fn gem_platform(ruby: &Ruby) -> Platform {
+    match ruby.version.engine {
+        RubyEngine::JRuby => Platform::java(),
+        RubyEngine::TruffleRuby => Platform::ruby(),
+        _ => Platform::local(),
+    }
+}
+
+fn retain_gems_to_be_installed(lockfile: &mut GemfileDotLock, platform: &Platform) {
     lockfile.gem.iter_mut().for_each(|gem_section| {
         use std::collections::HashMap;
 
@@ -445,7 +641,7 @@ fn retain_gems_to_be_installed(lockfile: &mut GemfileDotLock) {
         for spec in &gem_section.specs {
             let release_tuple = &spec.release_tuple;
 
-            if !release_tuple.platform.is_local() {
+            if !release_tuple.platform.matches(platform) {
                 continue;
             }
 
@@ -466,7 +662,11 @@ fn retain_gems_to_be_installed(lockfile: &mut GemfileDotLock) {
     })
 }
 
-fn discard_installed_gems(lockfile: &mut GemfileDotLock, install_layout: &InstallLayout) {
+fn discard_installed_gems(
+    config: &Config,
+    lockfile: &mut GemfileDotLock,
+    install_layout: &InstallLayout,
+) {
     lockfile.gem.iter_mut().for_each(|gem_section| {
         use std::path::Path;
 
@@ -475,11 +675,11 @@ fn discard_installed_gems(lockfile: &mut GemfileDotLock, install_layout: &Instal
             let gem_path = install_layout.gem_path(full_name);
             let spec_path = install_layout.spec_path(full_name);
             let extensions_dir = install_layout.extensions_dir(full_name);
-            let ext_path = cached_compile_path(&extensions_dir);
+            let build_args = build_flags(config, &spec.release_tuple.name);
 
             !Path::new(&gem_path).exists()
                 || !Path::new(&spec_path).exists()
-                || (Path::new(&extensions_dir).exists() && !Path::new(&ext_path).exists())
+                || (Path::new(&extensions_dir).exists() && !is_built(&extensions_dir, &build_args))
         })
     });
 
@@ -536,8 +736,8 @@ fn install_path(
         .into_path_buf();
     fs_err::create_dir_all(&cached_gemspecs_dir)?;
 
-    let path_key = rv_cache::cache_digest(path_section.remote);
-    let path_dir = Utf8PathBuf::from(path_section.remote);
+    let path_key = rv_cache::cache_digest(path_section.remote.as_ref());
+    let path_dir = Utf8PathBuf::from(path_section.remote.as_ref());
 
     let mut path_specs = Vec::new();
     let pattern = path_dir.join("**/*.gemspec").to_string();
@@ -589,7 +789,7 @@ fn install_git_repos<'i>(
     span.pb_set_style(&ProgressStyle::with_template("{spinner:.green} {span_name}").unwrap());
     let _guard = span.enter();
 
-    let repos = download_git_repos(git_sources, &config.cache, args)?;
+    let repos = download_git_repos(git_sources, config, args.max_concurrent_installs)?;
 
     debug!("Installing git gems");
 
@@ -735,37 +935,48 @@ fn install_git_repo(
 /// Note this is not async, it shells out to `git clone` so it will block.
 fn download_git_repos<'i>(
     git_sources: &Vec<GitSection<'i>>,
-    cache: &rv_cache::Cache,
-    args: &CiInnerArgs,
+    config: &Config,
+    max_concurrent_installs: usize,
 ) -> Result<Vec<DownloadedGitRepo<'i>>> {
     debug!("Downloading git gems");
 
     // Download git repos to this dir.
-    let git_clone_dir = cache
+    let git_clone_dir = config
+        .cache
         .shard(rv_cache::CacheBucket::Git, "gits")
         .into_path_buf();
     fs_err::create_dir_all(&git_clone_dir)?;
 
-    let pool = create_rayon_pool(args.max_concurrent_installs).unwrap();
+    let pool = create_rayon_pool(max_concurrent_installs).unwrap();
     use rayon::prelude::*;
     let downloads = pool.install(|| {
         git_sources
             .par_iter()
-            .map(|git_source| download_git_repo(&git_clone_dir, git_source))
+            .map(|git_source| download_git_repo(&git_clone_dir, git_source, config.offline))
             .collect::<Result<Vec<_>>>()
     })?;
     Ok(downloads)
 }
 
 /// Clones git repos from their remote, or looks them up in the cache if they're already downloaded.
+/// When `offline`, the revision has to be in the cache already.
 fn download_git_repo<'i>(
     git_clone_dir: &Utf8Path,
     git_source: &GitSection<'i>,
+    offline: bool,
 ) -> Result<DownloadedGitRepo<'i>> {
+    let not_cached = || Error::GitNotCached {
+        remote: git_source.remote.to_string(),
+        revision: git_source.revision.to_string(),
+    };
     // This will be the subdir within `git_clone_dir` that the git cloned repos are written to.
-    let cache_key = rv_cache::cache_digest((git_source.remote, git_source.revision));
+    let cache_key =
+        rv_cache::cache_digest((git_source.remote.as_ref(), git_source.revision.as_ref()));
     let git_repo_dir = git_clone_dir.join(&cache_key);
 
+    // Other processes can be fetching the same repo, so wait for them to finish.
+    let _lock = rv_cache::CacheShard::from_path(&git_repo_dir).lock()?;
+
     // Check if it's already in the cache.
     if std::fs::exists(&git_repo_dir)? {
         tracing::event!(tracing::Level::DEBUG, %git_repo_dir, %git_source.remote, %git_source.revision, "checking for revision");
@@ -780,6 +991,9 @@ fn download_git_repo<'i>(
             .spawn()?
             .wait()?;
         if !sha_check.success() {
+            if offline {
+                return Err(not_cached());
+            }
             tracing::event!(tracing::Level::DEBUG, %git_repo_dir, %git_source.remote, %git_source.revision, "updating repo");
             let git_fetch = std::process::Command::new("git")
                 .current_dir(&git_repo_dir)
@@ -788,7 +1002,7 @@ fn download_git_repo<'i>(
                     "--quiet",
                     "--force",
                     "--tags",
-                    git_source.remote,
+                    git_source.remote.as_ref(),
                     "refs/heads/*:refs/heads/*",
                 ])
                 .spawn()?
@@ -799,9 +1013,14 @@ fn download_git_repo<'i>(
                 });
             }
         }
+    } else if offline {
+        return Err(not_cached());
     } else {
-        // It wasn't cached, so clone it.
+        // It wasn't cached, so clone it. Clone next to the cache first, so that an interrupted
+        // clone never looks like a cached repo.
         tracing::event!(tracing::Level::DEBUG, %git_clone_dir, %git_source.remote, %git_source.revision, "Cloning repo");
+        let staging = camino_tempfile::tempdir_in(git_clone_dir)?;
+        let staged_repo_dir = staging.path().join(&cache_key);
         let git_cloned = std::process::Command::new("git")
             .current_dir(git_clone_dir)
             .args([
@@ -809,8 +1028,8 @@ fn download_git_repo<'i>(
                 "--quiet",
                 "--bare",
                 "--no-hardlinks",
-                git_source.remote,
-                cache_key.as_ref(),
+                git_source.remote.as_ref(),
+                staged_repo_dir.as_str(),
             ])
             .spawn()?
             .wait()?;
@@ -819,6 +1038,7 @@ fn download_git_repo<'i>(
                 error: format!("git clone had exit code {}", git_cloned),
             });
         }
+        fs_err::rename(&staged_repo_dir, &git_repo_dir)?;
     }
 
     // Success! Save the paths of all the repos we just cloned.
@@ -861,11 +1081,41 @@ fn cache_gemspec_path(
         .expect("Failed to parse the result of RubyGems YAML serialization");
 
     debug!("writing YAML gemspec to {}", &cached_path);
-    fs_err::write(&cached_path, &yaml_contents)?;
+    rv_cache::write_atomic(&cached_path, &yaml_contents)?;
 
     Ok(dep_gemspec)
 }
 
+/// Leaves only the gems needed by the selected Gemfile groups in the lockfile.
+fn retain_selected_groups(
+    lockfile: &mut GemfileDotLock<'_>,
+    gemfile_path: &Utf8Path,
+    groups: &GroupSelection,
+) -> Result<()> {
+    let gemfile = fs_err::read_to_string(gemfile_path)
+        .map_err(Error::from)
+        .and_then(|contents| {
+            crate::gemfile::parse(&rv_lockfile::normalize_line_endings(&contents))
+                .map_err(Error::GroupsNeedGemfile)
+        });
+    let gemfile = match gemfile {
+        Ok(gemfile) => gemfile,
+        // Without chosen groups, the only thing to skip is optional groups, which are rare
+        // enough that Gemfiles rv can't read should still install everything.
+        Err(err) if groups.is_empty() => {
+            debug!("Installing all groups, because the Gemfile could not be read: {err}");
+            return Ok(());
+        }
+        Err(err) => return Err(err),
+    };
+
+    let skipped = groups::retain_groups(lockfile, &gemfile, groups);
+    if skipped > 0 {
+        debug!("Skipping {skipped} gems that the selected groups don't need");
+    }
+    Ok(())
+}
+
 fn find_lockfile_path(gemfile: &Option<Utf8PathBuf>) -> Result<Utf8PathBuf> {
     let Some(gemfile) = gemfile else {
         let lockfile_path = rv_dirs::canonicalize_utf8(Utf8Path::new("Gemfile.lock"))
@@ -1037,38 +1287,96 @@ fn compile_gems(
     span.pb_set_length(deps_count as u64);
     let _guard = span.enter();
 
+    // With --keep-going, gems that fail (or need a gem that failed) are recorded here,
+    // and compiling carries on with the gems that don't depend on them.
+    let failures: Mutex<Vec<CompileFailure>> = Mutex::default();
+    let failed_gems: Mutex<HashSet<String>> = Mutex::default();
+
     let graph = DepGraph::new(deps.as_slice());
     let total_cached_deps = graph
         .into_par_iter()
         .try_fold(
             || 0,
             |mut count, node| {
+                if args.keep_going
+                    && let Some(failed_dep) = failed_dependency(&info, &node, &failed_gems)
+                {
+                    failed_gems.lock().unwrap().insert(node.clone());
+                    if let Some(spec) = info.get_if_has_extension(&node) {
+                        span.pb_inc(1);
+                        progress.complete_one();
+                        failures.lock().unwrap().push(CompileFailure {
+                            gem: spec.full_name(),
+                            reason: FailureReason::FailedDependency(failed_dep),
+                        });
+                    }
+                    return Ok(count);
+                }
                 if let Some(spec) = info.get_if_has_extension(&node) {
                     span.pb_set_message(&spec.name);
-                    let compile_stats = compile_gem(config, args, spec)?;
-                    let compiled_ok = compile_stats.ok;
+                    let compiled = compile_gem(config, args, spec);
                     span.pb_inc(1);
                     progress.complete_one();
-                    if !compiled_ok {
-                        return Err(Error::CompileFailures {
-                            gem: spec.full_name(),
-                        });
-                    }
-                    if compile_stats.is_cached {
-                        count += 1;
-                    }
+                    let reason = match compiled {
+                        Ok(compile_stats) if compile_stats.ok => {
+                            if compile_stats.is_cached {
+                                count += 1;
+                            }
+                            return Ok(count);
+                        }
+                        Ok(_) if !args.keep_going => {
+                            return Err(Error::CompileFailures {
+                                gem: spec.full_name(),
+                            });
+                        }
+                        Err(err) if !args.keep_going => return Err(err),
+                        Ok(compile_stats) => FailureReason::Build {
+                            ext_dest: install_layout.extensions_dir(&spec.full_name()),
+                            log: compile_stats.failure_log.unwrap_or_default(),
+                        },
+                        Err(err) => FailureReason::Error(err.to_string()),
+                    };
+                    failed_gems.lock().unwrap().insert(node.clone());
+                    failures.lock().unwrap().push(CompileFailure {
+                        gem: spec.full_name(),
+                        reason,
+                    });
                 }
                 Ok(count)
             },
         )
         .try_reduce(|| 0, |a, b| Ok(a + b))?;
 
+    let mut failures = failures.into_inner().unwrap();
+    if !failures.is_empty() {
+        drop(_guard);
+        failures.sort_by(|a, b| a.gem.cmp(&b.gem));
+        compile_failures::print_summary(&failures);
+        return Err(Error::ManyCompileFailures {
+            gems: failures.into_iter().map(|failure| failure.gem).collect(),
+        });
+    }
+
     Ok(GemsCompiled {
         total: deps_count,
         cached: total_cached_deps,
     })
 }
 
+/// The first of a gem's dependencies that didn't compile, if any.
+fn failed_dependency(
+    info: &CompileNativeExtInfo<'_>,
+    name: &String,
+    failed_gems: &Mutex<HashSet<String>>,
+) -> Option<String> {
+    let spec = info.nodes.get(name)?;
+    let failed_gems = failed_gems.lock().unwrap();
+    spec.dependencies
+        .iter()
+        .find(|dep| dep.is_runtime() && failed_gems.contains(&dep.name))
+        .map(|dep| dep.name.clone())
+}
+
 /// Build a dependency graph of all the gems which need compiling,
 /// and dependencies (i.e. which gems must be compiled before other gems)
 fn make_dep_graph<'a>(
@@ -1172,7 +1480,8 @@ impl DownloadStats {
 async fn download_gems<'i>(
     config: &Config,
     lockfile: &'i GemfileDotLock<'i>,
-    args: &CiInnerArgs,
+    max_concurrent_requests: usize,
+    validate_checksums: bool,
     progress: &WorkProgress,
     stats: &DownloadStats,
 ) -> Result<Vec<DownloadedRubygems<'i>>> {
@@ -1185,16 +1494,18 @@ async fn download_gems<'i>(
     span.pb_set_message("0 cached, 0 downloaded");
     let _guard = span.enter();
 
+    if config.offline {
+        check_gems_cached(config, lockfile)?;
+    }
+
     let all_sources = futures_util::stream::iter(&lockfile.gem);
-    let checksums = if args.validate_checksums
-        && let Some(checks) = &lockfile.checksums
-    {
+    let checksums = if validate_checksums && let Some(checks) = &lockfile.checksums {
         let mut hm = HashMap::new();
         for checksum in checks {
             hm.insert(
                 checksum.release_tuple.clone(),
                 HowToChecksum {
-                    algorithm: match checksum.algorithm {
+                    algorithm: match &checksum.algorithm {
                         ChecksumAlgorithm::None => continue,
                         ChecksumAlgorithm::Unknown(other) => {
                             eprintln!("Unknown checksum algorithm {}", other.yellow());
@@ -1215,11 +1526,19 @@ async fn download_gems<'i>(
             let checksums = &checksums;
             let span = &span;
             async move {
-                download_gem_source(config, gem_source, checksums, args, progress, stats, span)
-                    .await
+                download_gem_source(
+                    config,
+                    gem_source,
+                    checksums,
+                    max_concurrent_requests,
+                    progress,
+                    stats,
+                    span,
+                )
+                .await
             }
         })
-        .buffered(args.max_concurrent_requests)
+        .buffered(max_concurrent_requests)
         .try_collect::<Vec<_>>()
         .await?
         .into_iter()
@@ -1463,15 +1782,66 @@ impl CompileNativeExtResult {
 
 static EXTCONF_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)extconf").unwrap());
 static RAKE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)rakefile|mkrf_conf").unwrap());
+static CARGO_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"Cargo\.toml").unwrap());
+static CMAKE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)CMakeLists\.txt").unwrap());
 
 struct CompileStats {
     ok: bool,
     is_cached: bool,
+    /// If compiling failed, the log that best explains why.
+    failure_log: Option<Utf8PathBuf>,
+}
+
+/// Write a file to signal there's no need to compile the gem again, unless its build flags change
+fn mark_as_built(ext_dest: &Utf8Path, build_args: &[String]) -> Result<()> {
+    Ok(fs_err::write(
+        cached_compile_path(ext_dest),
+        build_args.join("\n"),
+    )?)
 }
 
-/// Write a file to signal there's no need to compile the gem again
-fn mark_as_built(ext_dest: &Utf8Path) -> Result<()> {
-    Ok(fs_err::write(cached_compile_path(ext_dest), "")?)
+/// Was the gem previously compiled, with the same build flags?
+fn is_built(ext_dest: &Utf8Path, build_args: &[String]) -> bool {
+    fs_err::read_to_string(cached_compile_path(ext_dest))
+        .is_ok_and(|built_with| built_with == build_args.join("\n"))
+}
+
+/// Flags to compile a gem's native extensions with, from rv's settings or else Bundler's.
+fn build_flags(config: &Config, gem_name: &str) -> Vec<String> {
+    let flags = match config.rv_settings.build.get(gem_name) {
+        Some(flags) => Some(flags.clone()),
+        None => config.bundler_settings.build_flags(gem_name),
+    };
+    flags
+        .map(|flags| split_build_flags(&flags))
+        .unwrap_or_default()
+}
+
+/// Splits build flags into arguments like a shell would, so quoted values can contain spaces.
+fn split_build_flags(flags: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut quote = None;
+    let mut chars = flags.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"') | None, '\\') => {
+                if let Some(escaped) = chars.next() {
+                    arg.get_or_insert_default().push(escaped);
+                }
+            }
+            (Some(_), c) => arg.get_or_insert_default().push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                arg.get_or_insert_default();
+            }
+            (None, c) if c.is_whitespace() => args.extend(arg.take()),
+            (None, c) => arg.get_or_insert_default().push(c),
+        }
+    }
+    args.extend(arg);
+    args
 }
 
 /// Path to mark whether a gem has previously been compiled.
@@ -1492,15 +1862,31 @@ fn compile_gem(
     let gem_path = install_layout.gem_path(&full_name);
     let lib_dest = gem_path.join("lib");
     let ext_dest = install_layout.extensions_dir(&full_name);
+    let build_args = build_flags(config, &spec.name);
     let mut ran_rake = false;
 
-    let build_complete_path = cached_compile_path(&ext_dest);
-    debug!("Checking for {}", build_complete_path);
-    if std::fs::exists(&build_complete_path)? {
+    debug!("Checking for {}", cached_compile_path(&ext_dest));
+    if is_built(&ext_dest, &build_args) {
         debug!("native extensions for {} already built", full_name);
         return Ok(CompileStats {
             ok: true,
             is_cached: true,
+            failure_log: None,
+        });
+    }
+
+    let cache_dir = extension_cache::cache_dir(
+        config,
+        &full_name,
+        &install_layout.extensions_scope,
+        &build_args,
+    );
+    if extension_cache::restore(&cache_dir, &ext_dest, &lib_dest)? {
+        mark_as_built(&ext_dest, &build_args)?;
+        return Ok(CompileStats {
+            ok: true,
+            is_cached: true,
+            failure_log: None,
         });
     }
     debug!("compiling native extensions for {}", full_name);
@@ -1508,8 +1894,15 @@ fn compile_gem(
     for extstr in spec.extensions.clone() {
         let extension = extstr.as_ref();
         if EXTCONF_REGEX.is_match(extension) {
-            let outputs =
-                build_extconf(config, extension, gem_home, &gem_path, &ext_dest, &lib_dest)?;
+            let outputs = build_extconf(
+                config,
+                extension,
+                gem_home,
+                &gem_path,
+                &ext_dest,
+                &lib_dest,
+                &build_args,
+            )?;
 
             compile_results.push(CompileNativeExtResult {
                 extension: extension.to_string(),
@@ -1517,8 +1910,15 @@ fn compile_gem(
             });
         } else if RAKE_REGEX.is_match(extension) {
             if !ran_rake {
-                let outputs =
-                    build_rakefile(config, extension, gem_home, &gem_path, &ext_dest, &lib_dest)?;
+                let outputs = build_rakefile(
+                    config,
+                    extension,
+                    gem_home,
+                    &gem_path,
+                    &ext_dest,
+                    &lib_dest,
+                    &build_args,
+                )?;
 
                 compile_results.push(CompileNativeExtResult {
                     extension: extension.to_string(),
@@ -1527,6 +1927,36 @@ fn compile_gem(
             }
             // Ensure that we only run the Rake builder once, even if we have both a `Rakefile` and `mkrf_conf` file
             ran_rake = true;
+        } else if CMAKE_REGEX.is_match(extension) {
+            let outputs = build_cmake(
+                config,
+                extension,
+                gem_home,
+                &gem_path,
+                &ext_dest,
+                &lib_dest,
+                &build_args,
+            )?;
+
+            compile_results.push(CompileNativeExtResult {
+                extension: extension.to_string(),
+                outputs,
+            });
+        } else if CARGO_REGEX.is_match(extension) {
+            let outputs = cargo_builder::build_cargo(
+                config,
+                extension,
+                gem_home,
+                &gem_path,
+                &ext_dest,
+                &lib_dest,
+                &build_args,
+            )?;
+
+            compile_results.push(CompileNativeExtResult {
+                extension: extension.to_string(),
+                outputs,
+            });
         } else {
             return Err(Error::UnknownExtension {
                 filename: extension.to_string(),
@@ -1543,7 +1973,8 @@ fn compile_gem(
             log.write_all(b"\n\n")?;
         }
 
-        if res.success() {
+        // With --keep-going, failures are summarized at the end instead.
+        if res.success() || args.keep_going {
             continue;
         }
 
@@ -1568,13 +1999,29 @@ fn compile_gem(
 
     let all_ok = compile_results.iter().all(|res| res.success());
 
+    // When extconf.rb fails, it's usually because a library is missing, which mkmf.log explains.
+    let mkmf_log = ext_dest.join("mkmf.log");
+    let extconf_failed = compile_results.iter().any(|res| {
+        EXTCONF_REGEX.is_match(&res.extension)
+            && res.outputs.first().is_some_and(|out| !out.status.success())
+    });
+    let failure_log = match (all_ok, extconf_failed && mkmf_log.exists()) {
+        (true, _) => None,
+        (false, true) => Some(mkmf_log),
+        (false, false) => Some(ext_dest.join("build_ext.log")),
+    };
+
     if all_ok {
-        mark_as_built(&ext_dest)?;
+        mark_as_built(&ext_dest, &build_args)?;
+        if let Err(err) = extension_cache::store(&cache_dir, &ext_dest) {
+            debug!("Could not cache compiled extensions for {full_name}: {err}");
+        }
     }
 
     Ok(CompileStats {
         ok: all_ok,
         is_cached: false,
+        failure_log,
     })
 }
 
@@ -1585,6 +2032,7 @@ fn build_rakefile(
     gem_path: &Utf8PathBuf,
     ext_dest: &Utf8PathBuf,
     lib_dest: &Utf8PathBuf,
+    build_args: &[String],
 ) -> Result<Vec<std::process::Output>> {
     let ext_path = Utf8PathBuf::from_str(extension)?;
     let ext_dir = gem_path.join(ext_path.parent().expect("extconf has no parent"));
@@ -1597,7 +2045,7 @@ fn build_rakefile(
         output = crate::commands::run::capture_run_no_install(
             Invocation::ruby(vec![]),
             config,
-            vec![ext_file.to_string()],
+            [vec![ext_file.to_string()], build_args.to_vec()].concat(),
             Some(&ext_dir),
         )?;
         outputs.push(output);
@@ -1607,7 +2055,7 @@ fn build_rakefile(
     let tmp_dir = camino_tempfile::tempdir_in(gem_path)?;
     let sitearchdir = format!("RUBYARCHDIR={}", tmp_dir.path());
     let sitelibdir = format!("RUBYLIBDIR={}", tmp_dir.path());
-    let args = vec![sitearchdir, sitelibdir];
+    let args = [vec![sitearchdir, sitelibdir], build_args.to_vec()].concat();
 
     let rake = Invocation::tool("rake", vec![("GEM_HOME", gem_home.to_string())]);
 
@@ -1628,6 +2076,7 @@ fn build_extconf(
     gem_path: &Utf8PathBuf,
     ext_dest: &Utf8PathBuf,
     lib_dest: &Utf8PathBuf,
+    build_args: &[String],
 ) -> Result<Vec<std::process::Output>> {
     let ext_path = Utf8PathBuf::from_str(extension)?;
     let ext_dir = gem_path.join(ext_path.parent().expect("extconf has no parent"));
@@ -1639,7 +2088,7 @@ fn build_extconf(
     output = crate::commands::run::capture_run_no_install(
         Invocation::ruby(vec![("GEM_HOME", gem_home.to_string())]),
         config,
-        vec![ext_file.to_string()],
+        [vec![ext_file.to_string()], build_args.to_vec()].concat(),
         Some(&ext_dir),
     )?;
     outputs.push(output);
@@ -1713,6 +2162,92 @@ fn build_extconf(
     Ok(outputs)
 }
 
+fn build_cmake(
+    config: &Config,
+    extension: &str,
+    gem_home: &Utf8PathBuf,
+    gem_path: &Utf8PathBuf,
+    ext_dest: &Utf8PathBuf,
+    lib_dest: &Utf8PathBuf,
+    build_args: &[String],
+) -> Result<Vec<std::process::Output>> {
+    let ext_path = Utf8PathBuf::from_str(extension)?;
+    let ext_dir = gem_path.join(ext_path.parent().expect("CMakeLists.txt has no parent"));
+    let mut output;
+    let mut outputs = vec![];
+
+    // 1. Configure a build dir, with the built libraries going straight into the temp dir
+    let tmp_dir = camino_tempfile::tempdir_in(gem_path)?;
+    let build_dir = camino_tempfile::tempdir_in(gem_path)?;
+    let cmake_env = vec![("GEM_HOME", gem_home.to_string())];
+    let mut configure_args = vec![
+        "-S".to_string(),
+        ext_dir.to_string(),
+        "-B".to_string(),
+        build_dir.path().to_string(),
+        "-DCMAKE_BUILD_TYPE=Release".to_string(),
+        // Windows puts DLLs in the runtime output dir, everything else in the library one.
+        format!("-DCMAKE_RUNTIME_OUTPUT_DIRECTORY={}", tmp_dir.path()),
+        format!("-DCMAKE_LIBRARY_OUTPUT_DIRECTORY={}", tmp_dir.path()),
+    ];
+    configure_args.extend_from_slice(build_args);
+    output = crate::commands::run::capture_run_no_install(
+        Invocation::tool("cmake", cmake_env.clone()),
+        config,
+        configure_args,
+        Some(&ext_dir),
+    )?;
+    let success = output.status.success();
+    outputs.push(output);
+    if !success {
+        return Ok(outputs);
+    }
+
+    // 2. Build
+    output = crate::commands::run::capture_run_no_install(
+        Invocation::tool("cmake", cmake_env),
+        config,
+        vec![
+            "--build".to_string(),
+            build_dir.path().to_string(),
+            "--config".to_string(),
+            "Release".to_string(),
+        ],
+        Some(&ext_dir),
+    )?;
+    let success = output.status.success();
+    outputs.push(output);
+    if !success {
+        return Ok(outputs);
+    }
+
+    // 3. Copy the built libraries to ext and lib dirs
+    copy_cmake_libraries(tmp_dir.path(), &[lib_dest, ext_dest])?;
+
+    Ok(outputs)
+}
+
+/// Copies the shared libraries a CMake build put in `output_dir` to each of `dests`. Like
+/// RubyGems' `CmakeBuilder`, nothing is installed, and executables from the runtime output dir
+/// are left behind.
+fn copy_cmake_libraries(output_dir: &Utf8Path, dests: &[&Utf8PathBuf]) -> Result<()> {
+    for entry in output_dir.read_dir_utf8()? {
+        let entry = entry?;
+        let is_library = entry
+            .path()
+            .extension()
+            .is_some_and(|ext| ["so", "bundle", "dll", "dylib"].contains(&ext));
+        if !is_library || !entry.file_type()?.is_file() {
+            continue;
+        }
+        for dest in dests {
+            fs_err::create_dir_all(dest)?;
+            fs_err::copy(entry.path(), dest.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
 /// Result of unpacking a gem's `data.tar.gz` archive.
 struct UnpackedData {
     hashed: Hashed,
@@ -1790,22 +2325,32 @@ async fn download_gem_source<'i>(
     config: &Config,
     gem_source: &'i GemSection<'i>,
     checksums: &HashMap<ReleaseTuple, HowToChecksum>,
-    args: &CiInnerArgs,
+    max_concurrent_requests: usize,
     progress: &WorkProgress,
     stats: &DownloadStats,
     span: &tracing::Span,
 ) -> Result<Vec<DownloadedRubygems<'i>>> {
     let client = rv_http_client("ci")?;
-    let Some(remote) = gem_source.remote else {
+    let Some(remote) = gem_source.remote.as_deref() else {
         debug!("Skipping download of gems attached to the global source, because it has no remote");
         return Ok(vec![]);
     };
+    let remote_url = Url::parse(remote).map_err(|err| Error::BadRemote {
+        remote: remote.to_owned(),
+        err,
+    })?;
+    let mirror = config.resolve_mirror(&remote_url).await;
+    let remote = GemRemote {
+        source: remote,
+        mirror: config.with_credentials(&mirror),
+    };
 
     // Download them all, concurrently.
     let spec_stream = futures_util::stream::iter(&gem_source.specs);
     let downloaded_gems: Vec<_> = spec_stream
         .map(|spec| {
             let client = &client;
+            let remote = &remote;
             async move {
                 let result =
                     download_gem(config, remote, spec, client, checksums, stats, span).await;
@@ -1814,53 +2359,52 @@ async fn download_gem_source<'i>(
                 result
             }
         })
-        .buffered(args.max_concurrent_requests)
+        .buffered(max_concurrent_requests)
         .try_collect()
         .await?;
-    debug!("Finished downloading gems from source {}", remote);
+    debug!(
+        "Finished downloading gems from source {}",
+        redact(&remote_url)
+    );
     Ok(downloaded_gems)
 }
 
-/// Download a single gem, from the given URL, using the given client.
+/// A gem source, and where its gems are actually downloaded from.
+struct GemRemote<'a> {
+    source: &'a str,
+    /// The source's mirror, or the source itself if it isn't mirrored, with its credentials.
+    mirror: Url,
+}
+
+/// Download a single gem, from the given remote, using the given client.
 async fn download_gem<'i>(
     config: &Config,
-    remote: &str,
+    remote: &GemRemote<'_>,
     spec: &'i Spec,
     client: &Client,
     checksums: &HashMap<ReleaseTuple, HowToChecksum>,
     stats: &DownloadStats,
     span: &tracing::Span,
 ) -> Result<DownloadedRubygems<'i>> {
-    let mut url = url_for_spec(remote, spec)?;
-    let cache_key = rv_cache::cache_digest(url.as_ref());
-    let cache_path = config
-        .cache
-        .shard(rv_cache::CacheBucket::Gem, "gems")
-        .into_path_buf()
-        .join(format!("{cache_key}.gem"));
+    let url = url_for_spec(remote.mirror.as_str(), spec)?;
+    let cache_path = gem_cache_path(config, remote.source, spec)?;
 
     let contents = if cache_path.exists() {
-        debug!("Reusing gem from {url} in cache");
+        debug!("Reusing gem from {} in cache", redact(&url));
         stats.cached_one();
         let data = tokio::fs::read(&cache_path).await?;
+        record_use(&cache_path);
         Bytes::from(data)
     } else {
-        debug!("Downloading gem from {url}");
+        debug!("Downloading gem from {}", redact(&url));
         stats.downloaded_one();
 
-        if let Some(host) = url.host_str()
-            && let Some(token) = config.bundler_settings.token_for(host)
-        {
-            let _ = url.set_username(&token);
-        }
-
-        client
-            .get(url.clone())
-            .send()
-            .await?
-            .error_for_status()?
-            .bytes()
-            .await?
+        config
+            .rv_settings
+            .retry_policy()
+            .bytes(|| client.get(url.clone()))
+            .await
+            .map_err(redact_error)?
     };
 
     // Update the progress bar message with current stats
@@ -1877,7 +2421,7 @@ async fn download_gem<'i>(
                 let actual = sha2::Sha256::digest(&contents);
                 if actual[..] != checksum.value {
                     return Err(Error::LockfileChecksumFail {
-                        filename: url.to_string(),
+                        filename: redact(&url).to_string(),
                         gem_name: full_name,
                         algo: "sha256",
                     });
@@ -1888,16 +2432,56 @@ async fn download_gem<'i>(
     debug!("Validated {}", full_name);
 
     if !cache_path.exists() {
-        if let Some(parent) = cache_path.parent() {
-            tokio::fs::create_dir_all(parent).await?;
-        }
-        tokio::fs::write(&cache_path, &contents).await?;
+        // Other processes can be reading the cache, so never let them see a partly written gem.
+        let contents = contents.clone();
+        tokio::task::spawn_blocking(move || rv_cache::write_atomic(cache_path, contents))
+            .await
+            .map_err(std::io::Error::other)??;
         debug!("Cached {}", full_name);
     }
 
     Ok(DownloadedRubygems { contents, spec })
 }
 
+/// Where the gem for `spec` from `source` is cached. Gems are cached by their original URL, so
+/// switching mirrors doesn't download them again.
+/// Record that a cache entry was reused, so pruning the cache evicts it last.
+fn record_use(path: &Utf8Path) {
+    if let Err(err) = rv_cache::touch(path) {
+        debug!("Couldn't record the use of {path}: {err}");
+    }
+}
+
+fn gem_cache_path(config: &Config, source: &str, spec: &Spec) -> Result<Utf8PathBuf> {
+    let cache_key = rv_cache::cache_digest(url_for_spec(source, spec)?.as_ref());
+    Ok(config
+        .cache
+        .shard(rv_cache::CacheBucket::Gem, "gems")
+        .into_path_buf()
+        .join(format!("{cache_key}.gem")))
+}
+
+/// Offline, every gem has to be in the cache already. Lists all the ones that aren't at once,
+/// rather than failing on the first.
+fn check_gems_cached(config: &Config, lockfile: &GemfileDotLock<'_>) -> Result<()> {
+    let mut gems = Vec::new();
+    for gem_source in &lockfile.gem {
+        let Some(remote) = gem_source.remote.as_deref() else {
+            continue;
+        };
+        for spec in &gem_source.specs {
+            if !gem_cache_path(config, remote, spec)?.exists() {
+                gems.push(spec.release_tuple.full_name());
+            }
+        }
+    }
+    if gems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::NotCached { gems })
+    }
+}
+
 /// Format a duration in a human-readable way (e.g., "16s" or "1m16s").
 fn format_duration(duration: Duration) -> String {
     let secs = duration.as_secs();
@@ -1913,7 +2497,6 @@ fn format_duration(duration: Duration) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rv_gem_types::Platform;
 
     #[test]
     fn test_dep_graph() {
@@ -2157,6 +2740,7 @@ SHA512:
 
         let input = include_str!("../../../rv-lockfile/tests/inputs/Gemfile.twosources.lock");
 
+        let config = Config::new_dummy();
         let mut lockfile = rv_lockfile::parse(input).unwrap();
         let install_layout = InstallLayout {
             install_path: install_path.clone(),
@@ -2167,7 +2751,7 @@ SHA512:
         let installed_gem_dir = install_path.join("gems").join("rake-13.3.0");
         fs_err::create_dir_all(&installed_gem_dir).unwrap();
 
-        discard_installed_gems(&mut lockfile, &install_layout);
+        discard_installed_gems(&config, &mut lockfile, &install_layout);
 
         assert_eq!(lockfile.gem_spec_count(), 2);
         assert_eq!(lockfile.gem[0].specs[0].release_tuple.name, "rake");
@@ -2181,12 +2765,113 @@ SHA512:
         let installed_specification = specifications_dir.join("rake-13.3.0.gemspec");
         fs_err::write(&installed_specification, "").unwrap();
 
-        discard_installed_gems(&mut lockfile, &install_layout);
+        discard_installed_gems(&config, &mut lockfile, &install_layout);
 
         assert_eq!(lockfile.gem_spec_count(), 1);
         assert_eq!(lockfile.gem[0].specs[0].release_tuple.name, "rack");
     }
 
+    #[test]
+    fn test_rebuild_when_build_flags_change() {
+        let temp_dir = camino_tempfile::tempdir().unwrap();
+        let ext_dest = temp_dir.path();
+        let flags = vec!["--with-pg-config=/usr/local/bin/pg_config".to_owned()];
+
+        assert!(!is_built(ext_dest, &[]));
+        mark_as_built(ext_dest, &[]).unwrap();
+        assert!(is_built(ext_dest, &[]));
+        assert!(!is_built(ext_dest, &flags));
+
+        mark_as_built(ext_dest, &flags).unwrap();
+        assert!(is_built(ext_dest, &flags));
+        assert!(!is_built(ext_dest, &[]));
+    }
+
+    #[test]
+    fn test_split_build_flags() {
+        assert_eq!(
+            split_build_flags("--use-system-libraries  --with-xml2-include=/opt/include"),
+            vec!["--use-system-libraries", "--with-xml2-include=/opt/include"]
+        );
+        assert_eq!(
+            split_build_flags(r#"--with-cflags="-O2 -g" '--with-opt-dir=/my dir' a\ b """#),
+            vec!["--with-cflags=-O2 -g", "--with-opt-dir=/my dir", "a b", ""]
+        );
+        assert_eq!(split_build_flags(""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_prefer_java_gems_for_jruby() {
+        let input = indoc::indoc! {"
+            GEM
+              remote: https://rubygems.org/
+              specs:
+                nokogiri (1.18.8)
+                nokogiri (1.18.8-arm64-darwin)
+                nokogiri (1.18.8-java)
+                nokogiri (1.18.8-x86_64-linux)
+
+            PLATFORMS
+              arm64-darwin
+              java
+              ruby
+              x86_64-linux
+
+            DEPENDENCIES
+              nokogiri
+
+            BUNDLED WITH
+               2.6.9
+        "};
+        let mut lockfile = rv_lockfile::parse(input).unwrap();
+
+        retain_gems_to_be_installed(&mut lockfile, &Platform::java());
+
+        let versions: Vec<_> = lockfile.gem[0]
+            .specs
+            .iter()
+            .map(|spec| spec.release_tuple.full_version())
+            .collect();
+        assert_eq!(versions, ["1.18.8-java"]);
+    }
+
+    #[test]
+    fn test_only_ruby_gems_for_truffleruby() {
+        let input = indoc::indoc! {"
+            GEM
+              remote: https://rubygems.org/
+              specs:
+                nokogiri (1.18.8)
+                  racc (~> 1.4)
+                nokogiri (1.18.8-arm64-darwin)
+                  racc (~> 1.4)
+                nokogiri (1.18.8-x86_64-linux-gnu)
+                  racc (~> 1.4)
+                racc (1.8.1)
+
+            PLATFORMS
+              arm64-darwin
+              ruby
+              x86_64-linux-gnu
+
+            DEPENDENCIES
+              nokogiri
+
+            BUNDLED WITH
+               2.6.9
+        "};
+        let mut lockfile = rv_lockfile::parse(input).unwrap();
+
+        retain_gems_to_be_installed(&mut lockfile, &Platform::ruby());
+
+        let versions: Vec<_> = lockfile.gem[0]
+            .specs
+            .iter()
+            .map(|spec| spec.release_tuple.full_name())
+            .collect();
+        assert_eq!(versions, ["nokogiri-1.18.8", "racc-1.8.1"]);
+    }
+
     #[test]
     fn test_prefer_platform_specific_gems() {
         // Use the real Discourse lockfile fixture which has libv8-node with
@@ -2213,7 +2898,7 @@ SHA512:
             libv8_before.len()
         );
 
-        retain_gems_to_be_installed(&mut lockfile);
+        retain_gems_to_be_installed(&mut lockfile, &Platform::local());
 
         // Get all specs filtered by platform specific
         let filtered_specs: Vec<_> = lockfile
@@ -2257,4 +2942,38 @@ SHA512:
             "should select platform-specific version for current platform"
         );
     }
+
+    #[test]
+    fn test_copy_cmake_libraries() {
+        // What a CMake build leaves in its library and runtime output dirs.
+        let output_dir = camino_tempfile::tempdir().unwrap();
+        for file in [
+            "hello.so",
+            "hello.bundle",
+            "hello.dll",
+            "hello-cli",
+            "hello.pdb",
+        ] {
+            fs_err::write(output_dir.path().join(file), "").unwrap();
+        }
+        fs_err::create_dir_all(output_dir.path().join("include")).unwrap();
+        fs_err::write(output_dir.path().join("include/hello.h"), "").unwrap();
+        // A directory named like a library isn't one.
+        fs_err::create_dir(output_dir.path().join("plugins.so")).unwrap();
+
+        let dest = camino_tempfile::tempdir().unwrap();
+        let lib_dest = dest.path().join("lib");
+        let ext_dest = dest.path().join("ext");
+        copy_cmake_libraries(output_dir.path(), &[&lib_dest, &ext_dest]).unwrap();
+
+        for dir in [lib_dest, ext_dest] {
+            let mut copied: Vec<_> = dir
+                .read_dir_utf8()
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_owned())
+                .collect();
+            copied.sort();
+            assert_eq!(copied, ["hello.bundle", "hello.dll", "hello.so"]);
+        }
+    }
 }
@@ -0,0 +1,401 @@
+//! Building native extensions written in Rust, declared in a gemspec as a `Cargo.toml`.
+//! This mirrors RubyGems' `Gem::Ext::CargoBuilder`, so gems built with rb-sys work the same
+//! whether they're installed by RubyGems or rv.
+
+use std::str::FromStr;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Deserialize;
+use tracing::debug;
+
+use super::rbconfig::RbConfig;
+use super::{Error, Result};
+use crate::commands::run::Invocation;
+use crate::config::Config;
+
+pub(super) fn build_cargo(
+    config: &Config,
+    extension: &str,
+    gem_home: &Utf8PathBuf,
+    gem_path: &Utf8PathBuf,
+    ext_dest: &Utf8PathBuf,
+    lib_dest: &Utf8PathBuf,
+    build_args: &[String],
+) -> Result<Vec<std::process::Output>> {
+    let manifest = gem_path.join(Utf8PathBuf::from_str(extension)?);
+    let ext_dir = manifest.parent().expect("Cargo.toml has no parent");
+    let rbconfig = RbConfig::query(config, gem_home)?;
+
+    // 1. Compile the crate as a cdylib, linked against the current Ruby
+    let target_dir = camino_tempfile::tempdir_in(gem_path)?;
+    let profile = std::env::var("RB_SYS_CARGO_PROFILE").unwrap_or_else(|_| "release".to_owned());
+    let target = std::env::var("CARGO_BUILD_TARGET").ok();
+
+    let mut args = vec![
+        "rustc".to_owned(),
+        "--crate-type".to_owned(),
+        "cdylib".to_owned(),
+    ];
+    if let Some(target) = &target {
+        args.extend(["--target".to_owned(), target.clone()]);
+    }
+    args.extend([
+        "--target-dir".to_owned(),
+        target_dir.path().to_string(),
+        "--manifest-path".to_owned(),
+        manifest.to_string(),
+        "--lib".to_owned(),
+        "--profile".to_owned(),
+        profile.clone(),
+    ]);
+    args.extend_from_slice(build_args);
+    args.push("--".to_owned());
+    args.extend(rustc_args(&rbconfig));
+
+    // rb-sys looks for these to know it's being built as a gem, rather than by Cargo alone.
+    let rustflags = match std::env::var("RUSTFLAGS") {
+        Ok(flags) if !flags.is_empty() => format!("{flags} --cfg=rb_sys_gem --cfg=rubygems"),
+        _ => "--cfg=rb_sys_gem --cfg=rubygems".to_owned(),
+    };
+    let cargo = Invocation::tool("cargo", vec![("GEM_HOME", gem_home.to_string())])
+        .with_env(rbconfig.env())
+        .with_env([("RUSTFLAGS".to_owned(), rustflags)]);
+
+    let output = crate::commands::run::capture_run_no_install(cargo, config, args, Some(ext_dir))?;
+    let success = output.status.success();
+    let outputs = vec![output];
+    if !success {
+        return Ok(outputs);
+    }
+
+    // 2. Find the library Cargo built, by the name of the crate's lib target
+    let crate_name = lib_name(config, gem_home, &manifest)?;
+    let profile_dir = if profile == "dev" { "debug" } else { &profile };
+    let mut out_dir = target_dir.path().to_path_buf();
+    if let Some(target) = &target {
+        out_dir.push(target);
+    }
+    out_dir.push(profile_dir);
+    let Some(dylib) = find_cdylib(&out_dir, &crate_name) else {
+        return Err(Error::MissingCargoDylib {
+            extension: extension.to_owned(),
+            gemname: gem_path.file_name().unwrap_or_default().to_owned(),
+        });
+    };
+    debug!("Cargo built {dylib}");
+
+    // 3. Copy it to the ext and lib dirs, named the way Ruby's `require` expects
+    let nesting = nesting(extension);
+    let dest_name = format!("{crate_name}.{}", rbconfig.get("DLEXT"));
+    for dest in [ext_dest, lib_dest] {
+        let dest_dir = dest.join(&nesting);
+        fs_err::create_dir_all(&dest_dir)?;
+        fs_err::copy(&dylib, dest_dir.join(&dest_name))?;
+    }
+
+    Ok(outputs)
+}
+
+/// Arguments for `rustc` so the library links against the current Ruby.
+fn rustc_args(rbconfig: &RbConfig) -> Vec<String> {
+    let mut args = Vec::new();
+
+    // Link with the same compiler Ruby was built with.
+    let mut cc = rbconfig.get("CC").split_whitespace();
+    if let Some(linker) = cc.next() {
+        args.extend(["-C".to_owned(), format!("linker={linker}")]);
+        for arg in cc {
+            args.extend(["-C".to_owned(), format!("link-arg={arg}")]);
+        }
+    }
+
+    let libdir = rbconfig.get("libdir");
+    if !libdir.is_empty() {
+        args.extend(["-L".to_owned(), format!("native={libdir}")]);
+    }
+    args.extend(link_flags(rbconfig.get("DLDFLAGS")));
+    args.extend(link_flags(rbconfig.get("LIBS")));
+
+    if rbconfig.is_darwin() {
+        // Ruby's symbols are found when the extension is loaded, not when it's linked.
+        args.extend([
+            "-C".to_owned(),
+            "link-arg=-Wl,-undefined,dynamic_lookup".to_owned(),
+        ]);
+    }
+    if rbconfig.is_mingw() {
+        args.extend(link_flags(rbconfig.get("LIBRUBYARG_SHARED")));
+        for arg in [
+            "-Wl,--dynamicbase",
+            "-Wl,--disable-auto-image-base",
+            "-static-libgcc",
+        ] {
+            args.extend(["-C".to_owned(), format!("link-arg={arg}")]);
+        }
+    }
+    args
+}
+
+/// Converts flags meant for a C linker into the equivalent `rustc` flags.
+fn link_flags(flags: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut tokens = flags.split_whitespace();
+    while let Some(token) = tokens.next() {
+        // Unexpanded make variables, and flags rustc's linker may not understand.
+        if token.contains("$(") || token.contains("compress-debug-sections") {
+            continue;
+        }
+        let (flag, value) = match token {
+            "-L" | "-l" | "-F" => match tokens.next() {
+                Some(value) => (token, value),
+                None => continue,
+            },
+            _ if token.len() > 2
+                && token
+                    .get(..2)
+                    .is_some_and(|prefix| ["-L", "-l", "-F"].contains(&prefix)) =>
+            {
+                token.split_at(2)
+            }
+            _ => {
+                args.extend(["-C".to_owned(), format!("link-arg={token}")]);
+                continue;
+            }
+        };
+        let converted = match flag {
+            "-L" => ("-L", format!("native={value}")),
+            "-F" => ("-l", format!("framework={value}")),
+            _ => match value.strip_prefix(':') {
+                Some(file) => ("-l", static_or_dylib(file)),
+                None => ("-l", value.to_owned()),
+            },
+        };
+        args.extend([converted.0.to_owned(), converted.1]);
+    }
+    args
+}
+
+/// `-l:libfoo.a` names a library file exactly, which rustc spells differently.
+fn static_or_dylib(file: &str) -> String {
+    let name = file.strip_prefix("lib").unwrap_or(file);
+    if let Some(name) = name.strip_suffix(".a") {
+        return format!("static={name}");
+    }
+    let name = [".so", ".dylib", ".dll"]
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .unwrap_or(name);
+    format!("dylib={name}")
+}
+
+/// The parts of `cargo metadata` needed to find a package's lib target.
+#[derive(Deserialize)]
+struct CargoMetadata {
+    packages: Vec<CargoPackage>,
+}
+
+#[derive(Deserialize)]
+struct CargoPackage {
+    manifest_path: Utf8PathBuf,
+    targets: Vec<CargoTarget>,
+}
+
+#[derive(Deserialize)]
+struct CargoTarget {
+    name: String,
+    kind: Vec<String>,
+}
+
+/// Asks Cargo for the name of the lib target of the crate at `manifest`, with `-` replaced by
+/// `_` like in the file names Cargo gives libraries. Like RubyGems, this is what the extension
+/// is named, since the target directory may also have libraries of other crates.
+fn lib_name(config: &Config, gem_home: &Utf8Path, manifest: &Utf8Path) -> Result<String> {
+    let metadata_error = |reason: String| Error::CargoMetadata {
+        manifest: manifest.to_string(),
+        reason,
+    };
+    let output = crate::commands::run::capture_run_no_install(
+        Invocation::tool("cargo", vec![("GEM_HOME", gem_home.to_string())]),
+        config,
+        vec![
+            "metadata".to_owned(),
+            "--format-version".to_owned(),
+            "1".to_owned(),
+            "--no-deps".to_owned(),
+            "--manifest-path".to_owned(),
+            manifest.to_string(),
+        ],
+        manifest.parent(),
+    )?;
+    if !output.status.success() {
+        return Err(metadata_error(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+    let metadata: CargoMetadata =
+        serde_json::from_slice(&output.stdout).map_err(|err| metadata_error(err.to_string()))?;
+    lib_name_from_metadata(&metadata, manifest)
+        .ok_or_else(|| metadata_error("it has no lib target".to_owned()))
+}
+
+fn lib_name_from_metadata(metadata: &CargoMetadata, manifest: &Utf8Path) -> Option<String> {
+    let package = metadata
+        .packages
+        .iter()
+        .find(|package| package.manifest_path.as_path() == manifest)?;
+    let lib = package
+        .targets
+        .iter()
+        .find(|target| target.kind.iter().any(|kind| kind.ends_with("lib")))?;
+    Some(lib.name.replace('-', "_"))
+}
+
+/// Finds the cdylib Cargo built for the crate whose lib target is `crate_name`.
+fn find_cdylib(dir: &Utf8Path, crate_name: &str) -> Option<Utf8PathBuf> {
+    [
+        format!("lib{crate_name}.so"),
+        format!("lib{crate_name}.dylib"),
+        format!("{crate_name}.dll"),
+    ]
+    .into_iter()
+    .map(|file_name| dir.join(file_name))
+    .find(|path| path.is_file())
+}
+
+/// Where in lib/ the extension goes. Like RubyGems, this drops the first and last path
+/// components, so `ext/foo/bar/Cargo.toml` goes in `lib/foo/bar`.
+fn nesting(extension: &str) -> String {
+    let parts: Vec<&str> = extension.split('/').filter(|part| *part != ".").collect();
+    match parts.len() {
+        0..=2 => String::new(),
+        len => parts[1..len - 1].join("/"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_flags() {
+        assert_eq!(
+            link_flags("-L/opt/ruby/lib -L /usr/lib -lpthread -l m -l:libgmp.a -l:libz.so"),
+            vec![
+                "-L",
+                "native=/opt/ruby/lib",
+                "-L",
+                "native=/usr/lib",
+                "-l",
+                "pthread",
+                "-l",
+                "m",
+                "-l",
+                "static=gmp",
+                "-l",
+                "dylib=z",
+            ]
+        );
+        assert_eq!(
+            link_flags(
+                "-Wl,-undefined,dynamic_lookup $(DEFFILE) -Wl,--compress-debug-sections=zlib -F /Library/Frameworks"
+            ),
+            vec![
+                "-C",
+                "link-arg=-Wl,-undefined,dynamic_lookup",
+                "-l",
+                "framework=/Library/Frameworks",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rustc_args() {
+        let rbconfig = RbConfig::from_pairs(&[
+            ("CC", "clang -fdeclspec"),
+            ("libdir", "/opt/ruby/lib"),
+            ("DLDFLAGS", "-Wl,-multiply_defined,suppress"),
+            ("LIBS", "-lpthread"),
+            ("target_os", "darwin24"),
+        ]);
+        assert_eq!(
+            rustc_args(&rbconfig),
+            vec![
+                "-C",
+                "linker=clang",
+                "-C",
+                "link-arg=-fdeclspec",
+                "-L",
+                "native=/opt/ruby/lib",
+                "-C",
+                "link-arg=-Wl,-multiply_defined,suppress",
+                "-l",
+                "pthread",
+                "-C",
+                "link-arg=-Wl,-undefined,dynamic_lookup",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lib_name_from_metadata() {
+        let metadata: CargoMetadata = serde_json::from_str(
+            r#"{
+                "packages": [
+                    {
+                        "name": "oxi-helpers",
+                        "manifest_path": "/gems/oxi/ext/helpers/Cargo.toml",
+                        "targets": [{ "name": "oxi-helpers", "kind": ["lib"] }]
+                    },
+                    {
+                        "name": "oxi",
+                        "manifest_path": "/gems/oxi/ext/oxi/Cargo.toml",
+                        "targets": [
+                            { "name": "build-script-build", "kind": ["custom-build"] },
+                            { "name": "oxi-ext", "kind": ["cdylib"] },
+                            { "name": "oxi-cli", "kind": ["bin"] }
+                        ]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            lib_name_from_metadata(&metadata, Utf8Path::new("/gems/oxi/ext/oxi/Cargo.toml"))
+                .as_deref(),
+            Some("oxi_ext")
+        );
+        assert_eq!(
+            lib_name_from_metadata(&metadata, Utf8Path::new("/gems/oxi/ext/helpers/Cargo.toml"))
+                .as_deref(),
+            Some("oxi_helpers")
+        );
+        assert_eq!(
+            lib_name_from_metadata(&metadata, Utf8Path::new("/gems/other/Cargo.toml")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_find_cdylib() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        // A dependency built as a dylib sorts first, but isn't the extension.
+        fs_err::write(dir.path().join("liba_dependency.so"), "").unwrap();
+        fs_err::write(dir.path().join("liboxi_ext.so"), "").unwrap();
+        fs_err::create_dir(dir.path().join("deps")).unwrap();
+
+        assert_eq!(
+            find_cdylib(dir.path(), "oxi_ext"),
+            Some(dir.path().join("liboxi_ext.so"))
+        );
+        assert_eq!(find_cdylib(dir.path(), "other"), None);
+    }
+
+    #[test]
+    fn test_nesting() {
+        assert_eq!(nesting("ext/oxi/Cargo.toml"), "oxi");
+        assert_eq!(nesting("ext/oxi/test/Cargo.toml"), "oxi/test");
+        assert_eq!(nesting("Cargo.toml"), "");
+        assert_eq!(nesting("rust/Cargo.toml"), "");
+    }
+}
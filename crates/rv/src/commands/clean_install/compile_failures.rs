@@ -0,0 +1,101 @@
+//! Reporting every gem whose native extensions didn't compile, so all the missing system
+//! libraries can be found in one run of `rv ci --keep-going`.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use owo_colors::OwoColorize;
+
+/// How many lines from the end of a failed build's log to show.
+const LOG_LINES: usize = 20;
+
+/// A gem whose native extensions didn't compile.
+#[derive(Debug)]
+pub(super) struct CompileFailure {
+    pub gem: String,
+    pub reason: FailureReason,
+}
+
+#[derive(Debug)]
+pub(super) enum FailureReason {
+    /// A build command failed. The log is the most useful one to read, e.g. `mkmf.log` if
+    /// `extconf.rb` couldn't find a library.
+    Build {
+        ext_dest: Utf8PathBuf,
+        log: Utf8PathBuf,
+    },
+    /// rv couldn't run the build at all.
+    Error(String),
+    /// Not attempted, because a gem it depends on didn't compile.
+    FailedDependency(String),
+}
+
+impl CompileFailure {
+    fn print(&self) {
+        eprintln!("\n{}", self.gem.red().bold());
+        match &self.reason {
+            FailureReason::Build { ext_dest, log } => {
+                for name in ["build_ext.log", "mkmf.log"] {
+                    let path = ext_dest.join(name);
+                    if path.exists() {
+                        eprintln!("  {name}: {}", path.cyan());
+                    }
+                }
+                let tail = last_lines(log, LOG_LINES);
+                if !tail.is_empty() {
+                    eprintln!(
+                        "  Last lines of {}:",
+                        log.file_name().unwrap_or(log.as_str())
+                    );
+                    for line in tail {
+                        eprintln!("    {line}");
+                    }
+                }
+            }
+            FailureReason::Error(err) => eprintln!("  {err}"),
+            FailureReason::FailedDependency(dep) => {
+                eprintln!("  Skipped, because it depends on {dep}, which didn't compile")
+            }
+        }
+    }
+}
+
+/// Print a summary of every gem that didn't compile.
+pub(super) fn print_summary(failures: &[CompileFailure]) {
+    let n_gems = if failures.len() == 1 {
+        "1 gem".to_string()
+    } else {
+        format!("{} gems", failures.len())
+    };
+    eprintln!("\nCould not compile native extensions for {n_gems}:");
+    for failure in failures {
+        failure.print();
+    }
+}
+
+/// The last `n` lines of a log file, or nothing if it can't be read.
+fn last_lines(path: &Utf8Path, n: usize) -> Vec<String> {
+    let Ok(contents) = fs_err::read(path) else {
+        return Vec::new();
+    };
+    let contents = String::from_utf8_lossy(&contents);
+    let lines: Vec<&str> = contents.trim_end().lines().collect();
+    lines[lines.len().saturating_sub(n)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_lines() {
+        let temp_dir = camino_tempfile::tempdir().unwrap();
+        let log = temp_dir.path().join("mkmf.log");
+        fs_err::write(&log, "one\ntwo\nthree\n\n").unwrap();
+
+        assert_eq!(last_lines(&log, 2), vec!["two", "three"]);
+        assert_eq!(last_lines(&log, 10), vec!["one", "two", "three"]);
+        assert!(last_lines(&temp_dir.path().join("missing.log"), 2).is_empty());
+    }
+}
@@ -0,0 +1,104 @@
+//! Compiled native extensions, cached so every project and tool using the same gem, Ruby
+//! and build flags can reuse them instead of compiling again.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use dircpy::CopyBuilder;
+use tracing::debug;
+
+use super::Result;
+use crate::config::Config;
+
+/// Files the install writes next to the compiled extension, which aren't part of the build.
+const NOT_BUILD_OUTPUTS: &[&str] = &["gem.build_complete", "build_ext.log", "mkmf.log"];
+
+/// Where a gem's compiled extensions are cached. The key covers everything that changes the
+/// compiled files: the gem (including its platform), Ruby's ABI and platform, and build flags.
+pub(super) fn cache_dir(
+    config: &Config,
+    full_name: &str,
+    extensions_scope: &str,
+    build_args: &[String],
+) -> Utf8PathBuf {
+    let key = rv_cache::cache_digest((extensions_scope, build_args));
+    config
+        .cache
+        .shard(rv_cache::CacheBucket::Extension, full_name)
+        .into_path_buf()
+        .join(key)
+}
+
+/// Copy previously compiled extensions into place. Returns false if there weren't any.
+pub(super) fn restore(
+    cache_dir: &Utf8Path,
+    ext_dest: &Utf8Path,
+    lib_dest: &Utf8Path,
+) -> Result<bool> {
+    if !cache_dir.is_dir() {
+        return Ok(false);
+    }
+    debug!("Reusing compiled extensions from {cache_dir}");
+    for dest in [lib_dest, ext_dest] {
+        CopyBuilder::new(cache_dir, dest).overwrite(true).run()?;
+    }
+    super::record_use(cache_dir);
+    Ok(true)
+}
+
+/// Save freshly compiled extensions for other installs to reuse.
+pub(super) fn store(cache_dir: &Utf8Path, ext_dest: &Utf8Path) -> Result<()> {
+    let parent = cache_dir.parent().expect("cache dir has no parent");
+    fs_err::create_dir_all(parent)?;
+
+    // Copy everything first, then move it into place, so concurrent installs never see a
+    // partially copied cache entry.
+    let tmp_dir = camino_tempfile::tempdir_in(parent)?;
+    let mut copy = CopyBuilder::new(ext_dest, tmp_dir.path());
+    for file in NOT_BUILD_OUTPUTS {
+        copy = copy.with_exclude_filter(file);
+    }
+    copy.run()?;
+
+    if let Err(err) = fs_err::rename(tmp_dir.path(), cache_dir) {
+        // Another install cached the same build first, which is fine.
+        debug!("Not caching compiled extensions in {cache_dir}: {err}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_and_restore() {
+        let temp_dir = camino_tempfile::tempdir().unwrap();
+        let root = temp_dir.path();
+        let cache_dir = root.join("cache/nokogiri-1.18.10/abc123");
+
+        let ext_dest = root.join("first/extensions/nokogiri-1.18.10");
+        fs_err::create_dir_all(ext_dest.join("nokogiri")).unwrap();
+        fs_err::write(ext_dest.join("nokogiri/nokogiri.so"), "compiled").unwrap();
+        fs_err::write(ext_dest.join("build_ext.log"), "make output").unwrap();
+        fs_err::write(ext_dest.join("gem.build_complete"), "").unwrap();
+
+        assert!(!restore(&cache_dir, &ext_dest, &ext_dest).unwrap());
+        store(&cache_dir, &ext_dest).unwrap();
+        assert!(cache_dir.join("nokogiri/nokogiri.so").exists());
+        assert!(!cache_dir.join("build_ext.log").exists());
+        assert!(!cache_dir.join("gem.build_complete").exists());
+
+        let other_ext_dest = root.join("second/extensions/nokogiri-1.18.10");
+        let other_lib_dest = root.join("second/gems/nokogiri-1.18.10/lib");
+        assert!(restore(&cache_dir, &other_ext_dest, &other_lib_dest).unwrap());
+        for dest in [other_ext_dest, other_lib_dest] {
+            assert_eq!(
+                fs_err::read_to_string(dest.join("nokogiri/nokogiri.so")).unwrap(),
+                "compiled"
+            );
+        }
+
+        // Storing the same build again leaves the cached copy alone.
+        store(&cache_dir, &ext_dest).unwrap();
+        assert!(cache_dir.join("nokogiri/nokogiri.so").exists());
+    }
+}
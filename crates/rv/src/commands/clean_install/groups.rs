@@ -0,0 +1,274 @@
+//! Choosing which Gemfile groups to install, like Bundler's `BUNDLE_WITHOUT`,
+//! `BUNDLE_WITH` and `BUNDLE_ONLY` settings.
+
+use std::collections::{HashMap, HashSet};
+
+use rv_lockfile::datatypes::GemfileDotLock;
+
+use crate::config::bundler_settings::BundlerSettings;
+use crate::gemfile::Gemfile;
+
+/// Which Gemfile groups should be installed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct GroupSelection {
+    /// Groups to leave out.
+    pub without: Vec<String>,
+    /// Optional groups to install anyway.
+    pub with: Vec<String>,
+    /// If not empty, only these groups are installed.
+    pub only: Vec<String>,
+}
+
+impl GroupSelection {
+    /// Combines the command-line flags with Bundler's settings. Flags take priority.
+    pub fn new(
+        without: Option<&str>,
+        with: Option<&str>,
+        only: Option<&str>,
+        settings: &BundlerSettings,
+    ) -> Self {
+        let setting = |flag: Option<&str>, key: &str| match flag {
+            Some(groups) => split_groups(groups),
+            None => settings
+                .get_string(key)
+                .map(|groups| split_groups(&groups))
+                .unwrap_or_default(),
+        };
+        Self {
+            without: setting(without, "BUNDLE_WITHOUT"),
+            with: setting(with, "BUNDLE_WITH"),
+            only: setting(only, "BUNDLE_ONLY"),
+        }
+    }
+
+    /// Were any groups chosen? If not, everything except optional groups is installed.
+    pub fn is_empty(&self) -> bool {
+        self.without.is_empty() && self.with.is_empty() && self.only.is_empty()
+    }
+
+    /// Should gems in this group be installed?
+    fn includes(&self, group: &str, optional_groups: &[String]) -> bool {
+        if !self.only.is_empty() {
+            return self.only.iter().any(|g| g == group);
+        }
+        if self.without.iter().any(|g| g == group) {
+            return false;
+        }
+        !optional_groups.iter().any(|g| g == group) || self.with.iter().any(|g| g == group)
+    }
+}
+
+/// Bundler accepts group lists separated by colons or spaces, e.g. `development:test`.
+fn split_groups(groups: &str) -> Vec<String> {
+    groups
+        .split(|c: char| c == ':' || c.is_whitespace())
+        .filter(|group| !group.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Removes every locked gem that isn't needed by a gem in the selected groups.
+/// Returns how many gems were removed.
+pub(super) fn retain_groups(
+    lockfile: &mut GemfileDotLock<'_>,
+    gemfile: &Gemfile,
+    selection: &GroupSelection,
+) -> usize {
+    // A gem listed several times is installed if any of its declarations is in a selected group.
+    let mut included: HashMap<&str, bool> = HashMap::new();
+    for dep in &gemfile.dependencies {
+        let in_selected_group = dep
+            .groups
+            .iter()
+            .any(|group| selection.includes(group, &gemfile.optional_groups));
+        *included.entry(&dep.name).or_default() |= in_selected_group;
+    }
+
+    // Gems the Gemfile doesn't mention are kept, to be safe.
+    let (roots, excluded_roots): (Vec<&str>, Vec<&str>) = lockfile
+        .dependencies
+        .iter()
+        .map(|dep| dep.name.as_ref())
+        .partition(|name| included.get(name).copied().unwrap_or(true));
+
+    // Each gem's dependencies, across every platform it's locked for.
+    let mut deps_of: HashMap<&str, Vec<&str>> = HashMap::new();
+    let specs = lockfile
+        .gem
+        .iter()
+        .flat_map(|s| &s.specs)
+        .chain(lockfile.git.iter().flat_map(|s| &s.specs))
+        .chain(lockfile.path.iter().flat_map(|s| &s.specs));
+    for spec in specs {
+        deps_of
+            .entry(&spec.release_tuple.name)
+            .or_default()
+            .extend(spec.deps.iter().map(|dep| dep.name.as_str()));
+    }
+
+    // Only remove gems that excluded gems need, and included gems don't. Anything else in the
+    // lockfile isn't reachable from DEPENDENCIES at all, so leave it be.
+    let needed = transitive_closure(roots, &deps_of);
+    let unneeded: HashSet<String> = transitive_closure(excluded_roots, &deps_of)
+        .difference(&needed)
+        .cloned()
+        .collect();
+    if unneeded.is_empty() {
+        return 0;
+    }
+
+    let original_count = lockfile.spec_count();
+    for section in &mut lockfile.gem {
+        section
+            .specs
+            .retain(|spec| !unneeded.contains(&spec.release_tuple.name));
+    }
+    for section in &mut lockfile.git {
+        section
+            .specs
+            .retain(|spec| !unneeded.contains(&spec.release_tuple.name));
+    }
+    for section in &mut lockfile.path {
+        section
+            .specs
+            .retain(|spec| !unneeded.contains(&spec.release_tuple.name));
+    }
+    // There's no point fetching git repos or paths that no remaining gem comes from.
+    lockfile.git.retain(|section| !section.specs.is_empty());
+    lockfile.path.retain(|section| !section.specs.is_empty());
+
+    original_count - lockfile.spec_count()
+}
+
+/// Every gem in `roots`, and every gem they depend on, directly or indirectly.
+fn transitive_closure(roots: Vec<&str>, deps_of: &HashMap<&str, Vec<&str>>) -> HashSet<String> {
+    let mut found = HashSet::new();
+    let mut to_visit = roots;
+    while let Some(name) = to_visit.pop() {
+        if found.insert(name.to_owned()) {
+            to_visit.extend(deps_of.get(name).into_iter().flatten());
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GEMFILE: &str = r#"source "https://rubygems.org"
+
+gem "rails"
+
+group :development, :test do
+  gem "rspec"
+end
+
+group :test do
+  gem "capybara"
+end
+
+group :docs, optional: true do
+  gem "yard"
+end
+
+gem "debug", group: :development
+gem "debug", group: :ci
+"#;
+
+    const LOCKFILE: &str = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    capybara (3.40.0)
+      rack (>= 1.6.0)
+    debug (1.11.0)
+    rack (3.2.3)
+    rails (8.0.0)
+      rack (>= 2.2.4)
+    rspec (3.13.0)
+      rspec-core (~> 3.13.0)
+    rspec-core (3.13.0)
+    yard (0.9.37)
+
+PLATFORMS
+  ruby
+
+DEPENDENCIES
+  capybara
+  debug
+  rails
+  rspec
+  yard
+
+BUNDLED WITH
+   2.7.2
+";
+
+    fn installed(selection: GroupSelection) -> Vec<String> {
+        let gemfile = crate::gemfile::parse(GEMFILE).unwrap();
+        let mut lockfile = rv_lockfile::parse(LOCKFILE).unwrap();
+        retain_groups(&mut lockfile, &gemfile, &selection);
+        lockfile.gem[0]
+            .specs
+            .iter()
+            .map(|spec| spec.release_tuple.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_split_groups() {
+        assert_eq!(
+            split_groups("development:test"),
+            vec!["development", "test"]
+        );
+        assert_eq!(
+            split_groups("development test"),
+            vec!["development", "test"]
+        );
+        assert_eq!(split_groups(""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_default_skips_optional_groups() {
+        assert_eq!(
+            installed(GroupSelection::default()),
+            vec!["capybara", "debug", "rack", "rails", "rspec", "rspec-core"]
+        );
+    }
+
+    #[test]
+    fn test_without() {
+        let selection = GroupSelection {
+            without: vec!["development".to_owned(), "test".to_owned()],
+            ..Default::default()
+        };
+        // debug is still needed by the ci group, and rack by rails.
+        assert_eq!(installed(selection), vec!["debug", "rack", "rails"]);
+    }
+
+    #[test]
+    fn test_with_optional_group() {
+        let selection = GroupSelection {
+            without: vec!["test".to_owned()],
+            with: vec!["docs".to_owned()],
+            ..Default::default()
+        };
+        assert_eq!(
+            installed(selection),
+            vec!["debug", "rack", "rails", "rspec", "rspec-core", "yard"]
+        );
+    }
+
+    #[test]
+    fn test_only() {
+        let selection = GroupSelection {
+            only: vec!["test".to_owned()],
+            ..Default::default()
+        };
+        assert_eq!(
+            installed(selection),
+            vec!["capybara", "rack", "rspec", "rspec-core"]
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! Ruby's build configuration (`RbConfig::CONFIG`), which native extension builders
+//! need to compile and link against the Ruby that will load the extension.
+
+use std::collections::HashMap;
+
+use camino::Utf8Path;
+
+use super::{Error, Result};
+use crate::commands::run::Invocation;
+use crate::config::Config;
+
+/// Prints every `RbConfig::CONFIG` entry as NUL-separated key/value pairs.
+const DUMP_RBCONFIG: &str =
+    r#"RbConfig::CONFIG.each { |key, value| print key, "\0", value.to_s, "\0" }"#;
+
+#[derive(Debug, Default, Clone)]
+pub(super) struct RbConfig {
+    values: HashMap<String, String>,
+}
+
+impl RbConfig {
+    /// Asks the current Ruby for its build configuration.
+    pub fn query(config: &Config, gem_home: &Utf8Path) -> Result<Self> {
+        let output = crate::commands::run::capture_run_no_install(
+            Invocation::ruby(vec![("GEM_HOME", gem_home.to_string())]),
+            config,
+            vec!["-e".to_owned(), DUMP_RBCONFIG.to_owned()],
+            None,
+        )?;
+        if !output.status.success() {
+            return Err(Error::RbConfig(
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ));
+        }
+        Ok(Self::parse(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    fn parse(dump: &str) -> Self {
+        let mut parts = dump.split('\0');
+        let mut values = HashMap::new();
+        while let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+            values.insert(key.to_owned(), value.to_owned());
+        }
+        Self { values }
+    }
+
+    /// The value of a config key, or an empty string if Ruby doesn't set it.
+    pub fn get(&self, key: &str) -> &str {
+        self.values.get(key).map_or("", String::as_str)
+    }
+
+    /// Every config entry as `RBCONFIG_<key>` environment variables, which is how
+    /// RubyGems passes Ruby's configuration to rb-sys and other build scripts.
+    pub fn env(&self) -> impl Iterator<Item = (String, String)> + '_ {
+        self.values
+            .iter()
+            .map(|(key, value)| (format!("RBCONFIG_{key}"), value.clone()))
+    }
+
+    /// Is this Ruby built for macOS?
+    pub fn is_darwin(&self) -> bool {
+        self.get("target_os").contains("darwin")
+    }
+
+    /// Is this Ruby built for Windows with the MinGW toolchain?
+    pub fn is_mingw(&self) -> bool {
+        self.get("target_os").contains("mingw")
+    }
+
+    #[cfg(test)]
+    pub fn from_pairs(pairs: &[(&str, &str)]) -> Self {
+        Self {
+            values: pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rbconfig_dump() {
+        let rbconfig = RbConfig::parse("DLEXT\0bundle\0CC\0clang -fdeclspec\0EMPTY\0\0");
+        assert_eq!(rbconfig.get("DLEXT"), "bundle");
+        assert_eq!(rbconfig.get("CC"), "clang -fdeclspec");
+        assert_eq!(rbconfig.get("EMPTY"), "");
+        assert_eq!(rbconfig.get("MISSING"), "");
+
+        let mut env: Vec<_> = rbconfig.env().collect();
+        env.sort();
+        assert_eq!(
+            env[0],
+            ("RBCONFIG_CC".to_owned(), "clang -fdeclspec".to_owned())
+        );
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use camino::Utf8PathBuf;
+use owo_colors::OwoColorize;
+use rv_gem_types::{Platform, ProjectDependency, Requirement};
+use rv_lockfile::datatypes::{
+    Checksum, GemRange, GemSection, GemfileDotLock, LockfileIndentation, RubyVersionSection, Spec,
+};
+use rv_ruby::version::RubyVersion;
+use tracing::debug;
+use url::Url;
+
+use crate::{
+    GlobalArgs,
+    config::Config,
+    gemfile::{self, DependencySource, RubyDirective},
+    gemserver::{self, sources::GemSources},
+};
+
+#[derive(Debug, clap_derive::Args)]
+pub struct LockArgs {
+    /// Path to Gemfile
+    #[arg(long, env = "BUNDLE_GEMFILE")]
+    gemfile: Option<Utf8PathBuf>,
+
+    /// Check that the existing Gemfile.lock is in the canonical form Bundler writes,
+    /// without resolving the Gemfile again
+    #[arg(long, conflicts_with = "normalize")]
+    check: bool,
+
+    /// Rewrite the existing Gemfile.lock in the canonical form Bundler writes,
+    /// without resolving the Gemfile again
+    #[arg(long)]
+    normalize: bool,
+}
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    Config(#[from] crate::config::Error),
+    #[error("Gemfile \"{0}\" does not exist")]
+    MissingGemfile(Utf8PathBuf),
+    #[error("Lockfile \"{0}\" does not exist")]
+    MissingLockfile(Utf8PathBuf),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Parse(#[from] rv_lockfile::ParseErrors),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Lint(#[from] rv_lockfile::lint::LintErrors),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Gemfile(#[from] gemfile::ParseError),
+    #[error("The Gemfile does not declare a `source` to fetch gems from")]
+    NoSource,
+    #[error("{0} is not a valid URL")]
+    BadUrl(String),
+    #[error(
+        "The gem {gem} comes from {source_kind} {location}, but rv can only lock gems from a gem server"
+    )]
+    UnsupportedSource {
+        gem: String,
+        source_kind: &'static str,
+        location: String,
+    },
+    #[error("Could not read the Ruby version from {path}: {reason}")]
+    InvalidRubyFile { path: Utf8PathBuf, reason: String },
+    #[error(transparent)]
+    GemserverError(#[from] gemserver::Error),
+    #[error("Could not resolve the Gemfile's dependencies: {0}")]
+    CouldNotResolve(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+pub(crate) async fn lock(global_args: &GlobalArgs, args: LockArgs) -> Result<()> {
+    let config = &Config::with_settings(global_args, None)?;
+
+    config.self_update_if_needed().await;
+
+    let gemfile_path = args.gemfile.unwrap_or_else(|| "Gemfile".into());
+    if args.check || args.normalize {
+        return lint_lockfile(&gemfile_path.with_added_extension("lock"), args.normalize);
+    }
+
+    let contents = fs_err::read_to_string(&gemfile_path)
+        .map_err(|_| Error::MissingGemfile(gemfile_path.clone()))?;
+    let gemfile = gemfile::parse(&rv_lockfile::normalize_line_endings(&contents))?;
+
+    let mut sources = gem_sources(config, &gemfile).await?;
+    let ruby_to_use = ruby_for_gemfile(config, &gemfile_path, &gemfile.ruby).await?;
+    debug!("Locking for Ruby {ruby_to_use}");
+
+    // Gems can be listed several times (e.g. in different groups), so merge their requirements.
+    let mut requirements: BTreeMap<&str, Requirement> = BTreeMap::new();
+    let mut deps_to_resolve = BTreeSet::new();
+    for dep in &gemfile.dependencies {
+        let requirement = requirements
+            .entry(&dep.name)
+            .or_insert_with(|| Requirement {
+                constraints: Vec::new(),
+            });
+        for constraint in &dep.requirement.constraints {
+            if !requirement.constraints.contains(constraint) {
+                requirement.constraints.push(constraint.clone());
+            }
+        }
+        if dep.applies_to(&ruby_to_use) {
+            deps_to_resolve.insert(dep.name.as_str());
+        } else {
+            debug!("Skipping {}, it doesn't apply to this platform", dep.name);
+        }
+    }
+
+    let root_deps: Vec<ProjectDependency> = deps_to_resolve
+        .iter()
+        .map(|name| ProjectDependency {
+            name: (*name).to_owned(),
+            requirement: requirements[name].clone(),
+        })
+        .collect();
+
+    let root = crate::resolver::gemfile_root(root_deps.clone());
+    sources
+        .add_transitive_deps(&root, None, &ruby_to_use)
+        .await?;
+
+    debug!("Resolving all dependencies via PubGrub");
+    let mut versions_needed =
+        crate::resolver::solve_gemfile(root_deps, std::mem::take(&mut sources.gems_to_deps))
+            .map_err(|e| Error::CouldNotResolve(e.to_string()))?;
+    versions_needed.sort_by(|(a, _), (b, _)| a.cmp(b));
+    debug!("All dependencies resolved");
+
+    // Like Bundler, write a GEM section for every source, each with the gems that came from it.
+    let mut remotes: Vec<&Url> = sources.servers().map(|server| &server.url).collect();
+    remotes.sort();
+    let gem = remotes
+        .into_iter()
+        .map(|remote| GemSection {
+            // Bundler always writes remotes with a trailing slash, which the gemserver URL has.
+            remote: Some(remote.to_string().into()),
+            specs: versions_needed
+                .iter()
+                .filter(|(release_tuple, _)| {
+                    sources
+                        .source_of(&release_tuple.name)
+                        .is_some_and(|server| &server.url == remote)
+                })
+                .map(|(release_tuple, gem_release)| {
+                    let mut deps = gem_release.deps.clone();
+                    deps.sort();
+                    Spec {
+                        release_tuple: release_tuple.clone(),
+                        deps,
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+    let checksums = versions_needed
+        .iter()
+        .map(|(release_tuple, gem_release)| Checksum {
+            release_tuple: release_tuple.clone(),
+            algorithm: gem_release.checksum_algorithm(),
+            value: gem_release.metadata.checksum.clone(),
+        })
+        .collect();
+    let dependencies = requirements
+        .into_iter()
+        .map(|(name, requirement)| GemRange {
+            name: name.into(),
+            requirement: if requirement.constraints.is_empty() {
+                Requirement::default()
+            } else {
+                requirement
+            },
+            // Gems pinned to a source are marked with a `!`.
+            nonstandard: sources.is_pinned(name),
+        })
+        .collect();
+    // Like Bundler, only lock the Ruby version if the Gemfile asked for one.
+    let ruby_version = gemfile.ruby.as_ref().map(|_| RubyVersionSection {
+        indentation: LockfileIndentation::ThreeSpaces,
+        cruby_version: ruby_to_use.clone(),
+        engine_version: None,
+    });
+
+    let lockfile = GemfileDotLock {
+        gem,
+        platforms: vec![Platform::local()],
+        dependencies,
+        ruby_version,
+        checksums: Some(checksums),
+        ..Default::default()
+    };
+
+    let lockfile_path = gemfile_path.with_added_extension("lock");
+    fs_err::write(&lockfile_path, lockfile.to_string())?;
+
+    println!(
+        "Locked {} gems in {}",
+        versions_needed.len(),
+        lockfile_path.cyan()
+    );
+    Ok(())
+}
+
+/// Check an existing lockfile is canonical, optionally rewriting it so it is.
+fn lint_lockfile(lockfile_path: &Utf8PathBuf, normalize: bool) -> Result<()> {
+    let raw_contents = fs_err::read_to_string(lockfile_path)
+        .map_err(|_| Error::MissingLockfile(lockfile_path.clone()))?;
+    let contents = rv_lockfile::normalize_line_endings(&raw_contents);
+    let mut lockfile = rv_lockfile::parse(&contents)?;
+
+    if !normalize {
+        rv_lockfile::lint::lint(&contents, &lockfile)?;
+        println!("{} is in canonical form", lockfile_path.cyan());
+        return Ok(());
+    }
+
+    rv_lockfile::lint::normalize(&mut lockfile);
+    let normalized = lockfile.to_string();
+    if normalized != contents {
+        fs_err::write(lockfile_path, &normalized)?;
+        println!("Normalized {}", lockfile_path.cyan());
+    }
+    // Some problems can only be fixed by resolving again, so report them.
+    let lockfile = rv_lockfile::parse(&normalized)?;
+    rv_lockfile::lint::lint(&normalized, &lockfile)?;
+    Ok(())
+}
+
+/// Which gem servers should the Gemfile's gems come from?
+async fn gem_sources(config: &Config, gemfile: &gemfile::Gemfile) -> Result<GemSources> {
+    let parse_url = |source: &String| -> Result<Url> {
+        source.parse().map_err(|_| Error::BadUrl(source.clone()))
+    };
+
+    let mut sources = GemSources::default();
+    for source in &gemfile.sources {
+        sources.add_global(config, parse_url(source)?).await?;
+    }
+    for dep in &gemfile.dependencies {
+        match &dep.source {
+            DependencySource::Default => {
+                if gemfile.sources.is_empty() {
+                    return Err(Error::NoSource);
+                }
+            }
+            DependencySource::Rubygems(remote) => {
+                sources.pin(config, &dep.name, parse_url(remote)?).await?;
+            }
+            DependencySource::Git(location) => {
+                return Err(Error::UnsupportedSource {
+                    gem: dep.name.clone(),
+                    source_kind: "the git repository",
+                    location: location.clone(),
+                });
+            }
+            DependencySource::Path(location) => {
+                return Err(Error::UnsupportedSource {
+                    gem: dep.name.clone(),
+                    source_kind: "the path",
+                    location: location.clone(),
+                });
+            }
+        }
+    }
+
+    if sources.servers().next().is_none() {
+        return Err(Error::NoSource);
+    }
+    Ok(sources)
+}
+
+/// Pick the Ruby to resolve gems for, preferring what the Gemfile asks for.
+async fn ruby_for_gemfile(
+    config: &Config,
+    gemfile_path: &Utf8PathBuf,
+    directive: &Option<RubyDirective>,
+) -> Result<RubyVersion> {
+    match directive {
+        Some(RubyDirective::Requirement(requirement)) => {
+            Ok(config.best_ruby_matching_requirement(requirement).await?)
+        }
+        Some(RubyDirective::File(file)) => {
+            let path = match gemfile_path.parent() {
+                Some(dir) => dir.join(file),
+                None => file.into(),
+            };
+            let contents = fs_err::read_to_string(&path).map_err(|e| Error::InvalidRubyFile {
+                path: path.clone(),
+                reason: e.to_string(),
+            })?;
+            contents
+                .trim()
+                .parse()
+                .map_err(
+                    |e: rv_ruby::version::ParseVersionError| Error::InvalidRubyFile {
+                        path,
+                        reason: e.to_string(),
+                    },
+                )
+        }
+        None => match config.current_ruby() {
+            Some(ruby) => Ok(ruby.version),
+            None => Ok(config.find_matching_remote_ruby().await?),
+        },
+    }
+}
@@ -104,6 +104,11 @@ pub enum RubyCommand {
         /// Overwrite an existing installed version.
         #[arg(long)]
         force: bool,
+
+        /// Build Ruby from source if there's no precompiled build of it for this platform.
+        /// Extra flags for `./configure` can be given in RUBY_CONFIGURE_OPTS.
+        #[arg(long)]
+        build_from_source: bool,
     },
 
     #[command(about = "Uninstall a specific Ruby version")]
@@ -168,7 +173,18 @@ pub(crate) async fn ruby(global_args: &GlobalArgs, args: RubyArgs) -> Result<()>
             install_dir,
             tarball_path,
             force,
-        } => install::install(global_args, install_dir, version, tarball_path, force).await?,
+            build_from_source,
+        } => {
+            install::install(
+                global_args,
+                install_dir,
+                version,
+                tarball_path,
+                force,
+                build_from_source,
+            )
+            .await?
+        }
         RubyCommand::Uninstall { version } => uninstall::uninstall(global_args, version).await?,
         RubyCommand::Run {
             version,
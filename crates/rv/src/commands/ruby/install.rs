@@ -8,15 +8,21 @@ use owo_colors::OwoColorize;
 use reqwest::StatusCode;
 use std::path::PathBuf;
 use tokio::io::AsyncWriteExt;
-use tracing::{debug, info_span};
+use tracing::{debug, info_span, warn};
 use tracing_indicatif::span_ext::IndicatifSpanExt;
 
+use rv_client::retry::Attempts;
 use rv_platform::HostPlatform;
+use rv_ruby::Ruby;
+use rv_ruby::engine::RubyEngine;
 use rv_ruby::request::RubyRequest;
 
+use crate::checksum::sha256_of;
 use crate::progress::WorkProgress;
 use crate::{GlobalArgs, config::Config};
 
+mod source;
+
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
@@ -45,6 +51,47 @@ pub enum Error {
     InvalidTarballPath(PathBuf),
     #[error(transparent)]
     UnsupportedPlatform(#[from] rv_platform::UnsupportedPlatformError),
+    #[error(
+        "Ruby {version} isn't in the cache at {path}, so it can't be installed offline. Pass its archive with --tarball-path instead"
+    )]
+    NotCached { version: String, path: Utf8PathBuf },
+    #[error(
+        "Finding the latest ruby-dev needs the network, so it can't be installed offline. Pass its archive with --tarball-path instead"
+    )]
+    DevOffline,
+    #[error(
+        "The Ruby archive {path} has SHA-256 digest {actual}, but {expected} was published for it. It was removed from the cache, so installing again will download it afresh"
+    )]
+    ChecksumMismatch {
+        path: Utf8PathBuf,
+        expected: String,
+        actual: String,
+    },
+    #[error("There's no {engine} build for {platform}, so it can't be installed here")]
+    NoBuildForPlatform {
+        engine: RubyEngine,
+        platform: &'static str,
+    },
+    #[error("TruffleRuby's post-install hook failed ({0}), so it wasn't installed")]
+    PostInstallHookFailed(std::process::ExitStatus),
+    #[error("Ruby can't be built from source on {0}")]
+    SourceBuildUnsupported(&'static str),
+    #[error(
+        "No precompiled Ruby matches {0}, and building one from source needs its exact version, like 3.4.5"
+    )]
+    SourceBuildNeedsExactVersion(RubyRequest),
+    #[error(
+        "No SHA-256 digest is published for {url}, so it can't be verified and built. Building Ruby from source needs the network to check it"
+    )]
+    UnverifiedSource { url: String },
+    #[error("Building Ruby from source failed at `{step}`. See the build log at {log}")]
+    SourceBuildFailed { step: String, log: Utf8PathBuf },
+    #[error("The Ruby unpacked from {archive} doesn't work, so it wasn't installed")]
+    InvalidRuby {
+        archive: Utf8PathBuf,
+        #[source]
+        source: rv_ruby::RubyError,
+    },
 }
 
 type Result<T> = miette::Result<T, Error>;
@@ -55,18 +102,21 @@ pub(crate) async fn install(
     request: Option<RubyRequest>,
     tarball_path: Option<Utf8PathBuf>,
     force: bool,
+    build_from_source: bool,
 ) -> Result<()> {
-    let config = &Config::new(global_args, request)?;
+    let config = &Config::with_settings(global_args, request)?;
 
     config.self_update_if_needed().await;
 
     let progress = WorkProgress::new();
 
-    let request = config.ruby_request();
-
-    let version = match request {
-        RubyRequest::Dev => "dev".to_string(),
-        RubyRequest::Released(_) => config.find_matching_remote_ruby().await?.number(),
+    let (engine, version) = match requested_version(config).await {
+        // Partial versions are only matched against the precompiled Rubies, so one that matches
+        // none of them can't be built from source either.
+        Err(Error::ConfigError(crate::config::Error::NoMatchingRuby)) if build_from_source => {
+            return Err(Error::SourceBuildNeedsExactVersion(config.ruby_request()));
+        }
+        result => result?,
     };
 
     let install_dir = match install_dir {
@@ -86,15 +136,23 @@ pub(crate) async fn install(
     let archive_path = if let Some(path) = tarball_path {
         path
     } else {
-        download_tarball(config, &version, &progress).await?
+        match download_tarball(config, &engine, &version, &progress).await {
+            // There's no precompiled build of this Ruby for this host, or none is cached.
+            Err(
+                Error::NoMatchingRuby | Error::NoBuildForPlatform { .. } | Error::NotCached { .. },
+            ) if build_from_source && engine == RubyEngine::Ruby && version != "dev" => {
+                source::build_tarball(config, &version, &progress).await?
+            }
+            result => result?,
+        }
     };
 
-    extract_ruby_archive(&archive_path, &install_dir, &version)?;
+    extract_ruby_archive(&archive_path, &install_dir, &engine, &version)?;
 
     let installed_version = if version == "dev" {
         "ruby-dev".cyan().to_string()
     } else {
-        format!("Ruby version {}", version.cyan())
+        format!("Ruby version {}", display_version(&engine, &version).cyan())
     };
 
     println!("Installed {installed_version} to {}", install_dir.cyan());
@@ -102,19 +160,57 @@ pub(crate) async fn install(
     Ok(())
 }
 
+/// The engine and version of Ruby `config` asks for, e.g. `3.4.5` or `dev`.
+async fn requested_version(config: &Config) -> Result<(RubyEngine, String)> {
+    Ok(match config.ruby_request() {
+        RubyRequest::Dev => (RubyEngine::Ruby, "dev".to_string()),
+        RubyRequest::Released(_) => {
+            let version = config.find_matching_remote_ruby().await?;
+            (version.engine.clone(), version.number())
+        }
+    })
+}
+
+/// How users know `engine`'s `version`: just the version for CRuby, e.g. `3.4.5`, and with the
+/// engine for the others, e.g. `jruby-10.0.0.0`.
+fn display_version(engine: &RubyEngine, version: &str) -> String {
+    match engine {
+        RubyEngine::Ruby => version.to_owned(),
+        engine => format!("{engine}-{version}"),
+    }
+}
+
+/// Downloads the archive of `config`'s requested Ruby into the cache, without installing it.
+/// Returns the Ruby's version and where its archive is cached.
+pub(crate) async fn cache_archive(config: &Config) -> Result<(String, Utf8PathBuf)> {
+    let (engine, version) = requested_version(config).await?;
+    let progress = WorkProgress::new();
+    let archive_path = download_tarball(config, &engine, &version, &progress).await?;
+    Ok((display_version(&engine, &version), archive_path))
+}
+
 // downloads a remote ruby archive (tarball or zip)
 async fn download_tarball(
     config: &Config,
+    engine: &RubyEngine,
     version: &str,
     progress: &WorkProgress,
 ) -> Result<Utf8PathBuf> {
     let host = HostPlatform::current()?;
-    let mut url = ruby_url(version, &host);
+    let ext = archive_ext(engine, &host);
+    let mut url = match engine {
+        RubyEngine::JRuby => jruby_url(version),
+        RubyEngine::TruffleRuby => truffleruby_url(version, &host)?,
+        _ => ruby_url(version, &host),
+    };
 
     if version == "dev" && !host.is_windows() {
-        url = find_latest_ruby_dev_url(&url).await?;
+        if config.offline {
+            return Err(Error::DevOffline);
+        }
+        url = find_latest_ruby_dev_url(config, &url).await?;
     }
-    let archive_path = archive_cache_path(config, &url, &host);
+    let archive_path = archive_cache_path(config, &url, ext);
 
     let cache_dir = archive_path.parent().unwrap();
     if !cache_dir.exists() {
@@ -126,13 +222,97 @@ async fn download_tarball(
             "Archive {} already exists, skipping download.",
             archive_path.cyan()
         );
+    } else if config.offline {
+        return Err(Error::NotCached {
+            version: version.to_owned(),
+            path: archive_path,
+        });
     } else {
-        download_ruby_archive(config, &url, &archive_path, version, progress, &host).await?;
+        download_ruby_archive(config, &url, &archive_path, version, progress, ext).await?;
     }
 
+    verify_archive(config, engine, &url, &archive_path).await?;
+
     Ok(archive_path)
 }
 
+/// Check the archive at `archive_path`, downloaded from `url`, against its published digest.
+/// An archive that doesn't match is removed from the cache, so it isn't reused.
+async fn verify_archive(
+    config: &Config,
+    engine: &RubyEngine,
+    url: &str,
+    archive_path: &Utf8Path,
+) -> Result<()> {
+    let Some(expected) = expected_digest(config, engine, url).await? else {
+        warn!("No SHA-256 digest is published for {url}, so it can't be verified");
+        return Ok(());
+    };
+
+    let actual = sha256_of(archive_path)?;
+    if actual != expected {
+        fs_err::remove_file(archive_path)?;
+        return Err(Error::ChecksumMismatch {
+            path: archive_path.to_owned(),
+            expected,
+            actual,
+        });
+    }
+    debug!("Verified {archive_path} has SHA-256 digest {expected}");
+    Ok(())
+}
+
+/// The SHA-256 digest the archive at `url` should have: the one in the release metadata, or
+/// else the one in a `SHA256SUMS` file published next to the archive, or in a `.sha256` file
+/// published alongside it.
+async fn expected_digest(
+    config: &Config,
+    engine: &RubyEngine,
+    url: &str,
+) -> Result<Option<String>> {
+    if let Some(digest) = config.ruby_archive_digest(engine, url).await {
+        return Ok(Some(digest));
+    }
+    if config.offline {
+        return Ok(None);
+    }
+
+    let Some((base_url, file_name)) = url.rsplit_once('/') else {
+        return Ok(None);
+    };
+    if let Some(sums) = fetch_checksum_file(config, &format!("{base_url}/SHA256SUMS")).await?
+        && let Some(digest) = digest_from_sums(&sums, file_name)
+    {
+        return Ok(Some(digest));
+    }
+    let digest = fetch_checksum_file(config, &format!("{url}.sha256")).await?;
+    Ok(digest.and_then(|digest| {
+        let digest = digest.split_whitespace().next()?;
+        Some(digest.to_ascii_lowercase())
+    }))
+}
+
+/// The contents of the checksum file at `url`, if there is one.
+async fn fetch_checksum_file(config: &Config, url: &str) -> Result<Option<String>> {
+    let retry = config.rv_settings.retry_policy();
+    let response = fetch_url(url, true, 0, &mut retry.start()).await?;
+    if !response.status().is_success() {
+        debug!("No checksum file found at {url}: {}", response.status());
+        return Ok(None);
+    }
+    Ok(Some(response.text().await?))
+}
+
+/// Find the digest of `file_name` in a `SHA256SUMS` file. Its lines look like `<hex>  <name>`,
+/// with a `*` before names that were hashed in binary mode.
+fn digest_from_sums(sums: &str, file_name: &str) -> Option<String> {
+    sums.lines().find_map(|line| {
+        let (digest, name) = line.split_once(char::is_whitespace)?;
+        let name = name.trim_start().trim_start_matches('*');
+        (name == file_name).then(|| digest.to_ascii_lowercase())
+    })
+}
+
 /// Does a usable archive already exist at this path?
 fn valid_archive_exists(path: &Utf8Path) -> bool {
     fs_err::metadata(path).is_ok_and(|m| m.is_file() && m.len() > 0)
@@ -146,6 +326,40 @@ fn ruby_url(version: &str, host: &HostPlatform) -> String {
     format!("{download_base}/{download_path}")
 }
 
+/// Where to download the JRuby binary distribution of `version` from.
+fn jruby_url(version: &str) -> String {
+    let download_base = std::env::var("RV_JRUBY_INSTALL_URL")
+        .unwrap_or_else(|_| "https://repo1.maven.org/maven2/org/jruby/jruby-dist".to_owned());
+
+    format!("{download_base}/{version}/jruby-dist-{version}-bin.tar.gz")
+}
+
+/// Where to download the TruffleRuby standalone of `version` for `host` from.
+fn truffleruby_url(version: &str, host: &HostPlatform) -> Result<String> {
+    let platform = host
+        .truffleruby_platform()
+        .ok_or(Error::NoBuildForPlatform {
+            engine: RubyEngine::TruffleRuby,
+            platform: host.target_triple(),
+        })?;
+    let download_base = std::env::var("RV_TRUFFLERUBY_INSTALL_URL").unwrap_or_else(|_| {
+        "https://github.com/truffleruby/truffleruby/releases/download".to_owned()
+    });
+
+    Ok(format!(
+        "{download_base}/graal-{version}/truffleruby-community-{version}-{platform}.tar.gz"
+    ))
+}
+
+/// The extension of `engine`'s archives for `host`. JRuby is the same archive everywhere, and
+/// TruffleRuby only has tarballs.
+fn archive_ext(engine: &RubyEngine, host: &HostPlatform) -> &'static str {
+    match engine {
+        RubyEngine::JRuby | RubyEngine::TruffleRuby => "tar.gz",
+        _ => host.archive_ext(),
+    }
+}
+
 fn download_base_for(version: &str, host: &HostPlatform) -> String {
     if host.is_windows() {
         "https://github.com/oneclick/rubyinstaller2/releases/download".to_owned()
@@ -172,9 +386,10 @@ fn download_path_for(version: &str, host: &HostPlatform) -> String {
     }
 }
 
-async fn find_latest_ruby_dev_url(url: &str) -> Result<String> {
+async fn find_latest_ruby_dev_url(config: &Config, url: &str) -> Result<String> {
     let redirects = false;
-    let response = fetch_url(url, redirects).await?;
+    let retry = config.rv_settings.retry_policy();
+    let response = fetch_url(url, redirects, 0, &mut retry.start()).await?;
 
     if response.status() == StatusCode::FOUND {
         Ok(response
@@ -189,8 +404,7 @@ async fn find_latest_ruby_dev_url(url: &str) -> Result<String> {
     }
 }
 
-fn archive_cache_path(config: &Config, url: impl AsRef<str>, host: &HostPlatform) -> Utf8PathBuf {
-    let ext = host.archive_ext();
+fn archive_cache_path(config: &Config, url: impl AsRef<str>, ext: &str) -> Utf8PathBuf {
     let cache_key = rv_cache::cache_digest(url.as_ref());
     config
         .cache
@@ -199,8 +413,7 @@ fn archive_cache_path(config: &Config, url: impl AsRef<str>, host: &HostPlatform
         .join(format!("{cache_key}.{ext}"))
 }
 
-fn temp_archive_path(config: &Config, url: impl AsRef<str>, host: &HostPlatform) -> Utf8PathBuf {
-    let ext = host.archive_ext();
+fn temp_archive_path(config: &Config, url: impl AsRef<str>, ext: &str) -> Utf8PathBuf {
     let cache_key = rv_cache::cache_digest(url.as_ref());
     config
         .cache
@@ -209,20 +422,47 @@ fn temp_archive_path(config: &Config, url: impl AsRef<str>, host: &HostPlatform)
         .join(format!("{cache_key}.{ext}.tmp"))
 }
 
+/// How much of the archive an earlier, interrupted download already saved to `temp_path`.
+async fn partial_download_len(temp_path: &Utf8Path) -> u64 {
+    tokio::fs::metadata(temp_path)
+        .await
+        .map_or(0, |metadata| metadata.len())
+}
+
+/// Where a `206 Partial Content` response starts, from its `Content-Range` header.
+fn content_range_start(response: &reqwest::Response) -> Option<u64> {
+    let range = response
+        .headers()
+        .get(reqwest::header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?;
+    let (start, _) = range.strip_prefix("bytes ")?.split_once('-')?;
+    start.parse().ok()
+}
+
 /// Write the file from this HTTP `response` to the given `path`.
-/// While the stream is being handled, it'll be written to the given `temp_path`.
+/// While the stream is being handled, it'll be written to the given `temp_path`,
+/// after the first `offset` bytes if the response resumes an earlier download.
 /// Then once the download finishes, the file will be renamed to `path`.
 async fn write_to_filesystem(
     response: reqwest::Response,
     temp_path: &Utf8Path,
     path: &Utf8Path,
+    offset: u64,
     total_size: u64,
     progress: &WorkProgress,
     span: &tracing::Span,
 ) -> Result<()> {
-    let mut file = tokio::fs::File::create(&temp_path).await?;
+    let mut file = if offset > 0 {
+        tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(&temp_path)
+            .await?
+    } else {
+        tokio::fs::File::create(&temp_path).await?
+    };
     let mut stream = response.bytes_stream();
-    let mut downloaded: u64 = 0;
+    let mut downloaded: u64 = offset;
 
     while let Some(chunk) = stream.next().await {
         let chunk = chunk?;
@@ -254,59 +494,102 @@ async fn download_ruby_archive(
     archive_path: &Utf8PathBuf,
     version: &str,
     progress: &WorkProgress,
-    host: &HostPlatform,
+    ext: &str,
 ) -> Result<()> {
     debug!("Downloading archive from {url}");
     let redirects = true;
-    let response = fetch_url(url, redirects).await?;
+    let retry = config.rv_settings.retry_policy();
+    let mut attempts = retry.start();
+    let temp_path = temp_archive_path(config, url, ext);
 
-    if !response.status().is_success() {
+    let span = info_span!("Downloading Ruby", version);
+    span.pb_set_style(&ProgressStyle::with_template("{spinner:.green} {span_name} {msg}").unwrap());
+    let _guard = span.enter();
+    let mut started_progress = false;
+
+    loop {
+        // Pick up where an interrupted download, from this run or an earlier one, left off.
+        let resume_from = partial_download_len(&temp_path).await;
+        let response = fetch_url(url, redirects, resume_from, &mut attempts).await?;
         let status = response.status();
-        if status == StatusCode::NOT_FOUND {
-            return Err(Error::NoMatchingRuby);
-        }
-        let body = response
-            .text()
-            .await
-            .unwrap_or_else(|e| format!("<error reading body: {e}>"));
-        return Err(Error::DownloadFailed {
-            url: url.to_string(),
-            status,
-            body,
-        });
-    }
 
-    // Get Content-Length for progress tracking
-    let total_size = response.content_length().unwrap_or(0);
+        // A server that ignores the range sends the whole archive, which overwrites the partial
+        // download. One that can't resume from there means it doesn't line up with the archive
+        // on the server, so start over.
+        let offset = match status {
+            StatusCode::PARTIAL_CONTENT => {
+                content_range_start(&response).filter(|start| *start == resume_from)
+            }
+            StatusCode::RANGE_NOT_SATISFIABLE => None,
+            _ => Some(0),
+        };
+        let Some(offset) = offset else {
+            debug!("Could not resume the download of {url}, starting again");
+            tokio::fs::remove_file(&temp_path).await?;
+            continue;
+        };
 
-    // Set up progress tracking
-    progress.start_phase(total_size, 100);
+        if !status.is_success() {
+            if status == StatusCode::NOT_FOUND {
+                return Err(Error::NoMatchingRuby);
+            }
+            let body = response
+                .text()
+                .await
+                .unwrap_or_else(|e| format!("<error reading body: {e}>"));
+            return Err(Error::DownloadFailed {
+                url: url.to_string(),
+                status,
+                body,
+            });
+        }
+        if offset > 0 {
+            debug!("Resuming download of {url} from byte {offset}");
+        }
 
-    let span = info_span!("Downloading Ruby", version);
-    span.pb_set_style(&ProgressStyle::with_template("{spinner:.green} {span_name} {msg}").unwrap());
-    let _guard = span.enter();
+        // Get Content-Length for progress tracking
+        let total_size = response.content_length().map_or(0, |len| len + offset);
 
-    // Write the archive bytes to the filesystem.
-    let temp_path = temp_archive_path(config, url, host);
-    if let Err(e) = write_to_filesystem(
-        response,
-        &temp_path,
-        archive_path,
-        total_size,
-        progress,
-        &span,
-    )
-    .await
-    {
-        // Clean up the temporary file if there was any error.
-        tokio::fs::remove_file(temp_path).await?;
-        return Err(e);
-    }
+        // Set up progress tracking
+        if !started_progress {
+            progress.start_phase(total_size, 100);
+            progress.complete_many(offset);
+            started_progress = true;
+        }
 
-    Ok(())
+        // Write the archive bytes to the filesystem.
+        match write_to_filesystem(
+            response,
+            &temp_path,
+            archive_path,
+            offset,
+            total_size,
+            progress,
+            &span,
+        )
+        .await
+        {
+            Ok(()) => return Ok(()),
+            // Keep what was downloaded, so the next attempt can resume from there.
+            Err(Error::ReqwestError(err)) if attempts.retry_after_error(&err).await => continue,
+            Err(e @ Error::ReqwestError(_)) => return Err(e),
+            Err(e) => {
+                // Clean up the temporary file if there was any other error.
+                tokio::fs::remove_file(temp_path).await?;
+                return Err(e);
+            }
+        }
+    }
 }
 
-async fn fetch_url(url: &str, redirects: bool) -> Result<reqwest::Response> {
+/// Request `url`, retrying failures that might go away by themselves. If `resume_from` is
+/// more than zero, only the rest of the file after that many bytes is requested.
+async fn fetch_url(
+    url: &str,
+    redirects: bool,
+    resume_from: u64,
+    attempts: &mut Attempts<'_>,
+) -> Result<reqwest::Response> {
     // Build the request with optional GitHub authentication
     let client = if !redirects {
         reqwest::Client::builder()
@@ -316,25 +599,42 @@ async fn fetch_url(url: &str, redirects: bool) -> Result<reqwest::Response> {
         reqwest::Client::new()
     };
 
-    let mut request_builder = client.get(url);
-
     // Add GitHub token authentication if available and URL is from GitHub
     // Check GITHUB_TOKEN first (GitHub Actions), then GH_TOKEN (GitHub CLI/general use)
-    if crate::config::github::is_github_url(url) {
-        if let Some(token) = crate::config::github::github_token() {
+    let token = if crate::config::github::is_github_url(url) {
+        let token = crate::config::github::github_token();
+        if token.is_some() {
             debug!("Using authenticated GitHub request for archive download");
-            request_builder = request_builder.header("Authorization", format!("Bearer {}", token));
         } else {
             debug!("No GitHub token found, using unauthenticated request for archive download");
         }
-    }
+        token
+    } else {
+        None
+    };
+
+    let request = || {
+        let mut request_builder = client.get(url);
+        if let Some(token) = &token {
+            request_builder = request_builder.header("Authorization", format!("Bearer {}", token));
+        }
+        if resume_from > 0 {
+            request_builder =
+                request_builder.header(reqwest::header::RANGE, format!("bytes={resume_from}-"));
+        }
+        request_builder
+    };
 
-    Ok(request_builder.send().await?)
+    Ok(attempts.send(request).await?)
 }
 
+/// Unpack the Ruby in `archive_path` into `rubies_dir`. It's unpacked into a staging directory
+/// first, and only moved into place once it runs, so an interrupted or broken install never
+/// looks like an installed Ruby, and never replaces a working one.
 fn extract_ruby_archive(
     archive_path: &Utf8Path,
     rubies_dir: &Utf8Path,
+    engine: &RubyEngine,
     version: &str,
 ) -> Result<()> {
     let host = HostPlatform::current()?;
@@ -346,13 +646,50 @@ fn extract_ruby_archive(
         fs_err::create_dir_all(rubies_dir)?;
     }
 
+    // Stage inside `rubies_dir`, so that moving the Ruby into place is a rename on one filesystem.
+    let staging = camino_tempfile::Builder::new()
+        .prefix(".rv-install-")
+        .tempdir_in(rubies_dir)?;
+
     // Determine archive type by extension
     let extension = archive_path.extension().unwrap_or("");
-    match extension {
-        "zip" => extract_zip(archive_path, rubies_dir, version),
-        "7z" => extract_7z(archive_path, rubies_dir, version, &host),
-        _ => extract_tarball(archive_path, rubies_dir, version),
+    match (engine, extension) {
+        (RubyEngine::JRuby, _) => extract_jruby(archive_path, staging.path(), version),
+        (RubyEngine::TruffleRuby, _) => extract_truffleruby(archive_path, staging.path(), version),
+        (_, "zip") => extract_zip(archive_path, staging.path(), version),
+        (_, "7z") => extract_7z(archive_path, staging.path(), version, &host),
+        _ => extract_tarball(archive_path, staging.path(), version),
+    }?;
+
+    let ruby_dir_name = format!("{engine}-{version}");
+    let staged_ruby = staging.path().join(&ruby_dir_name);
+    Ruby::from_dir(staged_ruby.clone(), false).map_err(|source| Error::InvalidRuby {
+        archive: archive_path.to_owned(),
+        source,
+    })?;
+
+    replace_dir(
+        &staged_ruby,
+        &rubies_dir.join(ruby_dir_name),
+        staging.path(),
+    )
+}
+
+/// Move `new` to `dest`, swapping out whatever was there before. The old tree is moved into
+/// `trash`, and put back if `new` can't be moved into place.
+fn replace_dir(new: &Utf8Path, dest: &Utf8Path, trash: &Utf8Path) -> Result<()> {
+    if !dest.exists() {
+        fs_err::rename(new, dest)?;
+        return Ok(());
     }
+
+    let old = trash.join("old");
+    fs_err::rename(dest, &old)?;
+    if let Err(err) = fs_err::rename(new, dest) {
+        fs_err::rename(&old, dest)?;
+        return Err(err.into());
+    }
+    Ok(())
 }
 
 fn extract_tarball(tarball_path: &Utf8Path, rubies_dir: &Utf8Path, version: &str) -> Result<()> {
@@ -387,6 +724,71 @@ fn extract_tarball(tarball_path: &Utf8Path, rubies_dir: &Utf8Path, version: &str
     Ok(())
 }
 
+/// Unpack a JRuby binary distribution, whose files are all in `jruby-<version>/`.
+fn extract_jruby(tarball_path: &Utf8Path, rubies_dir: &Utf8Path, version: &str) -> Result<()> {
+    let tarball = fs_err::File::open(tarball_path)?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(tarball));
+    for e in archive.entries()? {
+        let mut entry = e?;
+        let dst = rubies_dir.as_std_path().join(entry.path()?);
+        if let Some(parent) = dst.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        crate::tar_utils::unpack_entry(&mut entry, &dst)?;
+    }
+
+    // JRuby's launcher is `bin/jruby`, so add the `bin/ruby` that rv runs, like other Ruby
+    // installers do.
+    let bin_dir = rubies_dir.join(format!("jruby-{version}")).join("bin");
+    #[cfg(unix)]
+    if !bin_dir.join("ruby").exists() {
+        fs_err::os::unix::fs::symlink("jruby", bin_dir.join("ruby"))?;
+    }
+    #[cfg(windows)]
+    if !bin_dir.join("ruby.exe").exists() {
+        fs_err::copy(bin_dir.join("jruby.exe"), bin_dir.join("ruby.exe"))?;
+    }
+    Ok(())
+}
+
+/// Unpack a TruffleRuby standalone, whose files are all in
+/// `truffleruby-community-<version>-<platform>/`, into `truffleruby-<version>/`.
+fn extract_truffleruby(
+    tarball_path: &Utf8Path,
+    rubies_dir: &Utf8Path,
+    version: &str,
+) -> Result<()> {
+    let ruby_dir = rubies_dir.join(format!("truffleruby-{version}"));
+    let tarball = fs_err::File::open(tarball_path)?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(tarball));
+    for e in archive.entries()? {
+        let mut entry = e?;
+        let entry_path = entry.path()?;
+        let mut path = entry_path.components();
+        path.next();
+
+        let dst = ruby_dir.as_std_path().join(path.as_path());
+        if let Some(parent) = dst.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        crate::tar_utils::unpack_entry(&mut entry, &dst)?;
+    }
+
+    // Older standalones build their OpenSSL and psych extensions against the host's libraries
+    // once they're unpacked, like ruby-build does.
+    let hook = ruby_dir.join("lib/truffle/post_install_hook.sh");
+    if hook.exists() {
+        debug!("Running TruffleRuby's post-install hook {hook}");
+        let status = std::process::Command::new(&hook)
+            .current_dir(&ruby_dir)
+            .status()?;
+        if !status.success() {
+            return Err(Error::PostInstallHookFailed(status));
+        }
+    }
+    Ok(())
+}
+
 fn extract_zip(zip_path: &Utf8Path, rubies_dir: &Utf8Path, version: &str) -> Result<()> {
     let file = fs_err::File::open(zip_path)?;
     let mut archive = zip::ZipArchive::new(file)?;
@@ -506,6 +908,44 @@ mod tests {
             "https://github.com/oneclick/rubyinstaller2/releases/download/rubyinstaller-head/rubyinstaller-head-x64.7z"
         );
     }
+    #[test]
+    fn test_jruby_url() {
+        assert_eq!(
+            jruby_url("10.0.0.0"),
+            "https://repo1.maven.org/maven2/org/jruby/jruby-dist/10.0.0.0/jruby-dist-10.0.0.0-bin.tar.gz"
+        );
+    }
+
+    #[test]
+    fn test_truffleruby_url() {
+        let host = HostPlatform::from_target_triple("x86_64-unknown-linux-gnu").unwrap();
+        assert_eq!(
+            truffleruby_url("24.2.1", &host).unwrap(),
+            "https://github.com/truffleruby/truffleruby/releases/download/graal-24.2.1/truffleruby-community-24.2.1-linux-amd64.tar.gz"
+        );
+
+        let host = HostPlatform::from_target_triple("aarch64-apple-darwin").unwrap();
+        assert_eq!(
+            truffleruby_url("24.2.1", &host).unwrap(),
+            "https://github.com/truffleruby/truffleruby/releases/download/graal-24.2.1/truffleruby-community-24.2.1-macos-aarch64.tar.gz"
+        );
+
+        let host = HostPlatform::from_target_triple("x86_64-unknown-linux-musl").unwrap();
+        assert!(matches!(
+            truffleruby_url("24.2.1", &host),
+            Err(Error::NoBuildForPlatform { .. })
+        ));
+    }
+
+    #[test]
+    fn test_display_version() {
+        assert_eq!(display_version(&RubyEngine::Ruby, "3.4.5"), "3.4.5");
+        assert_eq!(
+            display_version(&RubyEngine::JRuby, "10.0.0.0"),
+            "jruby-10.0.0.0"
+        );
+    }
+
     #[test]
     fn test_extract_zip_creates_correct_structure() {
         let temp_dir = TempDir::new().unwrap();
@@ -566,8 +1006,49 @@ mod tests {
         let rubies_path = Utf8Path::from_path(rubies_dir.path()).unwrap();
         let zip_utf8_path = Utf8Path::from_path(zip_path.path()).unwrap();
 
-        let result = extract_ruby_archive(zip_utf8_path, rubies_path, "3.4.1");
-        assert!(result.is_ok());
+        // The zip was unpacked, but there's no Ruby in it, so nothing is installed.
+        let result = extract_ruby_archive(zip_utf8_path, rubies_path, &RubyEngine::Ruby, "3.4.1");
+        assert!(matches!(result, Err(Error::InvalidRuby { .. })));
+        assert_eq!(std::fs::read_dir(rubies_dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_replace_dir_swaps_out_existing_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = Utf8Path::from_path(temp_dir.path()).unwrap();
+        let trash = root.join("staging");
+        let new = trash.join("ruby-3.4.1");
+        let dest = root.join("ruby-3.4.1");
+        fs_err::create_dir_all(&new).unwrap();
+        fs_err::write(new.join("version"), "new").unwrap();
+        fs_err::create_dir_all(&dest).unwrap();
+        fs_err::write(dest.join("version"), "old").unwrap();
+
+        replace_dir(&new, &dest, &trash).unwrap();
+
+        assert_eq!(fs_err::read_to_string(dest.join("version")).unwrap(), "new");
+        assert_eq!(
+            fs_err::read_to_string(trash.join("old/version")).unwrap(),
+            "old"
+        );
+    }
+
+    #[test]
+    fn test_digest_from_sums() {
+        let sums = indoc::indoc! {"
+            ABC123  ruby-3.4.5.arm64_sonoma.tar.gz
+            def456 *ruby-3.4.5.x86_64_linux.tar.gz
+        "};
+
+        assert_eq!(
+            digest_from_sums(sums, "ruby-3.4.5.arm64_sonoma.tar.gz").as_deref(),
+            Some("abc123")
+        );
+        assert_eq!(
+            digest_from_sums(sums, "ruby-3.4.5.x86_64_linux.tar.gz").as_deref(),
+            Some("def456")
+        );
+        assert_eq!(digest_from_sums(sums, "ruby-3.4.5.tar.gz"), None);
     }
 
     #[test]
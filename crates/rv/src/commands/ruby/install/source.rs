@@ -0,0 +1,306 @@
+use anstream::println;
+use camino::{Utf8Path, Utf8PathBuf};
+use indicatif::ProgressStyle;
+use owo_colors::OwoColorize;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use tracing::{debug, info_span};
+use tracing_indicatif::span_ext::IndicatifSpanExt;
+
+use rv_platform::HostPlatform;
+
+use super::{
+    Error, Result, archive_cache_path, download_ruby_archive, fetch_checksum_file,
+    valid_archive_exists,
+};
+use crate::checksum::sha256_of;
+use crate::config::Config;
+use crate::progress::WorkProgress;
+
+/// Builds Ruby `version` from its source release, and returns where the built Ruby is cached as
+/// a tarball. Its files are in `ruby-<version>/`, like in the precompiled archives, so it's
+/// installed the same way. A Ruby that was built before is reused, rather than built again.
+pub(super) async fn build_tarball(
+    config: &Config,
+    version: &str,
+    progress: &WorkProgress,
+) -> Result<Utf8PathBuf> {
+    let host = HostPlatform::current()?;
+    if host.is_windows() {
+        return Err(Error::SourceBuildUnsupported(host.target_triple()));
+    }
+
+    let url = source_url(config, version);
+    let configure_opts = configure_opts();
+    let built_path = built_tarball_path(config, &url, &host, &configure_opts);
+    if valid_archive_exists(&built_path) {
+        println!(
+            "Ruby {} was already built from source, reusing {}",
+            version.cyan(),
+            built_path.cyan()
+        );
+        return Ok(built_path);
+    }
+
+    let source_path = archive_cache_path(config, &url, "tar.gz");
+    fs_err::create_dir_all(source_path.parent().unwrap())?;
+    if valid_archive_exists(&source_path) {
+        debug!("Reusing the source of Ruby {version} at {source_path}");
+    } else if config.offline {
+        return Err(Error::NotCached {
+            version: version.to_owned(),
+            path: source_path,
+        });
+    } else {
+        download_ruby_archive(config, &url, &source_path, version, progress, "tar.gz").await?;
+    }
+    verify_source(config, &url, &source_path).await?;
+
+    let log_path = built_path.with_file_name(format!("ruby-{version}.log"));
+    fs_err::create_dir_all(built_path.parent().unwrap())?;
+    build(
+        &source_path,
+        version,
+        &configure_opts,
+        &built_path,
+        &log_path,
+    )?;
+
+    Ok(built_path)
+}
+
+/// Where to download the source release of Ruby `version` from, e.g.
+/// `https://cache.ruby-lang.org/pub/ruby/3.4/ruby-3.4.5.tar.gz`.
+fn source_url(config: &Config, version: &str) -> String {
+    let mirror = config.rv_settings.ruby_source_mirror();
+    let minor_version = version.splitn(3, '.').take(2).collect::<Vec<_>>().join(".");
+
+    format!("{mirror}/{minor_version}/ruby-{version}.tar.gz")
+}
+
+/// Extra flags for `./configure`, from `RUBY_CONFIGURE_OPTS` like ruby-build.
+fn configure_opts() -> Vec<String> {
+    std::env::var("RUBY_CONFIGURE_OPTS")
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Where the Ruby built from the source at `url` is cached. Builds for other hosts, or with other
+/// flags, are cached separately.
+fn built_tarball_path(
+    config: &Config,
+    url: &str,
+    host: &HostPlatform,
+    configure_opts: &[String],
+) -> Utf8PathBuf {
+    let cflags = std::env::var("CFLAGS").unwrap_or_default();
+    let cache_key = rv_cache::cache_digest(format!(
+        "{url}-{}-{}-{cflags}",
+        host.target_triple(),
+        configure_opts.join(" ")
+    ));
+    config
+        .cache
+        .shard(rv_cache::CacheBucket::Ruby, "builds")
+        .into_path_buf()
+        .join(format!("{cache_key}.tar.gz"))
+}
+
+/// Check the source release at `source_path`, downloaded from `url`, against the SHA-256 digest
+/// in the mirror's `index.txt`. Unlike the precompiled archives, it has to be verified, since it's
+/// about to be run. Offline, only a source release that was verified before is trusted.
+async fn verify_source(config: &Config, url: &str, source_path: &Utf8Path) -> Result<()> {
+    let unverified = || Error::UnverifiedSource {
+        url: url.to_owned(),
+    };
+    let verified_path = verified_digest_path(source_path);
+    if config.offline {
+        let verified = fs_err::read_to_string(&verified_path).unwrap_or_default();
+        if verified.is_empty() || verified != sha256_of(source_path)? {
+            return Err(unverified());
+        }
+        debug!("{source_path} was verified against the digest published for {url} before");
+        return Ok(());
+    }
+
+    let index_url = format!("{}/index.txt", config.rv_settings.ruby_source_mirror());
+    let index = fetch_checksum_file(config, &index_url).await?;
+    let file_name = url.rsplit('/').next().unwrap_or(url);
+    let expected = index
+        .and_then(|index| digest_from_index(&index, file_name))
+        .ok_or_else(unverified)?;
+
+    let actual = sha256_of(source_path)?;
+    if actual != expected {
+        fs_err::remove_file(source_path)?;
+        return Err(Error::ChecksumMismatch {
+            path: source_path.to_owned(),
+            expected,
+            actual,
+        });
+    }
+    rv_cache::write_atomic(&verified_path, &actual)?;
+    debug!("Verified {source_path} against the SHA-256 digest published for {url}");
+    Ok(())
+}
+
+/// Where the digest of the source release at `source_path` is recorded once it's verified, so it
+/// can be trusted offline.
+fn verified_digest_path(source_path: &Utf8Path) -> Utf8PathBuf {
+    Utf8PathBuf::from(format!("{source_path}.sha256"))
+}
+
+/// Find the SHA-256 digest of `file_name` in a Ruby mirror's `index.txt`. It's a tab-separated
+/// table, with a header row naming its columns, e.g. `name`, `url`, `sha1`, `sha256` and `sha512`.
+fn digest_from_index(index: &str, file_name: &str) -> Option<String> {
+    let mut rows = index
+        .lines()
+        .map(|line| line.split('\t').collect::<Vec<_>>());
+    let column = rows.next()?.iter().position(|name| *name == "sha256")?;
+    let row = rows.find(|row| row.first() == Some(&file_name))?;
+    Some(row.get(column)?.to_ascii_lowercase())
+}
+
+/// Configure, make and install the Ruby source at `source_path` into a relocatable tree, and
+/// pack it into a tarball at `built_path`. Everything the build prints goes to `log_path`.
+fn build(
+    source_path: &Utf8Path,
+    version: &str,
+    configure_opts: &[String],
+    built_path: &Utf8Path,
+    log_path: &Utf8Path,
+) -> Result<()> {
+    let span = info_span!("Building Ruby", version);
+    span.pb_set_style(&ProgressStyle::with_template("{spinner:.green} {span_name}").unwrap());
+    let _guard = span.enter();
+
+    println!(
+        "Building Ruby {} from source, see {} for the build log",
+        version.cyan(),
+        log_path.cyan()
+    );
+
+    let build_dir = camino_tempfile::Builder::new()
+        .prefix(".rv-build-")
+        .tempdir_in(built_path.parent().unwrap())?;
+    let source = fs_err::File::open(source_path)?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(source));
+    crate::tar_utils::unpack_tar(&mut archive, build_dir.path().as_std_path())?;
+
+    let source_dir = build_dir.path().join(format!("ruby-{version}"));
+    let ruby_dir = build_dir
+        .path()
+        .join("prefix")
+        .join(format!("ruby-{version}"));
+    let mut log = fs_err::File::create(log_path)?;
+
+    // Load-relative, so that the Ruby still works once it's moved into the rubies dir.
+    let mut configure_args = vec![
+        format!("--prefix={ruby_dir}"),
+        "--enable-shared".to_owned(),
+        "--enable-load-relative".to_owned(),
+        "--disable-install-doc".to_owned(),
+    ];
+    configure_args.extend_from_slice(configure_opts);
+    let jobs = std::thread::available_parallelism().map_or(1, |jobs| jobs.get());
+
+    run_step(
+        &mut log,
+        log_path,
+        &source_dir,
+        "./configure",
+        &configure_args,
+    )?;
+    run_step(
+        &mut log,
+        log_path,
+        &source_dir,
+        "make",
+        &[format!("-j{jobs}")],
+    )?;
+    run_step(
+        &mut log,
+        log_path,
+        &source_dir,
+        "make",
+        &["install".to_owned()],
+    )?;
+
+    let built = camino_tempfile::NamedUtf8TempFile::new_in(built_path.parent().unwrap())?;
+    let mut tarball = tar::Builder::new(flate2::write::GzEncoder::new(
+        built.as_file(),
+        flate2::Compression::default(),
+    ));
+    tarball.follow_symlinks(false);
+    tarball.append_dir_all(format!("ruby-{version}"), &ruby_dir)?;
+    tarball.into_inner()?.finish()?;
+    built.persist(built_path).map_err(|err| err.error)?;
+
+    Ok(())
+}
+
+/// Run one step of the build in `dir`, adding what it prints to the build log.
+fn run_step(
+    log: &mut fs_err::File,
+    log_path: &Utf8Path,
+    dir: &Utf8Path,
+    program: &str,
+    args: &[String],
+) -> Result<()> {
+    let step = format!("{program} {}", args.join(" "));
+    debug!("Running {step} in {dir}");
+    writeln!(log, "$ {step}")?;
+
+    let status = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stdout(log.file().try_clone()?)
+        .stderr(log.file().try_clone()?)
+        .status()?;
+    if !status.success() {
+        return Err(Error::SourceBuildFailed {
+            step,
+            log: log_path.to_owned(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digest_from_index() {
+        let index = "name\turl\tsha1\tsha256\tsha512\n\
+            ruby-3.4.4.tar.gz\thttps://cache.ruby-lang.org/pub/ruby/3.4/ruby-3.4.4.tar.gz\taaa\tBBB\tccc\n\
+            ruby-3.4.5.tar.gz\thttps://cache.ruby-lang.org/pub/ruby/3.4/ruby-3.4.5.tar.gz\tddd\teee\tfff\n";
+
+        assert_eq!(
+            digest_from_index(index, "ruby-3.4.4.tar.gz").as_deref(),
+            Some("bbb")
+        );
+        assert_eq!(
+            digest_from_index(index, "ruby-3.4.5.tar.gz").as_deref(),
+            Some("eee")
+        );
+        assert_eq!(digest_from_index(index, "ruby-3.4.5.zip"), None);
+        assert_eq!(digest_from_index("", "ruby-3.4.5.tar.gz"), None);
+    }
+
+    #[test]
+    fn test_source_url() {
+        let config = Config::new_dummy();
+        assert_eq!(
+            source_url(&config, "3.4.5"),
+            "https://cache.ruby-lang.org/pub/ruby/3.4/ruby-3.4.5.tar.gz"
+        );
+        assert_eq!(
+            source_url(&config, "3.5.0-preview1"),
+            "https://cache.ruby-lang.org/pub/ruby/3.5/ruby-3.5.0-preview1.tar.gz"
+        );
+    }
+}
@@ -52,14 +52,14 @@ pub(crate) enum Program {
 pub(crate) struct Invocation {
     pub program: Program,
 
-    pub env: Vec<(&'static str, String)>,
+    pub env: Vec<(String, String)>,
 }
 
 impl Invocation {
     pub fn ruby(env: Vec<(&'static str, String)>) -> Self {
         Self {
             program: Program::Ruby,
-            env,
+            env: owned_env(env),
         }
     }
 
@@ -69,9 +69,21 @@ impl Invocation {
                 executable_path: executable.into(),
                 extra_paths: vec![],
             },
-            env,
+            env: owned_env(env),
         }
     }
+
+    /// Sets more environment variables, whose names aren't known ahead of time.
+    pub fn with_env(mut self, env: impl IntoIterator<Item = (String, String)>) -> Self {
+        self.env.extend(env);
+        self
+    }
+}
+
+fn owned_env(env: Vec<(&'static str, String)>) -> Vec<(String, String)> {
+    env.into_iter()
+        .map(|(key, val)| (key.to_owned(), val))
+        .collect()
 }
 
 pub(crate) async fn run(global_args: &GlobalArgs, args: RunArgs) -> Result<()> {
@@ -160,6 +172,7 @@ pub(crate) async fn run_command(
             Some(request),
             tarball_path,
             false,
+            false,
         )
         .await?
     };
@@ -26,6 +26,10 @@ pub enum ToolCommand {
         /// What gem server to use.
         #[arg(long, default_value = "https://gem.coop/")]
         gem_server: String,
+        /// Another gem server to look for the gem's dependencies on. Can be given several
+        /// times. A dependency found on more than one server is an error.
+        #[arg(long = "source", value_name = "URL")]
+        sources: Vec<String>,
         /// If true, and the tool is already installed, reinstall it.
         /// Otherwise, skip installing if the tool was already installed.
         #[arg(long, short)]
@@ -90,8 +94,9 @@ pub(crate) async fn tool(global_args: &GlobalArgs, tool_args: ToolArgs) -> Resul
         ToolCommand::Install {
             gem,
             gem_server,
+            sources,
             force,
-        } => install::install(global_args, gem, gem_server, force)
+        } => install::install(global_args, gem, gem_server, sources, force)
             .await
             .map(|_| ())?,
         ToolCommand::List { format } => list::list(global_args, format)?,
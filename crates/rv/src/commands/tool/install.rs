@@ -1,9 +1,9 @@
 use std::fs;
 
 use owo_colors::OwoColorize;
-use reqwest::StatusCode;
 use rv_gem_types::ReleaseTuple;
-use rv_lockfile::datatypes::GemfileDotLock;
+use rv_lockfile::datatypes::{Checksum, GemfileDotLock, Spec};
+use rv_ruby::version::RubyVersion;
 use rv_version::Version;
 use tracing::debug;
 use url::Url;
@@ -12,7 +12,7 @@ use crate::{
     GlobalArgs,
     commands::{clean_install::InstallStats, tool::Installed},
     config::Config,
-    gemserver::{self, GemName, GemRelease, Gemserver},
+    gemserver::{self, GemName, GemRelease, sources::GemSources},
 };
 
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
@@ -55,78 +55,18 @@ pub(crate) async fn install(
     global_args: &GlobalArgs,
     gem: GemName,
     gem_server: String,
+    extra_sources: Vec<String>,
     force: bool,
 ) -> Result<Installed> {
-    let config = &Config::new(global_args, None)?;
+    let config = &Config::with_settings(global_args, None)?;
 
     config.self_update_if_needed().await;
 
-    // Check if 'gem' is in 'gem@version' format.
-    // If `gem_version` is None, it means "latest". Otherwise it's a specific version.
-    let (gem_name, gem_version) = if let Some((name, gem_version)) = gem.split_once('@') {
-        let gem_version = if gem_version == "latest" {
-            None
-        } else {
-            // You don't have to give a version,
-            // but if you give one, it has to parse!
-            Some(gem_version.parse()?)
-        };
-        (name.to_owned(), gem_version)
-    } else {
-        (gem, None)
-    };
-
-    let gem_server: Url = gem_server.parse().map_err(|_| Error::BadUrl(gem_server))?;
-
-    let mut gemserver = Gemserver::new(config, gem_server)?;
-
-    // Look up the gem to install.
-    let releases_resp = gemserver
-        .get_releases_for_gem(&gem_name)
-        .await
-        .map_err(|e| match e {
-            // If the HTTP error was 404, then return a nice error explaining that the gem
-            // wasn't found.
-            gemserver::Error::Reqwest(e) if e.status() == Some(StatusCode::NOT_FOUND) => {
-                Error::NotFound {
-                    gem_name: gem_name.to_owned(),
-                    server: gemserver.url.to_string(),
-                }
-            }
-            // Otherwise, keep the error as-is.
-            other => Error::from(other),
-        })?;
-
-    let releases = gemserver::parse_release_from_body(&releases_resp)?;
-    debug!("Found {} releases for the gem {}", releases.len(), gem_name);
-    if releases.is_empty() {
-        return Err(Error::NoReleasesPublished);
-    }
-
-    let release_to_install = match gem_version {
-        Some(user_choice) => releases
-            .iter()
-            .filter(|gem_release| gem_release.version() == &user_choice)
-            .max_by(|x, y| x.version_platform().cmp(y.version_platform()))
-            .map_or_else(
-                || Err(Error::NoVersionFound(user_choice)),
-                |v| Ok(v.to_owned()),
-            )?,
-        _ => releases
-            .iter()
-            .max_by(|x, y| x.version_platform().cmp(y.version_platform()))
-            .map_or_else(|| Err(Error::NoReleasesPublished), |v| Ok(v.to_owned()))?,
-    };
-
-    debug!("Selected {} {}", gem_name, release_to_install.full_name());
-
+    let tool = ToolRelease::find(config, gem, gem_server, extra_sources).await?;
+    let gem_name = tool.name.clone();
+    let release_to_install = tool.release.clone();
     let target_version = release_to_install.version_platform();
 
-    gemserver.gems_to_deps.insert(
-        gem_name.clone(),
-        [(target_version.clone(), release_to_install.clone())].into(),
-    );
-
     // Check if the tool was already installed.
     let install_path = super::tool_dir_for(&gem_name, &target_version.to_string());
     let already_installed = install_path.exists();
@@ -147,33 +87,8 @@ pub(crate) async fn install(
         }
     }
 
-    let ruby_to_use = config
-        .best_ruby_matching_requirement(&release_to_install.metadata.ruby)
-        .await?;
-    debug!("Selected Ruby {ruby_to_use} for this gem");
-
-    gemserver
-        .add_transitive_deps(&release_to_install, &ruby_to_use)
-        .await?;
-
-    // OK, now we know all transitive dependencies, and have a dependency graph.
-    // Now, translate the dependency constraint list into a PubGrub system, and resolve
-    // (i.e. figure out which version of every gem will be used.)
-    debug!("Resolving all dependencies via PubGrub");
-    let versions_needed = crate::resolver::solve(
-        gem_name.clone(),
-        release_to_install.clone(),
-        gemserver.gems_to_deps,
-    )
-    .map_err(|e| Error::CouldNotChooseVersion(e.to_string()))?;
-    debug!("All dependencies resolved");
-
     // Make a Gemfile.lock in-memory, install it via `rv ci`.
-    let lockfile_builder = LockfileBuilder {
-        gemserver_remote: gemserver.url.to_string(),
-        versions_needed,
-    };
-    let lockfile = lockfile_builder.lockfile();
+    let (ruby_to_use, lockfile) = tool.resolve(config).await?;
 
     let result = crate::commands::clean_install::install_tool_lockfile(
         global_args,
@@ -212,56 +127,155 @@ pub(crate) async fn install(
     })
 }
 
-/// Owns the information needed to create a lockfile.
-/// Currently the lockfile has to borrow from something, it does not
-/// actually hold any owned data (strings). It just views data
-/// from somewhere else (e.g. a file on disk, a network buffer, etc).
-///
-/// When building a lockfile from a resolved gem list, there's no actual lockfile
-/// on disk or anything, so this holds the data (e.g. strings) that the lockfile views.
-struct LockfileBuilder {
-    versions_needed: Vec<(ReleaseTuple, GemRelease)>,
-    gemserver_remote: String,
+/// The release of a tool gem that was asked for, and where to look for its dependencies.
+pub(crate) struct ToolRelease {
+    pub name: GemName,
+    pub release: GemRelease,
+    /// The server the gem came from.
+    server: Url,
+    sources: GemSources,
 }
 
-impl LockfileBuilder {
-    /// Create an in-memory Gemfile.lock that views/borrows its data from this builder.
-    pub fn lockfile(&self) -> GemfileDotLock<'_> {
-        let mut lockfile = rv_lockfile::datatypes::GemfileDotLock::default();
-        let mut gem_section = rv_lockfile::datatypes::GemSection {
-            remote: Some(&self.gemserver_remote),
-            specs: Vec::new(),
+impl ToolRelease {
+    /// Look up `gem`, which is either `gem@version` or just the gem's name for its latest
+    /// version, on `gem_server`. Its dependencies can come from there, or from any of the
+    /// `extra_sources`.
+    pub(crate) async fn find(
+        config: &Config,
+        gem: GemName,
+        gem_server: String,
+        extra_sources: Vec<String>,
+    ) -> Result<Self> {
+        // Check if 'gem' is in 'gem@version' format.
+        // If `gem_version` is None, it means "latest". Otherwise it's a specific version.
+        let (gem_name, gem_version) = if let Some((name, gem_version)) = gem.split_once('@') {
+            let gem_version = if gem_version == "latest" {
+                None
+            } else {
+                // You don't have to give a version,
+                // but if you give one, it has to parse!
+                Some(gem_version.parse()?)
+            };
+            (name.to_owned(), gem_version)
+        } else {
+            (gem, None)
         };
-        let mut checksums = vec![];
-        for (release_tuple, gem_release) in &self.versions_needed {
-            let spec = Self::spec_for_gem_dep(release_tuple);
-            gem_section.specs.push(spec);
-            let checksum = Self::checksum_for_spec(release_tuple, gem_release);
-            checksums.push(checksum);
+
+        let gem_server: Url = gem_server.parse().map_err(|_| Error::BadUrl(gem_server))?;
+
+        // The gem itself comes from the gem server. Its dependencies can come from there, or from
+        // any of the extra sources.
+        let mut sources = GemSources::default();
+        sources.pin(config, &gem_name, gem_server.clone()).await?;
+        for source in extra_sources {
+            let url: Url = source.parse().map_err(|_| Error::BadUrl(source))?;
+            sources.add_global(config, url).await?;
         }
 
-        lockfile.gem.push(gem_section);
-        lockfile.checksums = Some(checksums);
-        lockfile
+        // Look up the gem to install.
+        let (gemserver, releases) =
+            sources
+                .releases_for_gem(&gem_name)
+                .await
+                .map_err(|e| match e {
+                    // If the gem server doesn't have the gem, then return a nice error
+                    // explaining that the gem wasn't found.
+                    gemserver::Error::GemNotFound { sources, .. } => Error::NotFound {
+                        gem_name: gem_name.to_owned(),
+                        server: sources.join(", "),
+                    },
+                    // Otherwise, keep the error as-is.
+                    other => Error::from(other),
+                })?;
+        let gem_server = gemserver.url.clone();
+
+        debug!("Found {} releases for the gem {}", releases.len(), gem_name);
+        if releases.is_empty() {
+            return Err(Error::NoReleasesPublished);
+        }
+
+        let release = match gem_version {
+            Some(user_choice) => releases
+                .iter()
+                .filter(|gem_release| gem_release.version() == &user_choice)
+                .max_by(|x, y| x.version_platform().cmp(y.version_platform()))
+                .map_or_else(
+                    || Err(Error::NoVersionFound(user_choice)),
+                    |v| Ok(v.to_owned()),
+                )?,
+            _ => releases
+                .iter()
+                .max_by(|x, y| x.version_platform().cmp(y.version_platform()))
+                .map_or_else(|| Err(Error::NoReleasesPublished), |v| Ok(v.to_owned()))?,
+        };
+
+        debug!("Selected {} {}", gem_name, release.full_name());
+
+        sources.insert(gem_name.clone(), &gem_server, vec![release.clone()]);
+
+        Ok(Self {
+            name: gem_name,
+            release,
+            server: gem_server,
+            sources,
+        })
     }
 
-    fn spec_for_gem_dep(release_tuple: &ReleaseTuple) -> rv_lockfile::datatypes::Spec {
-        rv_lockfile::datatypes::Spec {
+    /// Choose the Ruby to run the tool with, and resolve every gem it needs into an in-memory
+    /// Gemfile.lock.
+    pub(crate) async fn resolve(
+        mut self,
+        config: &Config,
+    ) -> Result<(RubyVersion, GemfileDotLock<'static>)> {
+        let ruby_to_use = config
+            .best_ruby_matching_requirement(&self.release.metadata.ruby)
+            .await?;
+        debug!("Selected Ruby {ruby_to_use} for this gem");
+
+        self.sources
+            .add_transitive_deps(&self.release, Some(&self.server), &ruby_to_use)
+            .await?;
+
+        // OK, now we know all transitive dependencies, and have a dependency graph.
+        // Now, translate the dependency constraint list into a PubGrub system, and resolve
+        // (i.e. figure out which version of every gem will be used.)
+        debug!("Resolving all dependencies via PubGrub");
+        let versions_needed = crate::resolver::solve(
+            self.name.clone(),
+            self.release.clone(),
+            std::mem::take(&mut self.sources.gems_to_deps),
+        )
+        .map_err(|e| Error::CouldNotChooseVersion(e.to_string()))?;
+        debug!("All dependencies resolved");
+
+        Ok((ruby_to_use, tool_lockfile(&self.sources, versions_needed)))
+    }
+}
+
+/// Create an in-memory Gemfile.lock for the resolved gems, so `rv ci` can install them.
+fn tool_lockfile(
+    sources: &GemSources,
+    versions_needed: Vec<(ReleaseTuple, GemRelease)>,
+) -> GemfileDotLock<'static> {
+    let mut lockfile = GemfileDotLock::default();
+    for (release_tuple, gem_release) in versions_needed {
+        let remote = sources
+            .source_of(&release_tuple.name)
+            .expect("every resolved gem came from a source")
+            .url
+            .to_string();
+        let spec = Spec {
             // We don't need to know the deps here, we've already resolved all dependencies.
             // A real Gemfile.lock would populate them, but for this command we don't need to.
             deps: Vec::new(),
             release_tuple: release_tuple.clone(),
-        }
-    }
-
-    fn checksum_for_spec<'a>(
-        release_tuple: &ReleaseTuple,
-        gem_release: &GemRelease,
-    ) -> rv_lockfile::datatypes::Checksum<'a> {
-        rv_lockfile::datatypes::Checksum {
-            release_tuple: release_tuple.clone(),
-            algorithm: rv_lockfile::datatypes::ChecksumAlgorithm::SHA256,
-            value: gem_release.metadata.checksum.clone(),
-        }
+        };
+        let checksum = Checksum {
+            release_tuple,
+            algorithm: gem_release.checksum_algorithm(),
+            value: gem_release.metadata.checksum,
+        };
+        lockfile = lockfile.with_gem(remote, spec).with_checksum(checksum);
     }
+    lockfile
 }
@@ -142,6 +142,7 @@ pub(crate) async fn run(
                 global_args,
                 target_gem_version.suffix_of(target_gem_name),
                 gem_server,
+                Vec::new(),
                 false,
             )
             .await?
@@ -173,7 +174,7 @@ pub(crate) async fn run(
             executable_path: file,
             extra_paths: vec![tool_bin_dir.into()],
         },
-        env: vec![("GEM_HOME", gem_home.to_string())],
+        env: vec![("GEM_HOME".to_owned(), gem_home.to_string())],
     };
     crate::commands::run::run_command(
         invocation,
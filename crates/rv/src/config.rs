@@ -15,6 +15,7 @@ use tracing::{debug, error, instrument};
 
 use rv_ruby::{
     RemoteRuby, Ruby,
+    engine::RubyEngine,
     request::{RequestError, RubyRequest, Source},
     version::RubyVersion,
 };
@@ -24,7 +25,9 @@ use rv_gem_types::Requirement;
 use crate::{GlobalArgs, update};
 
 pub mod bundler_settings;
+pub mod credentials;
 pub mod github;
+pub mod mirrors;
 mod ruby_cache;
 mod ruby_fetcher;
 pub mod rv_settings;
@@ -63,6 +66,8 @@ pub struct Config {
     pub requested_ruby: RequestedRuby,
     pub bundler_settings: BundlerSettings,
     pub rv_settings: RvSettings,
+    /// Never touch the network, and only use what's already in the cache.
+    pub offline: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -141,6 +146,7 @@ impl Config {
             requested_ruby,
             bundler_settings,
             rv_settings,
+            offline: global_args.offline,
         })
     }
 
@@ -182,6 +188,7 @@ impl Config {
             requested_ruby: RequestedRuby::Global,
             bundler_settings: BundlerSettings::default(),
             rv_settings: RvSettings::default(),
+            offline: false,
         }
     }
 
@@ -190,8 +197,14 @@ impl Config {
         self.discover_installed_rubies()
     }
 
+    /// Every Ruby that can be installed, of every engine.
     pub async fn remote_rubies(&self) -> Vec<RemoteRuby> {
-        self.discover_remote_rubies().await
+        self.discover_remote_rubies(None).await
+    }
+
+    /// The Rubies of `engine` that can be installed.
+    pub async fn remote_rubies_of(&self, engine: &RubyEngine) -> Vec<RemoteRuby> {
+        self.discover_remote_rubies(Some(engine)).await
     }
 
     pub async fn find_matching_remote_ruby(&self) -> Result<RubyVersion> {
@@ -204,7 +217,11 @@ impl Config {
             Ok(version)
         } else {
             debug!("Fetching available rubies, because user gave an underspecified Ruby range");
-            let remote_rubies = self.remote_rubies().await;
+            let engine = match &requested_range {
+                RubyRequest::Released(request) => request.engine.clone(),
+                RubyRequest::Dev => RubyEngine::Ruby,
+            };
+            let remote_rubies = self.remote_rubies_of(&engine).await;
 
             let matched_ruby = requested_range
                 .find_match_in(&remote_rubies)
@@ -228,7 +245,8 @@ impl Config {
         match requirement.find_match_in(&installed_rubies, false) {
             Some(local_ruby) => Ok(local_ruby.version),
             None => {
-                let remote_rubies = &self.remote_rubies().await;
+                // A Gemfile's `ruby` requirement is for CRuby.
+                let remote_rubies = &self.remote_rubies_of(&RubyEngine::Ruby).await;
 
                 match requirement
                     .find_match_in(remote_rubies, false)
@@ -311,6 +329,11 @@ impl Config {
             env.insert("RUBY_ROOT", ruby.path.to_string());
             env.insert("RUBY_ENGINE", ruby.version.engine.name().into());
             env.insert("RUBY_VERSION", ruby.version.number());
+            // Point JRuby at this install, rather than at a JRUBY_HOME left over from another.
+            // It finds Java itself, through JAVA_HOME or PATH, so that's left alone.
+            if ruby.version.engine == RubyEngine::JRuby {
+                env.insert("JRUBY_HOME", ruby.path.to_string());
+            }
             let gem_home = self.gem_home(ruby);
             paths.insert_before(0, gem_home.join("bin").into());
             gem_paths.insert(0, gem_home.clone());
@@ -425,13 +448,14 @@ impl Default for Env {
 }
 
 impl Env {
-    const ENV_VARS: [&str; 6] = [
+    const ENV_VARS: [&str; 7] = [
         "RUBY_ROOT",
         "RUBY_ENGINE",
         "RUBY_VERSION",
         "RUBYOPT",
         "GEM_HOME",
         "GEM_PATH",
+        "JRUBY_HOME",
     ];
 
     pub fn insert(&mut self, var: &'static str, val: String) {
@@ -104,11 +104,64 @@ impl BundlerSettings {
         None
     }
 
-    pub fn token_for(&self, host: &str) -> Option<String> {
-        let key = format!("BUNDLE_{}", host.to_uppercase().replace('.', "__"));
+    /// Flags for compiling a gem's native extensions, set with `bundle config build.<gem>`.
+    pub fn build_flags(&self, gem_name: &str) -> Option<String> {
+        // Bundler spells `-` as `___` in setting names.
+        let key = format!(
+            "BUNDLE_BUILD__{}",
+            gem_name.to_uppercase().replace('-', "___")
+        );
 
         self.get_string(&key)
     }
+
+    /// The mirror set with `bundle config mirror.<source> <url>`, for a source like
+    /// `https://rubygems.org/`, or `all`.
+    pub fn mirror(&self, source: &str) -> Option<String> {
+        self.get_nested_string(&mirror_key(source))
+    }
+
+    /// How long to wait for the mirror of `source` before using `source` itself, set with
+    /// `bundle config mirror.<source>.fallback_timeout <seconds>`.
+    pub fn mirror_fallback_timeout(&self, source: &str) -> Option<String> {
+        self.get_nested_string(&format!("{}__FALLBACK_TIMEOUT", mirror_key(source)))
+    }
+
+    /// Looks up a setting whose name has Bundler's `__` separators. Names read from the
+    /// environment lose them, e.g. `BUNDLE_MIRROR__GEMS__EXAMPLE__COM` is read as
+    /// `BUNDLE_MIRROR_GEMS_EXAMPLE_COM`, so that spelling is checked too.
+    fn get_nested_string(&self, key: &str) -> Option<String> {
+        self.get_string(key).or_else(|| {
+            let mut env_key = String::with_capacity(key.len());
+            for c in key.chars() {
+                let c = if c == '-' { '_' } else { c };
+                if !(c == '_' && env_key.ends_with('_')) {
+                    env_key.push(c);
+                }
+            }
+            self.get_string(&env_key)
+        })
+    }
+
+    /// Credentials for a gem server, set with `bundle config <host> <token or user:password>`.
+    /// Like Bundler, this also works with a source URL like `https://gems.example.com/private/`
+    /// instead of a host.
+    pub fn token_for(&self, host: &str) -> Option<String> {
+        let key = format!(
+            "BUNDLE_{}",
+            host.to_uppercase().replace('-', "___").replace('.', "__")
+        );
+
+        self.get_nested_string(&key)
+    }
+}
+
+/// Bundler spells `.` as `__` and `-` as `___` in setting names.
+fn mirror_key(source: &str) -> String {
+    format!(
+        "BUNDLE_MIRROR__{}",
+        source.to_uppercase().replace('-', "___").replace('.', "__")
+    )
 }
 
 #[cfg(test)]
@@ -282,6 +335,37 @@ BUNDLE_DEPLOYMENT: true
         assert_eq!(None, bundler_settings.path())
     }
 
+    #[test]
+    fn test_build_flags() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+
+        let local_config_dir = project_dir.join(".bundle");
+        std::fs::create_dir_all(&local_config_dir).unwrap();
+
+        let local_config_content = r#"---
+BUNDLE_BUILD__PG: "--with-pg-config=/usr/local/bin/pg_config"
+BUNDLE_BUILD__SQLITE3___RUBY: "--enable-system-libraries"
+"#;
+
+        std::fs::write(local_config_dir.join("config"), local_config_content)
+            .expect("Failed to write config");
+
+        let bundler_settings = BundlerSettings::new(&home_dir, &project_dir).unwrap();
+
+        assert_eq!(
+            bundler_settings.build_flags("pg").as_deref(),
+            Some("--with-pg-config=/usr/local/bin/pg_config")
+        );
+        assert_eq!(
+            bundler_settings.build_flags("sqlite3-ruby").as_deref(),
+            Some("--enable-system-libraries")
+        );
+        assert_eq!(bundler_settings.build_flags("nokogiri"), None);
+    }
+
     #[test]
     fn test_token_for_from_config_file_only() {
         let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
@@ -0,0 +1,272 @@
+//! Gem source mirrors, e.g. an internal proxy of rubygems.org. Both the compact index and gem
+//! downloads are fetched from the mirror, while lockfiles keep naming the original source.
+
+use std::time::Duration;
+
+use tracing::debug;
+use url::Url;
+
+use super::Config;
+
+/// Bundler's fallback timeout when it's set to `true` rather than a number of seconds.
+const DEFAULT_FALLBACK_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A mirror configured for a gem source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mirror {
+    pub url: Url,
+    /// If set, use the original source when the mirror can't be reached within this time.
+    pub fallback_timeout: Option<Duration>,
+}
+
+impl Mirror {
+    fn new(url: &str, fallback_timeout: Option<Duration>) -> Option<Self> {
+        let url = match Url::parse(url) {
+            Ok(url) => with_trailing_slash(url),
+            Err(err) => {
+                debug!("Ignoring mirror {url}, because it isn't a valid URL: {err}");
+                return None;
+            }
+        };
+        Some(Self {
+            url,
+            fallback_timeout,
+        })
+    }
+
+    /// Can the mirror be connected to within its fallback timeout?
+    async fn is_reachable(&self, timeout: Duration) -> bool {
+        let (Some(host), Some(port)) = (self.url.host_str(), self.url.port_or_known_default())
+        else {
+            return false;
+        };
+        let connect = tokio::net::TcpStream::connect((host, port));
+        matches!(tokio::time::timeout(timeout, connect).await, Ok(Ok(_)))
+    }
+}
+
+impl Config {
+    /// The mirror configured for `source`. One set for its URL wins over one for its host,
+    /// which wins over one for `all`, and for each of those rv's settings win over Bundler's.
+    pub fn mirror_for(&self, source: &Url) -> Option<Mirror> {
+        let source = with_trailing_slash(source.clone());
+        let host = source.host_str().unwrap_or_default();
+
+        [source.as_str(), host, "all"].into_iter().find_map(|key| {
+            self.rv_mirror(key)
+                .or_else(|| self.bundler_mirror(key))
+                .filter(|mirror| mirror.url != source)
+        })
+    }
+
+    /// Where to fetch `source`'s gems and index from: its mirror if it has one, unless the
+    /// mirror is unreachable and falling back to the original source is allowed.
+    pub async fn resolve_mirror(&self, source: &Url) -> Url {
+        let Some(mirror) = self.mirror_for(source) else {
+            return source.clone();
+        };
+        if let Some(timeout) = mirror.fallback_timeout
+            && !self.offline
+            && !mirror.is_reachable(timeout).await
+        {
+            debug!(
+                "Mirror {} didn't respond within {timeout:?}, using {source} instead",
+                mirror.url
+            );
+            return source.clone();
+        }
+        debug!("Using mirror {} for {source}", mirror.url);
+        mirror.url
+    }
+
+    fn rv_mirror(&self, key: &str) -> Option<Mirror> {
+        let (_, setting) = self
+            .rv_settings
+            .mirror
+            .iter()
+            .find(|(source, _)| same_source(source, key))?;
+        let fallback_timeout = setting
+            .fallback_timeout
+            .filter(|secs| *secs > 0.0)
+            .map(Duration::from_secs_f64);
+        Mirror::new(&setting.url, fallback_timeout)
+    }
+
+    fn bundler_mirror(&self, key: &str) -> Option<Mirror> {
+        let url = self.bundler_settings.mirror(key)?;
+        let fallback_timeout = self
+            .bundler_settings
+            .mirror_fallback_timeout(key)
+            .and_then(|timeout| parse_fallback_timeout(&timeout));
+        Mirror::new(&url, fallback_timeout)
+    }
+}
+
+/// Does a source as written in the settings refer to `key`, i.e. a normalized source URL,
+/// host, or `all`?
+fn same_source(configured: &str, key: &str) -> bool {
+    match Url::parse(configured) {
+        Ok(url) if url.has_host() => with_trailing_slash(url).as_str() == key,
+        _ => configured.eq_ignore_ascii_case(key),
+    }
+}
+
+/// Bundler's `mirror.<source>.fallback_timeout` is either `true` or a number of seconds.
+fn parse_fallback_timeout(value: &str) -> Option<Duration> {
+    match value {
+        "true" => Some(DEFAULT_FALLBACK_TIMEOUT),
+        "false" => None,
+        secs => secs
+            .parse::<f64>()
+            .ok()
+            .filter(|secs| *secs > 0.0)
+            .map(Duration::from_secs_f64),
+    }
+}
+
+/// Sources are compared, and joined with paths, with a trailing slash, like Bundler does.
+fn with_trailing_slash(mut url: Url) -> Url {
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::rv_settings::MirrorSetting;
+
+    fn config_with_bundler(config_content: &str) -> Config {
+        let temp_dir = camino_tempfile::tempdir().unwrap();
+        let project_dir = temp_dir.path().join("project");
+        std::fs::create_dir_all(project_dir.join(".bundle")).unwrap();
+        std::fs::write(project_dir.join(".bundle/config"), config_content).unwrap();
+
+        let mut config = Config::new_dummy();
+        config.bundler_settings = crate::config::bundler_settings::BundlerSettings::new(
+            &temp_dir.path().join("home"),
+            &project_dir,
+        )
+        .unwrap();
+        config
+    }
+
+    #[test]
+    fn test_bundler_mirror() {
+        let config = config_with_bundler(
+            r#"---
+BUNDLE_MIRROR__HTTPS://RUBYGEMS__ORG/: "https://artifactory.example.com/api/gems/rubygems"
+BUNDLE_MIRROR__HTTPS://RUBYGEMS__ORG/__FALLBACK_TIMEOUT: "true"
+"#,
+        );
+
+        let mirror = config
+            .mirror_for(&Url::parse("https://rubygems.org").unwrap())
+            .unwrap();
+        assert_eq!(
+            mirror.url.as_str(),
+            "https://artifactory.example.com/api/gems/rubygems/"
+        );
+        assert_eq!(mirror.fallback_timeout, Some(DEFAULT_FALLBACK_TIMEOUT));
+
+        assert_eq!(
+            config.mirror_for(&Url::parse("https://gems.example.com/").unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_mirror_all() {
+        let config = config_with_bundler(
+            r#"---
+BUNDLE_MIRROR__ALL: "https://proxy.example.com/"
+"#,
+        );
+
+        let mirror = config
+            .mirror_for(&Url::parse("https://gems.example.com/private/").unwrap())
+            .unwrap();
+        assert_eq!(mirror.url.as_str(), "https://proxy.example.com/");
+        assert_eq!(mirror.fallback_timeout, None);
+
+        // The mirror itself isn't mirrored.
+        assert_eq!(
+            config.mirror_for(&Url::parse("https://proxy.example.com").unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_mirror_precedence() {
+        let mut config = config_with_bundler(
+            r#"---
+BUNDLE_MIRROR__HTTPS://RUBYGEMS__ORG/: "https://bundler-mirror.example.com/"
+"#,
+        );
+        config.rv_settings.mirror.insert(
+            "rubygems.org".to_owned(),
+            MirrorSetting {
+                url: "https://rv-mirror.example.com".to_owned(),
+                fallback_timeout: Some(2.0),
+            },
+        );
+
+        // Bundler's mirror is for the whole URL, which is more specific than rv's for the host.
+        let mirror = config
+            .mirror_for(&Url::parse("https://rubygems.org/").unwrap())
+            .unwrap();
+        assert_eq!(mirror.url.as_str(), "https://bundler-mirror.example.com/");
+
+        config.rv_settings.mirror.insert(
+            "https://rubygems.org".to_owned(),
+            MirrorSetting {
+                url: "https://rv-mirror.example.com".to_owned(),
+                fallback_timeout: Some(2.0),
+            },
+        );
+        let mirror = config
+            .mirror_for(&Url::parse("https://rubygems.org/").unwrap())
+            .unwrap();
+        assert_eq!(mirror.url.as_str(), "https://rv-mirror.example.com/");
+        assert_eq!(mirror.fallback_timeout, Some(Duration::from_secs(2)));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_mirror_falls_back_when_unreachable() {
+        let mut config = Config::new_dummy();
+        // Nothing listens on port 9 (discard) locally.
+        config.rv_settings.mirror.insert(
+            "all".to_owned(),
+            MirrorSetting {
+                url: "http://127.0.0.1:9/".to_owned(),
+                fallback_timeout: Some(0.5),
+            },
+        );
+        let source = Url::parse("https://rubygems.org/").unwrap();
+        assert_eq!(config.resolve_mirror(&source).await, source);
+
+        config
+            .rv_settings
+            .mirror
+            .get_mut("all")
+            .unwrap()
+            .fallback_timeout = None;
+        assert_eq!(
+            config.resolve_mirror(&source).await.as_str(),
+            "http://127.0.0.1:9/"
+        );
+    }
+
+    #[test]
+    fn test_parse_fallback_timeout() {
+        assert_eq!(
+            parse_fallback_timeout("true"),
+            Some(DEFAULT_FALLBACK_TIMEOUT)
+        );
+        assert_eq!(parse_fallback_timeout("false"), None);
+        assert_eq!(parse_fallback_timeout("0"), None);
+        assert_eq!(parse_fallback_timeout("3"), Some(Duration::from_secs(3)));
+    }
+}
@@ -59,14 +59,9 @@ impl Config {
             .cache
             .entry(rv_cache::CacheBucket::Ruby, "interpreters", &cache_key);
 
-        // Ensure cache directory exists
-        if let Some(parent) = cache_entry.path().parent() {
-            fs_err::create_dir_all(parent).into_diagnostic()?;
-        }
-
         // Serialize and write Ruby information to cache
         let json_data = serde_json::to_string(ruby).into_diagnostic()?;
-        fs_err::write(cache_entry.path(), json_data).into_diagnostic()?;
+        rv_cache::write_atomic(cache_entry.path(), json_data).into_diagnostic()?;
 
         Ok(())
     }
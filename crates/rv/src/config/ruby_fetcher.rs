@@ -11,7 +11,12 @@ use serde::{Deserialize, Serialize};
 use tracing::{debug, warn};
 
 use rv_platform::HostPlatform;
-use rv_ruby::{Asset, Release, RemoteRuby, request::RequestError, version::ParseVersionError};
+use rv_ruby::{
+    Asset, Release, RemoteRuby,
+    engine::RubyEngine,
+    request::RequestError,
+    version::{ParseVersionError, RubyVersion},
+};
 
 // Use GitHub's TTL, but don't re-check more than every 60 seconds.
 const MINIMUM_CACHE_TTL: Duration = Duration::from_secs(60);
@@ -28,6 +33,23 @@ static PARSE_MAX_AGE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"max-age=(\d+
 static RUBYINSTALLER_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^rubyinstaller-(.+)-(\d+)-(x64|x86|arm)\.7z$").unwrap());
 
+/// Regex for the JRuby binary distribution in a JRuby release.
+///
+/// Captures: group 1 = JRuby version.
+/// Example: `jruby-dist-10.0.0.0-bin.tar.gz` → version=`10.0.0.0`.
+static JRUBY_DIST_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^jruby-dist-(\d+(?:\.\d+)+)-bin\.tar\.gz$").unwrap());
+
+/// Regex for the TruffleRuby standalones in a TruffleRuby release.
+///
+/// Captures: group 1 = TruffleRuby version, group 2 = platform.
+/// Example: `truffleruby-community-24.2.1-linux-amd64.tar.gz` → version=`24.2.1`,
+/// platform=`linux-amd64`. The `-jvm-` standalones aren't matched, since the native ones start
+/// faster and don't need Java.
+static TRUFFLERUBY_STANDALONE_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^truffleruby-community-(\d+\.\d+\.\d+)-(\w+-\w+)\.tar\.gz$").unwrap()
+});
+
 // Updated struct to hold ETag and calculated expiry time
 #[derive(Serialize, Deserialize, Debug)]
 struct CachedRelease {
@@ -48,6 +70,8 @@ pub enum Error {
     GithubRequest(#[from] reqwest::Error),
     #[error(transparent)]
     ParseVersion(#[from] ParseVersionError),
+    #[error("The list of available Ruby versions isn't cached, so it can't be read offline")]
+    NotCached,
 }
 
 type Result<T> = miette::Result<T, Error>;
@@ -57,7 +81,10 @@ impl Config {
     ///
     /// On Windows, fetches from `oneclick/rubyinstaller2` (one release per Ruby version).
     /// On other platforms, fetches from `spinel-coop/rv-ruby` (all versions in one release).
-    pub async fn discover_remote_rubies(&self) -> Vec<RemoteRuby> {
+    /// Every engine's releases are another GitHub API request, so only `engine`'s are fetched,
+    /// or every engine's if it's None.
+    pub async fn discover_remote_rubies(&self, engine: Option<&RubyEngine>) -> Vec<RemoteRuby> {
+        let wanted = |other: RubyEngine| engine.is_none_or(|engine| *engine == other);
         // Detect host first — this decides which release source to query.
         let host = match HostPlatform::current() {
             Ok(h) => h,
@@ -67,48 +94,123 @@ impl Config {
             }
         };
 
+        let desired_os = host.os();
+        let desired_arch = host.arch();
+
+        let mut rubies: Vec<RemoteRuby> = Vec::new();
+        if wanted(RubyEngine::Ruby) {
+            rubies.extend(
+                self.remote_release(&host)
+                    .await
+                    .assets
+                    .iter()
+                    .filter_map(|asset| ruby_from_asset(asset).ok())
+                    .filter(|ruby| ruby.os == desired_os && ruby.arch == desired_arch),
+            );
+        }
+        // JRuby runs on the JVM, so the same distribution works on every host.
+        if wanted(RubyEngine::JRuby) {
+            rubies.extend(
+                self.jruby_release()
+                    .await
+                    .assets
+                    .iter()
+                    .filter_map(|asset| jruby_from_asset(asset, &host).ok()),
+            );
+        }
+        // TruffleRuby only has standalones for some hosts, so don't even look on the others.
+        if wanted(RubyEngine::TruffleRuby) && host.truffleruby_platform().is_some() {
+            rubies.extend(
+                self.truffleruby_release()
+                    .await
+                    .assets
+                    .iter()
+                    .filter_map(|asset| truffleruby_from_asset(asset).ok())
+                    .filter(|ruby| ruby.os == desired_os && ruby.arch == desired_arch),
+            );
+        }
+        rubies.sort();
+
+        debug!(
+            "Found {} available rubies for platform {}/{}",
+            rubies.len(),
+            desired_os,
+            desired_arch
+        );
+
+        rubies
+    }
+
+    /// The SHA-256 digest, in hex, that the release metadata lists for the Ruby archive at
+    /// `url`. Assets are matched by file name, since `url` may point at the latest release
+    /// rather than its tag.
+    pub async fn ruby_archive_digest(&self, engine: &RubyEngine, url: &str) -> Option<String> {
+        let release = match engine {
+            RubyEngine::JRuby => self.jruby_release().await,
+            RubyEngine::TruffleRuby => self.truffleruby_release().await,
+            _ => self.remote_release(&HostPlatform::current().ok()?).await,
+        };
+        let asset = release
+            .assets
+            .iter()
+            .find(|asset| file_name_of(&asset.browser_download_url) == file_name_of(url))?;
+        let digest = asset.digest.as_deref()?.strip_prefix("sha256:")?;
+        Some(digest.to_ascii_lowercase())
+    }
+
+    /// The release listing every Ruby `host` can install, or a stale copy if it can't be fetched.
+    async fn remote_release(&self, host: &HostPlatform) -> Release {
         let ((fetch_result, url), cache_file) = if host.is_windows() {
             (
-                fetch_rubyinstaller2_rubies(&self.cache).await,
+                fetch_rubyinstaller2_rubies(&self.cache, self.offline).await,
                 "rubyinstaller2.json",
             )
         } else {
             (
-                fetch_available_rubies(&self.cache).await,
+                fetch_available_rubies(&self.cache, self.offline).await,
                 "available_rubies.json",
             )
         };
 
-        let release = match fetch_result {
+        match fetch_result {
             Ok(release) => release,
             Err(e) => {
                 warn!("Could not fetch available Ruby versions: {}", e);
                 stale_cache_fallback(&self.cache, cache_file, &url)
             }
-        };
-
-        let desired_os = host.os();
-        let desired_arch = host.arch();
-
-        let mut rubies: Vec<RemoteRuby> = release
-            .assets
-            .iter()
-            .filter_map(|asset| ruby_from_asset(asset).ok())
-            .filter(|ruby| ruby.os == desired_os && ruby.arch == desired_arch)
-            .collect();
-        rubies.sort();
+        }
+    }
 
-        debug!(
-            "Found {} available rubies for platform {}/{}",
-            rubies.len(),
-            desired_os,
-            desired_arch
-        );
+    /// The release listing every JRuby version, or a stale copy if it can't be fetched.
+    async fn jruby_release(&self) -> Release {
+        let (fetch_result, url) = fetch_jruby_rubies(&self.cache, self.offline).await;
+        match fetch_result {
+            Ok(release) => release,
+            Err(e) => {
+                warn!("Could not fetch available JRuby versions: {}", e);
+                stale_cache_fallback(&self.cache, "jruby.json", &url)
+            }
+        }
+    }
 
-        rubies
+    /// The release listing every TruffleRuby standalone, or a stale copy if it can't be fetched.
+    async fn truffleruby_release(&self) -> Release {
+        let (fetch_result, url) = fetch_truffleruby_rubies(&self.cache, self.offline).await;
+        match fetch_result {
+            Ok(release) => release,
+            Err(e) => {
+                warn!("Could not fetch available TruffleRuby versions: {}", e);
+                stale_cache_fallback(&self.cache, "truffleruby.json", &url)
+            }
+        }
     }
 }
 
+/// The last path segment of `url`.
+fn file_name_of(url: &str) -> &str {
+    url.rsplit('/').next().unwrap_or(url)
+}
+
 fn cache_key_for(url: &str, cache_file: &str) -> String {
     rv_cache::cache_digest(format!("{}-{}", url, cache_file))
 }
@@ -117,7 +219,8 @@ fn url_for(env_var: &str, default_url: &str) -> String {
     std::env::var(env_var).unwrap_or_else(|_| default_url.to_string())
 }
 
-/// Fetches a GitHub releases endpoint with ETag/TTL caching.
+/// Fetches a GitHub releases endpoint with ETag/TTL caching. When `offline`, the cached copy
+/// is used however old it is.
 ///
 /// The `transform` closure converts the raw JSON response body into a `Release`.
 /// For rv-ruby this is identity (response is already a single `Release`).
@@ -127,6 +230,7 @@ async fn fetch_cached_github_release(
     cache_file: &str,
     env_var: &str,
     url: &str,
+    offline: bool,
     transform: impl FnOnce(bytes::Bytes) -> Result<Release>,
 ) -> Result<Release> {
     let client = reqwest::Client::new();
@@ -152,12 +256,15 @@ async fn fetch_cached_github_release(
 
     // 2. If we have fresh cached data, use it immediately.
     if let Some(cache) = &cached_data {
-        if SystemTime::now() < cache.expires_at {
+        if offline || SystemTime::now() < cache.expires_at {
             debug!("Using cached release data from {cache_file}.");
             return Ok(cache.release.clone());
         }
         debug!("Cache {cache_file} is stale, re-validating with server.");
     }
+    if offline {
+        return Err(Error::NotCached);
+    }
 
     // 3. Cache is stale or missing.
     let etag = cached_data.as_ref().and_then(|c| c.etag.clone());
@@ -225,27 +332,74 @@ async fn fetch_cached_github_release(
 }
 
 /// Fetches available rubies from rv-ruby (macOS/Linux).
-async fn fetch_available_rubies(cache: &rv_cache::Cache) -> (Result<Release>, String) {
+async fn fetch_available_rubies(
+    cache: &rv_cache::Cache,
+    offline: bool,
+) -> (Result<Release>, String) {
     let env_var = "RV_LIST_URL";
     let default_url = "https://api.github.com/repos/spinel-coop/rv-ruby/releases/latest";
     let url = url_for(env_var, default_url);
-    let release =
-        fetch_cached_github_release(cache, "available_rubies.json", env_var, &url, |body| {
-            Ok(serde_json::from_slice(&body)?)
-        })
-        .await;
+    let release = fetch_cached_github_release(
+        cache,
+        "available_rubies.json",
+        env_var,
+        &url,
+        offline,
+        |body| Ok(serde_json::from_slice(&body)?),
+    )
+    .await;
     (release, url)
 }
 
 /// Fetches available rubies from RubyInstaller2 (Windows).
-async fn fetch_rubyinstaller2_rubies(cache: &rv_cache::Cache) -> (Result<Release>, String) {
+async fn fetch_rubyinstaller2_rubies(
+    cache: &rv_cache::Cache,
+    offline: bool,
+) -> (Result<Release>, String) {
     let env_var = "RV_WINDOWS_LIST_URL";
     let default_url = "https://api.github.com/repos/oneclick/rubyinstaller2/releases?per_page=100";
     let url = url_for(env_var, default_url);
-    let release =
-        fetch_cached_github_release(cache, "rubyinstaller2.json", env_var, &url, |body| {
+    let release = fetch_cached_github_release(
+        cache,
+        "rubyinstaller2.json",
+        env_var,
+        &url,
+        offline,
+        |body| {
             let releases: Vec<Release> = serde_json::from_slice(&body)?;
             Ok(combine_rubyinstaller2_releases(releases))
+        },
+    )
+    .await;
+    (release, url)
+}
+
+/// Fetches available JRuby versions from the JRuby releases (all platforms).
+async fn fetch_jruby_rubies(cache: &rv_cache::Cache, offline: bool) -> (Result<Release>, String) {
+    let env_var = "RV_JRUBY_LIST_URL";
+    let default_url = "https://api.github.com/repos/jruby/jruby/releases?per_page=100";
+    let url = url_for(env_var, default_url);
+    let release =
+        fetch_cached_github_release(cache, "jruby.json", env_var, &url, offline, |body| {
+            let releases: Vec<Release> = serde_json::from_slice(&body)?;
+            Ok(combine_jruby_releases(releases))
+        })
+        .await;
+    (release, url)
+}
+
+/// Fetches available TruffleRuby versions from the TruffleRuby releases (all platforms).
+async fn fetch_truffleruby_rubies(
+    cache: &rv_cache::Cache,
+    offline: bool,
+) -> (Result<Release>, String) {
+    let env_var = "RV_TRUFFLERUBY_LIST_URL";
+    let default_url = "https://api.github.com/repos/truffleruby/truffleruby/releases?per_page=100";
+    let url = url_for(env_var, default_url);
+    let release =
+        fetch_cached_github_release(cache, "truffleruby.json", env_var, &url, offline, |body| {
+            let releases: Vec<Release> = serde_json::from_slice(&body)?;
+            Ok(combine_truffleruby_releases(releases))
         })
         .await;
     (release, url)
@@ -309,6 +463,7 @@ fn combine_rubyinstaller2_releases(releases: Vec<Release>) -> Release {
                 let normalized_asset = Asset {
                     name: normalized_name,
                     browser_download_url: asset.browser_download_url.clone(),
+                    digest: asset.digest.clone(),
                 };
 
                 match best.entry(key) {
@@ -334,6 +489,44 @@ fn combine_rubyinstaller2_releases(releases: Vec<Release>) -> Release {
     }
 }
 
+/// Collects the binary distribution of every JRuby release into a single synthetic Release.
+///
+/// Each JRuby release has many assets, e.g. sources, installers and `jruby-complete` jars.
+/// Only `jruby-dist-<version>-bin.tar.gz` is kept, which is the one rv installs.
+fn combine_jruby_releases(releases: Vec<Release>) -> Release {
+    let mut assets: Vec<Asset> = releases
+        .into_iter()
+        .flat_map(|release| release.assets)
+        .filter(|asset| JRUBY_DIST_REGEX.is_match(&asset.name))
+        .collect();
+    assets.sort_by(|a, b| a.name.cmp(&b.name));
+    assets.dedup_by(|a, b| a.name == b.name);
+
+    Release {
+        name: "jruby-combined".to_string(),
+        assets,
+    }
+}
+
+/// Collects the standalones of every TruffleRuby release into a single synthetic Release.
+///
+/// Each TruffleRuby release has a standalone per platform, plus their checksums and the JVM
+/// standalones. Only the native `truffleruby-community-<version>-<platform>.tar.gz` are kept.
+fn combine_truffleruby_releases(releases: Vec<Release>) -> Release {
+    let mut assets: Vec<Asset> = releases
+        .into_iter()
+        .flat_map(|release| release.assets)
+        .filter(|asset| TRUFFLERUBY_STANDALONE_REGEX.is_match(&asset.name))
+        .collect();
+    assets.sort_by(|a, b| a.name.cmp(&b.name));
+    assets.dedup_by(|a, b| a.name == b.name);
+
+    Release {
+        name: "truffleruby-combined".to_string(),
+        assets,
+    }
+}
+
 /// Parses the `max-age` value from a `Cache-Control` header.
 fn parse_max_age(header: &str) -> Option<Duration> {
     PARSE_MAX_AGE_REGEX
@@ -360,7 +553,7 @@ fn ruby_from_asset(asset: &Asset) -> Result<RemoteRuby> {
         Some(m) => &asset.name[..m.start() - 1],
         None => asset.name.as_str(),
     };
-    let version: rv_ruby::version::RubyVersion = version_str.parse()?;
+    let version: RubyVersion = version_str.parse()?;
     let display_name = version.to_string();
 
     Ok(RemoteRuby {
@@ -371,10 +564,50 @@ fn ruby_from_asset(asset: &Asset) -> Result<RemoteRuby> {
     })
 }
 
+/// Creates a Rubies info struct for `host` from a JRuby binary distribution asset
+fn jruby_from_asset(asset: &Asset, host: &HostPlatform) -> Result<RemoteRuby> {
+    let version = JRUBY_DIST_REGEX
+        .captures(&asset.name)
+        .map_or(asset.name.as_str(), |caps| caps.get(1).unwrap().as_str());
+    let version: RubyVersion = format!("jruby-{version}").parse()?;
+
+    Ok(RemoteRuby {
+        key: format!("{version}-{}-{}", host.os(), host.arch()),
+        version,
+        arch: host.arch().to_string(),
+        os: host.os().to_string(),
+    })
+}
+
+/// Creates a Rubies info struct from a TruffleRuby standalone asset
+fn truffleruby_from_asset(asset: &Asset) -> Result<RemoteRuby> {
+    let caps = TRUFFLERUBY_STANDALONE_REGEX.captures(&asset.name);
+    let host = caps.as_ref().and_then(|caps| {
+        HostPlatform::all()
+            .iter()
+            .find(|hp| hp.truffleruby_platform() == Some(&caps[2]))
+    });
+    let (os, arch) = match host {
+        Some(hp) => (hp.os(), hp.arch()),
+        None => ("unknown", "unknown"),
+    };
+
+    let version = caps
+        .as_ref()
+        .map_or(asset.name.as_str(), |caps| caps.get(1).unwrap().as_str());
+    let version: RubyVersion = format!("truffleruby-{version}").parse()?;
+
+    Ok(RemoteRuby {
+        key: format!("{version}-{os}-{arch}"),
+        version,
+        arch: arch.to_string(),
+        os: os.to_string(),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rv_ruby::version::RubyVersion;
 
     #[test]
     fn test_parse_cache_header() {
@@ -388,6 +621,12 @@ mod tests {
     fn test_deser_release() {
         let jtxt = fs_err::read_to_string("../../testdata/api.json").unwrap();
         let release: Release = serde_json::from_str(&jtxt).unwrap();
+        assert!(
+            release.assets[0]
+                .digest
+                .as_deref()
+                .is_some_and(|digest| digest.starts_with("sha256:"))
+        );
         let actual = ruby_from_asset(&release.assets[0]).unwrap();
         let expected = RemoteRuby {
             key: "ruby-3.3.0-linux-aarch64".to_owned(),
@@ -421,6 +660,7 @@ mod tests {
             let asset = Asset {
                 name: filename.to_owned(),
                 browser_download_url: format!("https://example.com/{filename}"),
+                digest: None,
             };
             let ruby = ruby_from_asset(&asset).unwrap();
             assert_eq!(ruby.os, expected_os, "Wrong OS for {filename}");
@@ -434,6 +674,7 @@ mod tests {
         let asset = Asset {
             name: "ruby-3.3.0.sparc_solaris.tar.gz".to_owned(),
             browser_download_url: "https://example.com/ruby-3.3.0.sparc_solaris.tar.gz".to_owned(),
+            digest: None,
         };
         let ruby = ruby_from_asset(&asset).unwrap();
         assert_eq!(ruby.os, "unknown");
@@ -445,6 +686,7 @@ mod tests {
         Asset {
             name: name.to_string(),
             browser_download_url: format!("https://github.com/download/{name}"),
+            digest: None,
         }
     }
 
@@ -577,6 +819,80 @@ mod tests {
         assert_eq!(ruby.arch, "aarch64");
     }
 
+    #[test]
+    fn test_combine_jruby_releases() {
+        let releases = vec![
+            make_release(
+                "10.0.0.0",
+                &[
+                    "jruby-dist-10.0.0.0-bin.tar.gz",
+                    "jruby-dist-10.0.0.0-bin.tar.gz.sha256",
+                    "jruby-dist-10.0.0.0-bin.zip",
+                    "jruby-dist-10.0.0.0-src.zip",
+                    "jruby-complete-10.0.0.0.jar",
+                ],
+            ),
+            make_release("9.4.12.0", &["jruby-dist-9.4.12.0-bin.tar.gz"]),
+        ];
+
+        let result = combine_jruby_releases(releases);
+        let names: Vec<&str> = result.assets.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "jruby-dist-10.0.0.0-bin.tar.gz",
+                "jruby-dist-9.4.12.0-bin.tar.gz"
+            ]
+        );
+
+        let host = HostPlatform::from_target_triple("x86_64-unknown-linux-gnu").unwrap();
+        let ruby = jruby_from_asset(&result.assets[0], &host).unwrap();
+        assert_eq!(ruby.version.to_string(), "jruby-10.0.0.0");
+        assert_eq!(ruby.key, "jruby-10.0.0.0-linux-x86_64");
+        assert_eq!(ruby.os, "linux");
+        assert_eq!(ruby.arch, "x86_64");
+    }
+
+    #[test]
+    fn test_combine_truffleruby_releases() {
+        let releases = vec![
+            make_release(
+                "graal-24.2.1",
+                &[
+                    "truffleruby-community-24.2.1-linux-amd64.tar.gz",
+                    "truffleruby-community-24.2.1-linux-amd64.tar.gz.sha256",
+                    "truffleruby-community-24.2.1-macos-aarch64.tar.gz",
+                    "truffleruby-community-jvm-24.2.1-linux-amd64.tar.gz",
+                ],
+            ),
+            make_release(
+                "graal-24.1.2",
+                &["truffleruby-community-24.1.2-linux-amd64.tar.gz"],
+            ),
+        ];
+
+        let result = combine_truffleruby_releases(releases);
+        let names: Vec<&str> = result.assets.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "truffleruby-community-24.1.2-linux-amd64.tar.gz",
+                "truffleruby-community-24.2.1-linux-amd64.tar.gz",
+                "truffleruby-community-24.2.1-macos-aarch64.tar.gz",
+            ]
+        );
+
+        let ruby = truffleruby_from_asset(&result.assets[1]).unwrap();
+        assert_eq!(ruby.version.to_string(), "truffleruby-24.2.1");
+        assert_eq!(ruby.key, "truffleruby-24.2.1-linux-x86_64");
+        assert_eq!(ruby.os, "linux");
+        assert_eq!(ruby.arch, "x86_64");
+
+        let ruby = truffleruby_from_asset(&result.assets[2]).unwrap();
+        assert_eq!(ruby.os, "macos");
+        assert_eq!(ruby.arch, "aarch64");
+    }
+
     #[test]
     fn test_combine_rubyinstaller2_releases_empty() {
         let result = combine_rubyinstaller2_releases(vec![]);
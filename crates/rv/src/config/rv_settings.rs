@@ -3,6 +3,8 @@ use camino::Utf8PathBuf;
 use config::{
     Config as ConfigRs, Environment, File, FileStoredFormat, Format, Map, Value, ValueKind,
 };
+use rv_client::retry::RetryPolicy;
+use std::collections::HashMap;
 
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
@@ -27,6 +29,31 @@ pub struct RvSettings {
 
     #[serde(default = "default_update_mode")]
     pub update_mode: String,
+
+    /// Flags to pass when compiling each gem's native extensions, by gem name.
+    #[serde(default)]
+    pub build: HashMap<String, String>,
+
+    /// How many times to retry a download that failed with a network error, or a server error
+    /// that's likely to go away.
+    pub http_retries: Option<u32>,
+
+    /// How many requests to a gem server can be in flight at once.
+    pub max_concurrent_requests: Option<usize>,
+
+    /// Mirrors to fetch gems from, by the gem source they replace.
+    #[serde(default)]
+    pub mirror: HashMap<String, MirrorSetting>,
+
+    /// Where to download Ruby's source releases from, when building Ruby from source.
+    pub ruby_source_mirror: Option<String>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub struct MirrorSetting {
+    pub url: String,
+    /// Seconds to wait for the mirror before using the original source instead.
+    pub fallback_timeout: Option<f64>,
 }
 
 fn default_update_mode() -> String {
@@ -54,7 +81,15 @@ impl Format for RvSettingsFormat {
             .children()
             .ok_or("Missing children in 'rv' node")?;
 
-        const ALLOWED_KEYS: &[&str] = &["install-path", "update-mode"];
+        const ALLOWED_KEYS: &[&str] = &[
+            "install-path",
+            "update-mode",
+            "build",
+            "http-retries",
+            "max-concurrent-requests",
+            "mirror",
+            "ruby-source-mirror",
+        ];
 
         let mut map = Map::new();
 
@@ -65,6 +100,16 @@ impl Format for RvSettingsFormat {
                 return Err(format!("Invalid key '{}' in rv config", key).into());
             }
 
+            if key == "build" {
+                map.insert(key.to_string(), parse_build_flags(node)?);
+                continue;
+            }
+
+            if key == "mirror" {
+                map.insert(key.to_string(), parse_mirrors(node)?);
+                continue;
+            }
+
             if node.entries().is_empty() {
                 return Err(format!("The key '{}' expects argument(s)", key).into());
             }
@@ -88,6 +133,75 @@ impl Format for RvSettingsFormat {
     }
 }
 
+/// Parses per-gem build flags, written like Bundler's `build.<gem>` settings:
+///
+/// ```kdl
+/// build {
+///   pg "--with-pg-config=/usr/local/bin/pg_config"
+/// }
+/// ```
+fn parse_build_flags(
+    node: &kdl::KdlNode,
+) -> std::result::Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+    let gems = node
+        .children()
+        .ok_or("The key 'build' expects a block of gem names and their flags")?;
+
+    let mut flags = Map::new();
+    for gem in gems.nodes() {
+        let name = gem.name().value();
+        let value = match gem.entry(0).map(|entry| entry.value()) {
+            Some(kdl::KdlValue::String(s)) => s.clone(),
+            _ => return Err(format!("The build flags for '{}' must be a string", name).into()),
+        };
+        flags.insert(name.to_string(), Value::new(None, ValueKind::String(value)));
+    }
+
+    Ok(Value::new(None, ValueKind::Table(flags)))
+}
+
+/// Parses gem source mirrors, written like Bundler's `mirror.<source>` settings:
+///
+/// ```kdl
+/// mirror {
+///   "https://rubygems.org" "https://artifactory.example.com/api/gems/rubygems/" fallback-timeout=2
+/// }
+/// ```
+///
+/// The source can also be just a host, or `all` to mirror every source.
+fn parse_mirrors(
+    node: &kdl::KdlNode,
+) -> std::result::Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+    let sources = node
+        .children()
+        .ok_or("The key 'mirror' expects a block of gem sources and their mirrors")?;
+
+    let mut mirrors = Map::new();
+    for source in sources.nodes() {
+        let name = source.name().value();
+        let mut mirror = Map::new();
+        for entry in source.entries() {
+            let value = match (entry.name().map(|n| n.value()), entry.value()) {
+                (None, kdl::KdlValue::String(url)) => ("url", ValueKind::String(url.clone())),
+                (Some("fallback-timeout"), kdl::KdlValue::Integer(secs)) => {
+                    ("fallback_timeout", ValueKind::Float(*secs as f64))
+                }
+                (Some("fallback-timeout"), kdl::KdlValue::Float(secs)) => {
+                    ("fallback_timeout", ValueKind::Float(*secs))
+                }
+                _ => return Err(format!("Invalid mirror setting for '{}'", name).into()),
+            };
+            mirror.insert(value.0.to_string(), Value::new(None, value.1));
+        }
+        if !mirror.contains_key("url") {
+            return Err(format!("The mirror for '{}' must be a URL", name).into());
+        }
+        mirrors.insert(name.to_string(), Value::new(None, ValueKind::Table(mirror)));
+    }
+
+    Ok(Value::new(None, ValueKind::Table(mirrors)))
+}
+
 impl FileStoredFormat for RvSettingsFormat {
     fn file_extensions(&self) -> &'static [&'static str] {
         &["kdl"]
@@ -184,6 +298,26 @@ impl RvSettings {
         Ok(())
     }
 
+    pub fn retry_policy(&self) -> RetryPolicy {
+        match self.http_retries {
+            Some(retries) => RetryPolicy::default().with_max_retries(retries),
+            None => RetryPolicy::default(),
+        }
+    }
+
+    pub fn max_concurrent_requests(&self) -> usize {
+        self.max_concurrent_requests.unwrap_or(10).max(1)
+    }
+
+    /// Where to download Ruby's source releases from: the configured mirror of
+    /// `https://cache.ruby-lang.org/pub/ruby`, or else that.
+    pub fn ruby_source_mirror(&self) -> &str {
+        self.ruby_source_mirror
+            .as_deref()
+            .unwrap_or("https://cache.ruby-lang.org/pub/ruby")
+            .trim_end_matches('/')
+    }
+
     pub fn install_path_as_utf8pathbuf(&self) -> Option<Utf8PathBuf> {
         self.install_path
             .as_ref()
@@ -236,6 +370,147 @@ rv{
         )
     }
 
+    #[test]
+    fn test_build_flags() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+        std::fs::create_dir_all(&project_dir).unwrap();
+
+        let config_content = r#"
+rv {
+  update-mode "none"
+  build {
+    pg "--with-pg-config=/usr/local/bin/pg_config"
+    nokogiri "--use-system-libraries"
+  }
+}
+"#;
+        std::fs::write(project_dir.join("rv.kdl"), config_content).unwrap();
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+
+        assert_eq!(rv_settings.update_mode, "none");
+        assert_eq!(
+            rv_settings.build.get("pg").map(String::as_str),
+            Some("--with-pg-config=/usr/local/bin/pg_config")
+        );
+        assert_eq!(
+            rv_settings.build.get("nokogiri").map(String::as_str),
+            Some("--use-system-libraries")
+        );
+    }
+
+    #[test]
+    fn test_mirrors() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+        std::fs::create_dir_all(&project_dir).unwrap();
+
+        let config_content = r#"
+rv {
+  mirror {
+    "https://rubygems.org" "https://artifactory.example.com/api/gems/rubygems/" fallback-timeout=2
+    all "https://proxy.example.com/"
+  }
+}
+"#;
+        std::fs::write(project_dir.join("rv.kdl"), config_content).unwrap();
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+
+        assert_eq!(
+            rv_settings.mirror.get("https://rubygems.org"),
+            Some(&MirrorSetting {
+                url: "https://artifactory.example.com/api/gems/rubygems/".to_owned(),
+                fallback_timeout: Some(2.0),
+            })
+        );
+        assert_eq!(
+            rv_settings.mirror.get("all"),
+            Some(&MirrorSetting {
+                url: "https://proxy.example.com/".to_owned(),
+                fallback_timeout: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_http_retries() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+        std::fs::create_dir_all(&project_dir).unwrap();
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+        assert_eq!(rv_settings.retry_policy(), RetryPolicy::default());
+
+        let config_content = r#"
+rv {
+  http-retries 5
+}
+"#;
+        std::fs::write(project_dir.join("rv.kdl"), config_content).unwrap();
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+        assert_eq!(rv_settings.http_retries, Some(5));
+        assert_eq!(rv_settings.retry_policy().max_retries(), 5);
+    }
+
+    #[test]
+    fn test_max_concurrent_requests() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+        std::fs::create_dir_all(&project_dir).unwrap();
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+        assert_eq!(rv_settings.max_concurrent_requests(), 10);
+
+        let config_content = r#"
+rv {
+  max-concurrent-requests 4
+}
+"#;
+        std::fs::write(project_dir.join("rv.kdl"), config_content).unwrap();
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+        assert_eq!(rv_settings.max_concurrent_requests(), 4);
+    }
+
+    #[test]
+    fn test_ruby_source_mirror() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+        std::fs::create_dir_all(&project_dir).unwrap();
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+        assert_eq!(
+            rv_settings.ruby_source_mirror(),
+            "https://cache.ruby-lang.org/pub/ruby"
+        );
+
+        let config_content = r#"
+rv {
+  ruby-source-mirror "https://mirror.example.com/ruby/"
+}
+"#;
+        std::fs::write(project_dir.join("rv.kdl"), config_content).unwrap();
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+        assert_eq!(
+            rv_settings.ruby_source_mirror(),
+            "https://mirror.example.com/ruby"
+        );
+    }
+
     #[test]
     fn test_fallback_to_defaults_when_no_env_vars_and_no_files() {
         let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
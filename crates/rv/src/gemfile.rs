@@ -0,0 +1,1045 @@
+//! A static reader for Gemfiles.
+//!
+//! Gemfiles are Ruby code, and Bundler evaluates them with a full Ruby interpreter.
+//! rv doesn't want to boot Ruby just to find out which gems a project needs, so this module
+//! understands the declarative subset of the Gemfile DSL that nearly every Gemfile sticks to:
+//! `source`, `ruby`, `gem`, and `group`/`platforms`/`source`/`git`/`path` blocks.
+//! Anything that needs real Ruby evaluation (conditionals, `eval_gemfile`, etc.) is reported
+//! as an error pointing at the offending line, rather than being silently ignored.
+
+use std::ops::Range;
+
+use miette::SourceSpan;
+use rv_gem_types::Requirement;
+use rv_ruby::{engine::RubyEngine, version::RubyVersion};
+
+/// The gems (and their sources) declared in a Gemfile.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Gemfile {
+    /// Global `source` lines, in the order they were declared.
+    pub sources: Vec<String>,
+    /// The `ruby` directive, if any.
+    pub ruby: Option<RubyDirective>,
+    /// Every `gem` line, in the order they were declared.
+    pub dependencies: Vec<GemfileDependency>,
+    /// Groups declared with `optional: true`, which are only installed when asked for.
+    pub optional_groups: Vec<String>,
+}
+
+/// What Ruby a Gemfile says the project needs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RubyDirective {
+    /// e.g. `ruby "~> 3.4"`
+    Requirement(Requirement),
+    /// e.g. `ruby file: ".ruby-version"`
+    File(String),
+}
+
+/// A single `gem` line from a Gemfile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GemfileDependency {
+    pub name: String,
+    pub requirement: Requirement,
+    /// Which groups the gem belongs to. Gems outside any group are in the `default` group.
+    pub groups: Vec<String>,
+    /// Which Bundler platforms (e.g. `mri`, `windows`, `jruby`) the gem is restricted to.
+    /// Empty means every platform.
+    pub platforms: Vec<String>,
+    pub source: DependencySource,
+}
+
+/// Where a Gemfile says a gem should come from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DependencySource {
+    /// Whichever global `source` the Gemfile declared.
+    Default,
+    /// A gem server given by a `source` block or the `source:` option.
+    Rubygems(String),
+    /// A git repository given by a `git` block or the `git:`/`github:` options.
+    Git(String),
+    /// A local directory given by a `path` block or the `path:` option.
+    Path(String),
+}
+
+impl GemfileDependency {
+    /// Does this gem apply to the given Ruby on the current OS?
+    /// Follows Bundler's platform names, see
+    /// <https://bundler.io/v2.5/man/gemfile.5.html#PLATFORMS>.
+    pub fn applies_to(&self, ruby: &RubyVersion) -> bool {
+        self.platforms.is_empty()
+            || self
+                .platforms
+                .iter()
+                .any(|platform| platform_applies_to(platform, ruby))
+    }
+}
+
+fn platform_applies_to(platform: &str, ruby: &RubyVersion) -> bool {
+    // Platforms can be suffixed with a Ruby version, e.g. `mri_33` means MRI 3.3.
+    let (name, version) = match platform.rsplit_once('_') {
+        Some((name, version))
+            if !version.is_empty() && version.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            (name, Some(version))
+        }
+        _ => (platform, None),
+    };
+
+    if let Some(version) = version {
+        let (major, minor) = version.split_at(1);
+        if major != ruby.major.to_string() || minor != ruby.minor.to_string() {
+            return false;
+        }
+    }
+
+    let windows = cfg!(windows);
+    match name {
+        "ruby" => !windows && matches!(ruby.engine, RubyEngine::Ruby | RubyEngine::TruffleRuby),
+        "mri" => !windows && ruby.engine == RubyEngine::Ruby,
+        "windows" | "mswin" | "mswin64" | "mingw" | "x64_mingw" => {
+            windows && ruby.engine == RubyEngine::Ruby
+        }
+        "jruby" => ruby.engine == RubyEngine::JRuby,
+        "truffleruby" => ruby.engine == RubyEngine::TruffleRuby,
+        _ => false,
+    }
+}
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[error("Could not read Gemfile: {msg}")]
+#[diagnostic()]
+pub struct ParseError {
+    /// The Gemfile contents
+    #[source_code]
+    gemfile_contents: String,
+
+    /// Where reading failed.
+    #[label("{msg}")]
+    span: SourceSpan,
+
+    /// Error message
+    msg: String,
+}
+
+/// Read the gems and sources declared in the given Gemfile contents.
+pub fn parse(contents: &str) -> Result<Gemfile, ParseError> {
+    Parser::default()
+        .parse(contents)
+        .map_err(|(span, msg)| ParseError {
+            gemfile_contents: contents.to_owned(),
+            span: span.into(),
+            msg,
+        })
+}
+
+type Result<T, E = (Range<usize>, String)> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Tok {
+    Ident(String),
+    /// `name:`, as in keyword arguments.
+    Label(String),
+    Str(String),
+    /// A string with `#{}` interpolation, which can't be known without running Ruby.
+    InterpolatedStr,
+    Sym(String),
+    /// `%w[]` and `%i[]` literals.
+    Words(Vec<String>),
+    Num(String),
+    Arrow,
+    Comma,
+    Pipe,
+    Open(char),
+    Close(char),
+    Newline,
+    Other(char),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    span: Range<usize>,
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>> {
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    let peek = |i: usize| bytes.get(i).map(|b| *b as char);
+
+    while let Some(c) = peek(i) {
+        let start = i;
+        let tok = match c {
+            ' ' | '\t' | '\r' => {
+                i += 1;
+                continue;
+            }
+            '\\' if peek(i + 1) == Some('\n') => {
+                i += 2;
+                continue;
+            }
+            '#' => {
+                while peek(i).is_some_and(|c| c != '\n') {
+                    i += 1;
+                }
+                continue;
+            }
+            '\n' | ';' => {
+                i += 1;
+                Tok::Newline
+            }
+            '"' | '\'' => {
+                let (value, interpolated, end) = read_string(src, i)?;
+                i = end;
+                if peek(i) == Some(':') && peek(i + 1) != Some(':') {
+                    i += 1;
+                    Tok::Label(value)
+                } else if interpolated {
+                    Tok::InterpolatedStr
+                } else {
+                    Tok::Str(value)
+                }
+            }
+            ':' if peek(i + 1) == Some('"') || peek(i + 1) == Some('\'') => {
+                let (value, _, end) = read_string(src, i + 1)?;
+                i = end;
+                Tok::Sym(value)
+            }
+            ':' if peek(i + 1).is_some_and(is_ident_start) => {
+                i += 1;
+                while peek(i).is_some_and(is_ident_char) {
+                    i += 1;
+                }
+                if matches!(peek(i), Some('?' | '!')) {
+                    i += 1;
+                }
+                Tok::Sym(src[start + 1..i].to_owned())
+            }
+            '=' if peek(i + 1) == Some('>') => {
+                i += 2;
+                Tok::Arrow
+            }
+            '%' if matches!(peek(i + 1), Some('w' | 'W' | 'i' | 'I')) => {
+                let open = peek(i + 2).unwrap_or(' ');
+                let close = match open {
+                    '[' => ']',
+                    '(' => ')',
+                    '{' => '}',
+                    '<' => '>',
+                    _ => return Err((start..i + 2, "unsupported %-literal".to_owned())),
+                };
+                let Some(len) = src[i + 3..].find(close) else {
+                    return Err((start..src.len(), "unterminated %-literal".to_owned()));
+                };
+                let words = src[i + 3..i + 3 + len]
+                    .split_whitespace()
+                    .map(str::to_owned)
+                    .collect();
+                i += 3 + len + 1;
+                Tok::Words(words)
+            }
+            c if is_ident_start(c) => {
+                loop {
+                    while peek(i).is_some_and(is_ident_char) {
+                        i += 1;
+                    }
+                    // Constants can be namespaced, e.g. `Gem::Version`.
+                    if peek(i) == Some(':')
+                        && peek(i + 1) == Some(':')
+                        && peek(i + 2).is_some_and(is_ident_start)
+                    {
+                        i += 2;
+                    } else {
+                        break;
+                    }
+                }
+                if matches!(peek(i), Some('?' | '!')) {
+                    i += 1;
+                }
+                let word = src[start..i].to_owned();
+                if peek(i) == Some(':') && peek(i + 1) != Some(':') {
+                    i += 1;
+                    Tok::Label(word)
+                } else {
+                    Tok::Ident(word)
+                }
+            }
+            c if c.is_ascii_digit() => {
+                while peek(i).is_some_and(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_') {
+                    i += 1;
+                }
+                Tok::Num(src[start..i].to_owned())
+            }
+            ',' => {
+                i += 1;
+                Tok::Comma
+            }
+            '|' => {
+                i += 1;
+                Tok::Pipe
+            }
+            '(' | '[' | '{' => {
+                i += 1;
+                Tok::Open(c)
+            }
+            ')' | ']' | '}' => {
+                i += 1;
+                Tok::Close(c)
+            }
+            other => {
+                i += 1;
+                while !src.is_char_boundary(i) {
+                    i += 1;
+                }
+                Tok::Other(other)
+            }
+        };
+        tokens.push(Token {
+            tok,
+            span: start..i,
+        });
+    }
+
+    Ok(tokens)
+}
+
+/// Reads a quoted string starting at `start`, returning its value, whether it
+/// uses interpolation, and the offset just past the closing quote.
+fn read_string(src: &str, start: usize) -> Result<(String, bool, usize)> {
+    let quote = src.as_bytes()[start] as char;
+    let mut value = String::new();
+    let mut interpolated = false;
+    let mut chars = src[start + 1..].char_indices();
+
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            c if c == quote => return Ok((value, interpolated, start + 1 + offset + 1)),
+            '\\' => {
+                let Some((_, escaped)) = chars.next() else {
+                    break;
+                };
+                match escaped {
+                    'n' if quote == '"' => value.push('\n'),
+                    't' if quote == '"' => value.push('\t'),
+                    c if c == quote || c == '\\' => value.push(c),
+                    c => {
+                        value.push('\\');
+                        value.push(c);
+                    }
+                }
+            }
+            '#' if quote == '"' && src[start + 1 + offset..].starts_with("#{") => {
+                interpolated = true;
+                value.push(c);
+            }
+            c => value.push(c),
+        }
+    }
+
+    Err((start..src.len(), "unterminated string".to_owned()))
+}
+
+/// A Ruby value that can be known without evaluating any Ruby.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Str(String),
+    Sym(String),
+    Array(Vec<Value>),
+    Bool(bool),
+    Nil,
+    /// Anything else, e.g. a method call or an interpolated string.
+    Dynamic,
+}
+
+impl Value {
+    /// The value as a string, if it's a string or a symbol.
+    fn as_name(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) | Value::Sym(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The value as a list of names, e.g. for `group: [:dev, :test]` or `group: :dev`.
+    fn as_names(&self) -> Option<Vec<String>> {
+        match self {
+            Value::Array(values) => values
+                .iter()
+                .map(|v| v.as_name().map(str::to_owned))
+                .collect(),
+            other => other.as_name().map(|name| vec![name.to_owned()]),
+        }
+    }
+}
+
+/// One argument to a Gemfile method, e.g. `"rails"` or `require: false`.
+struct Arg {
+    key: Option<String>,
+    value: Value,
+    span: Range<usize>,
+}
+
+/// An open `do ... end` block, and how it affects the gems declared inside it.
+enum Scope {
+    Group(Vec<String>),
+    Platforms(Vec<String>),
+    Source(DependencySource),
+}
+
+#[derive(Default)]
+struct Parser {
+    gemfile: Gemfile,
+    scopes: Vec<Scope>,
+    /// Span of each open block's opening statement, for error reporting.
+    open_blocks: Vec<Range<usize>>,
+}
+
+impl Parser {
+    fn parse(mut self, src: &str) -> Result<Gemfile> {
+        let tokens = tokenize(src)?;
+        for statement in split_statements(&tokens) {
+            self.statement(statement)?;
+        }
+
+        if let Some(span) = self.open_blocks.pop() {
+            return Err((span, "this block is never closed with `end`".to_owned()));
+        }
+
+        Ok(self.gemfile)
+    }
+
+    fn statement(&mut self, tokens: &[Token]) -> Result<()> {
+        let span = tokens[0].span.start..tokens[tokens.len() - 1].span.end;
+        let Tok::Ident(method) = &tokens[0].tok else {
+            return Err((span, "expected a Gemfile method like `gem`".to_owned()));
+        };
+
+        // Strip a trailing `do` or `do |args|`, remembering that this opens a block.
+        let mut rest = &tokens[1..];
+        if let [init @ .., last] = rest
+            && last.tok == Tok::Pipe
+            && let Some(pipe) = init.iter().rposition(|t| t.tok == Tok::Pipe)
+        {
+            rest = &rest[..pipe];
+        }
+        let opens_block = match rest {
+            [init @ .., last] if last.tok == Tok::Ident("do".to_owned()) => {
+                rest = init;
+                true
+            }
+            _ => false,
+        };
+
+        match method.as_str() {
+            "end" if rest.is_empty() => {
+                if self.open_blocks.pop().is_none() {
+                    return Err((span, "`end` without a matching block".to_owned()));
+                }
+                self.scopes.pop();
+                return Ok(());
+            }
+            // Neither of these affect which gems are needed.
+            "git_source" | "plugin" => {
+                if opens_block {
+                    return Err((span, format!("`{method}` blocks are not supported")));
+                }
+                return Ok(());
+            }
+            "if" | "unless" | "case" | "while" | "until" | "begin" | "def" | "install_if"
+            | "eval_gemfile" | "gemspec" | "env" => {
+                return Err((
+                    span,
+                    format!("`{method}` needs Ruby to evaluate, which rv doesn't support yet"),
+                ));
+            }
+            _ => {}
+        }
+
+        let args = parse_args(rest)?;
+        let scope = match method.as_str() {
+            "source" => {
+                let url = positional_name(&args, 0, &span, "a source URL")?;
+                if opens_block {
+                    Some(Scope::Source(DependencySource::Rubygems(url)))
+                } else {
+                    self.gemfile.sources.push(url);
+                    None
+                }
+            }
+            "git" | "path" if opens_block => {
+                let location = positional_name(&args, 0, &span, "a location")?;
+                Some(Scope::Source(if method == "git" {
+                    DependencySource::Git(location)
+                } else {
+                    DependencySource::Path(location)
+                }))
+            }
+            "group" | "groups" if opens_block => {
+                let groups = positional_names(&args)?;
+                let optional = args.iter().any(|arg| {
+                    arg.key.as_deref() == Some("optional") && matches!(arg.value, Value::Bool(true))
+                });
+                if optional {
+                    for group in &groups {
+                        if !self.gemfile.optional_groups.contains(group) {
+                            self.gemfile.optional_groups.push(group.clone());
+                        }
+                    }
+                }
+                Some(Scope::Group(groups))
+            }
+            "platforms" | "platform" if opens_block => {
+                Some(Scope::Platforms(positional_names(&args)?))
+            }
+            "ruby" if !opens_block => {
+                self.ruby(&args, &span)?;
+                None
+            }
+            "gem" if !opens_block => {
+                self.gem(&args, &span)?;
+                None
+            }
+            other => {
+                return Err((span, format!("unsupported Gemfile method `{other}`")));
+            }
+        };
+
+        if let Some(scope) = scope {
+            self.scopes.push(scope);
+            self.open_blocks.push(span);
+        }
+        Ok(())
+    }
+
+    fn ruby(&mut self, args: &[Arg], span: &Range<usize>) -> Result<()> {
+        if let Some(file) = args.iter().find(|arg| arg.key.as_deref() == Some("file")) {
+            let Value::Str(path) = &file.value else {
+                return Err((file.span.clone(), "expected a file path".to_owned()));
+            };
+            self.gemfile.ruby = Some(RubyDirective::File(path.clone()));
+            return Ok(());
+        }
+
+        let requirements = positional_strings(args, 0)?;
+        if requirements.is_empty() {
+            return Err((span.clone(), "expected a Ruby version".to_owned()));
+        }
+        let requirement = Requirement::new(requirements)
+            .map_err(|err| (span.clone(), format!("invalid Ruby version: {err}")))?;
+        self.gemfile.ruby = Some(RubyDirective::Requirement(requirement));
+        Ok(())
+    }
+
+    fn gem(&mut self, args: &[Arg], span: &Range<usize>) -> Result<()> {
+        let name = positional_name(args, 0, span, "a gem name")?;
+        let requirement = Requirement::new(positional_strings(args, 1)?)
+            .map_err(|err| (span.clone(), format!("invalid version requirement: {err}")))?;
+
+        let mut groups: Vec<String> = self
+            .scopes
+            .iter()
+            .flat_map(|scope| match scope {
+                Scope::Group(groups) => groups.clone(),
+                _ => Vec::new(),
+            })
+            .collect();
+        let mut platforms = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| match scope {
+                Scope::Platforms(platforms) => Some(platforms.clone()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let mut source = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| match scope {
+                Scope::Source(source) => Some(source.clone()),
+                _ => None,
+            })
+            .unwrap_or(DependencySource::Default);
+
+        for arg in args.iter() {
+            let Some(key) = &arg.key else {
+                continue;
+            };
+            let names = || {
+                arg.value
+                    .as_names()
+                    .ok_or_else(|| (arg.span.clone(), format!("expected names for `{key}`")))
+            };
+            let location = || match &arg.value {
+                Value::Str(location) => Ok(location.clone()),
+                _ => Err((arg.span.clone(), format!("expected a string for `{key}`"))),
+            };
+            match key.as_str() {
+                "group" | "groups" => groups.extend(names()?),
+                "platform" | "platforms" => platforms = names()?,
+                "source" => source = DependencySource::Rubygems(location()?),
+                "git" => source = DependencySource::Git(location()?),
+                "github" => {
+                    source =
+                        DependencySource::Git(format!("https://github.com/{}.git", location()?))
+                }
+                "path" => source = DependencySource::Path(location()?),
+                // Other options (like `require:`) don't change what gets locked.
+                _ => {}
+            }
+        }
+
+        if groups.is_empty() {
+            groups.push("default".to_owned());
+        }
+
+        self.gemfile.dependencies.push(GemfileDependency {
+            name,
+            requirement,
+            groups,
+            platforms,
+            source,
+        });
+        Ok(())
+    }
+}
+
+/// Splits tokens into statements, i.e. lines, except that a statement continues onto the
+/// next line if it's inside brackets or ends with a comma or `=>`.
+fn split_statements(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut depth = 0usize;
+
+    for (i, token) in tokens.iter().enumerate() {
+        match &token.tok {
+            Tok::Open(_) => depth += 1,
+            Tok::Close(_) => depth = depth.saturating_sub(1),
+            Tok::Newline if depth == 0 => {
+                let continues = i > start
+                    && matches!(tokens[i - 1].tok, Tok::Comma | Tok::Arrow | Tok::Newline);
+                if continues {
+                    continue;
+                }
+                if i > start {
+                    statements.push(&tokens[start..i]);
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if start < tokens.len() {
+        statements.push(&tokens[start..]);
+    }
+
+    // Continued statements still contain the newlines they spanned, drop those.
+    statements
+        .into_iter()
+        .map(|statement| {
+            let end = statement
+                .iter()
+                .rposition(|t| t.tok != Tok::Newline)
+                .map_or(0, |i| i + 1);
+            &statement[..end]
+        })
+        .filter(|statement| !statement.is_empty())
+        .collect()
+}
+
+/// Parses a method's arguments, e.g. `"rails", "~> 8.0", require: false`.
+fn parse_args(tokens: &[Token]) -> Result<Vec<Arg>> {
+    let tokens: Vec<&Token> = tokens.iter().filter(|t| t.tok != Tok::Newline).collect();
+
+    // Arguments can be wrapped in parens, e.g. `gem("rails")`.
+    let tokens = match tokens.as_slice() {
+        [first, inner @ .., last]
+            if first.tok == Tok::Open('(')
+                && last.tok == Tok::Close(')')
+                && closing_index(&tokens, 0) == Some(tokens.len() - 1) =>
+        {
+            inner.to_vec()
+        }
+        _ => tokens,
+    };
+
+    split_on_commas(&tokens)
+        .into_iter()
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| {
+            let span = arg[0].span.start..arg[arg.len() - 1].span.end;
+            let (key, value_tokens) = match arg {
+                [key, value @ ..] if matches!(key.tok, Tok::Label(_)) => {
+                    let Tok::Label(key) = &key.tok else {
+                        unreachable!()
+                    };
+                    (Some(key.clone()), value)
+                }
+                [key, arrow, value @ ..] if arrow.tok == Tok::Arrow => match &key.tok {
+                    Tok::Sym(key) | Tok::Str(key) => (Some(key.clone()), value),
+                    _ => return Err((span, "unsupported hash key".to_owned())),
+                },
+                value => (None, value),
+            };
+            Ok(Arg {
+                key,
+                value: parse_value(value_tokens),
+                span,
+            })
+        })
+        .collect()
+}
+
+fn parse_value(tokens: &[&Token]) -> Value {
+    match tokens {
+        [single] => match &single.tok {
+            Tok::Str(s) => Value::Str(s.clone()),
+            Tok::Sym(s) => Value::Sym(s.clone()),
+            Tok::Words(words) => Value::Array(words.iter().cloned().map(Value::Str).collect()),
+            Tok::Ident(ident) if ident == "true" => Value::Bool(true),
+            Tok::Ident(ident) if ident == "false" => Value::Bool(false),
+            Tok::Ident(ident) if ident == "nil" => Value::Nil,
+            _ => Value::Dynamic,
+        },
+        [first, inner @ .., last]
+            if first.tok == Tok::Open('[')
+                && last.tok == Tok::Close(']')
+                && closing_index(tokens, 0) == Some(tokens.len() - 1) =>
+        {
+            Value::Array(
+                split_on_commas(inner)
+                    .into_iter()
+                    .filter(|item| !item.is_empty())
+                    .map(parse_value)
+                    .collect(),
+            )
+        }
+        _ => Value::Dynamic,
+    }
+}
+
+/// Finds the index of the bracket closing the one at `open`.
+fn closing_index(tokens: &[&Token], open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token.tok {
+            Tok::Open(_) => depth += 1,
+            Tok::Close(_) => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn split_on_commas<'a, 't>(tokens: &'a [&'t Token]) -> Vec<&'a [&'t Token]> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.tok {
+            Tok::Open(_) => depth += 1,
+            Tok::Close(_) => depth = depth.saturating_sub(1),
+            Tok::Comma if depth == 0 => {
+                parts.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&tokens[start..]);
+    parts
+}
+
+fn positional(args: &[Arg]) -> impl Iterator<Item = &Arg> {
+    args.iter().filter(|arg| arg.key.is_none())
+}
+
+fn positional_name(args: &[Arg], index: usize, span: &Range<usize>, what: &str) -> Result<String> {
+    match positional(args).nth(index) {
+        Some(arg) => match &arg.value {
+            Value::Str(s) | Value::Sym(s) => Ok(s.clone()),
+            _ => Err((
+                arg.span.clone(),
+                format!("expected {what}, but this needs Ruby to evaluate"),
+            )),
+        },
+        None => Err((span.clone(), format!("expected {what}"))),
+    }
+}
+
+fn positional_names(args: &[Arg]) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for arg in positional(args) {
+        match arg.value.as_names() {
+            Some(more) => names.extend(more),
+            None => return Err((arg.span.clone(), "expected a name".to_owned())),
+        }
+    }
+    Ok(names)
+}
+
+/// All string arguments from `index` onwards, e.g. the version requirements in
+/// `gem "rails", "~> 8.0", ">= 8.0.1"`.
+fn positional_strings(args: &[Arg], index: usize) -> Result<Vec<String>> {
+    let mut strings = Vec::new();
+    for arg in positional(args).skip(index) {
+        match &arg.value {
+            Value::Str(s) => strings.push(s.clone()),
+            Value::Array(values) if values.iter().all(|v| matches!(v, Value::Str(_))) => {
+                strings.extend(values.iter().filter_map(|v| v.as_name().map(str::to_owned)))
+            }
+            _ => {
+                return Err((
+                    arg.span.clone(),
+                    "expected a version requirement, but this needs Ruby to evaluate".to_owned(),
+                ));
+            }
+        }
+    }
+    Ok(strings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dep(gemfile: &Gemfile, name: &str) -> GemfileDependency {
+        gemfile
+            .dependencies
+            .iter()
+            .find(|dep| dep.name == name)
+            .unwrap_or_else(|| panic!("{name} should be in the Gemfile"))
+            .clone()
+    }
+
+    #[test]
+    fn test_parse_basic_gemfile() {
+        let gemfile = parse(
+            r#"# frozen_string_literal: true
+
+source "https://rubygems.org"
+
+gem "rake"
+gem 'rspec', '~> 3.0', '>= 3.0.1'
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(gemfile.sources, vec!["https://rubygems.org"]);
+        assert_eq!(gemfile.ruby, None);
+        assert_eq!(gemfile.dependencies.len(), 2);
+
+        let rake = dep(&gemfile, "rake");
+        assert!(rake.requirement.is_latest_version());
+        assert_eq!(rake.groups, vec!["default"]);
+        assert_eq!(rake.source, DependencySource::Default);
+
+        let rspec = dep(&gemfile, "rspec");
+        assert_eq!(rspec.requirement.to_string(), "~> 3.0, >= 3.0.1");
+    }
+
+    #[test]
+    fn test_parse_existing_fixtures() {
+        for contents in [
+            include_str!("../../rv-lockfile/tests/inputs/Gemfile.testsource"),
+            include_str!("../../rv-lockfile/tests/inputs/Gemfile.minimal-ruby-project"),
+            include_str!("../../rv-lockfile/tests/inputs/Gemfile.git-rails"),
+            include_str!("../../rv-lockfile/tests/inputs/Gemfile.llhttp-ffi"),
+            include_str!("../../rv-lockfile/tests/inputs/Gemfile.empty"),
+        ] {
+            let gemfile = parse(contents).unwrap();
+            assert_eq!(gemfile.sources.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_parse_conditionals_are_unsupported() {
+        let contents = include_str!("../../rv-lockfile/tests/inputs/Gemfile.discourse");
+        let err = parse(contents).unwrap_err();
+        assert!(contents[err.span.offset()..].starts_with("if ENV[\"ALLOW_DEV_POPULATE\"]"));
+    }
+
+    #[test]
+    fn test_parse_gemspec_is_unsupported() {
+        let err = parse(include_str!("../../rv-lockfile/tests/inputs/Gemfile.faker")).unwrap_err();
+        assert_eq!(
+            err.msg,
+            "`gemspec` needs Ruby to evaluate, which rv doesn't support yet"
+        );
+    }
+
+    #[test]
+    fn test_parse_ruby_directive() {
+        let gemfile = parse("ruby '~> 3.4'\n").unwrap();
+        assert_eq!(
+            gemfile.ruby,
+            Some(RubyDirective::Requirement(
+                Requirement::parse("~> 3.4").unwrap()
+            ))
+        );
+
+        let gemfile = parse("ruby file: \".ruby-version\"\n").unwrap();
+        assert_eq!(
+            gemfile.ruby,
+            Some(RubyDirective::File(".ruby-version".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let gemfile = parse(
+            r#"source "https://rubygems.org"
+gem "pry", require: false, group: [:development, :test]
+gem "tzinfo-data", platforms: %i[windows jruby]
+gem "debug", :platforms => :mri, :require => "debug/prelude"
+gem("rails", "~> 8.0")
+gem "private", source: "https://gems.example.com"
+gem "local", path: "../local"
+gem "forked", github: "someone/forked", branch: "main"
+gem "multiline",
+  "~> 1.0",
+  require: false
+"#,
+        )
+        .unwrap();
+
+        let pry = dep(&gemfile, "pry");
+        assert_eq!(pry.groups, vec!["development", "test"]);
+
+        let tzinfo = dep(&gemfile, "tzinfo-data");
+        assert_eq!(tzinfo.platforms, vec!["windows", "jruby"]);
+
+        let debug = dep(&gemfile, "debug");
+        assert_eq!(debug.platforms, vec!["mri"]);
+
+        let rails = dep(&gemfile, "rails");
+        assert_eq!(rails.requirement.to_string(), "~> 8.0");
+
+        let private = dep(&gemfile, "private");
+        assert_eq!(
+            private.source,
+            DependencySource::Rubygems("https://gems.example.com".to_owned())
+        );
+
+        let local = dep(&gemfile, "local");
+        assert_eq!(local.source, DependencySource::Path("../local".to_owned()));
+
+        let forked = dep(&gemfile, "forked");
+        assert_eq!(
+            forked.source,
+            DependencySource::Git("https://github.com/someone/forked.git".to_owned())
+        );
+
+        let multiline = dep(&gemfile, "multiline");
+        assert_eq!(multiline.requirement.to_string(), "~> 1.0");
+    }
+
+    #[test]
+    fn test_parse_blocks() {
+        let gemfile = parse(
+            r#"source "https://rubygems.org"
+git_source(:github) { |repo| "https://github.com/#{repo}.git" }
+
+group :development, :test do
+  gem "rspec"
+
+  platforms :mri do
+    gem "byebug"
+  end
+end
+
+source "https://gems.example.com" do
+  gem "private"
+end
+
+group :docs, optional: true do
+  gem "yard"
+end
+
+gem "outside"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(gemfile.sources, vec!["https://rubygems.org"]);
+
+        let rspec = dep(&gemfile, "rspec");
+        assert_eq!(rspec.groups, vec!["development", "test"]);
+        assert!(rspec.platforms.is_empty());
+
+        let byebug = dep(&gemfile, "byebug");
+        assert_eq!(byebug.groups, vec!["development", "test"]);
+        assert_eq!(byebug.platforms, vec!["mri"]);
+
+        let private = dep(&gemfile, "private");
+        assert_eq!(
+            private.source,
+            DependencySource::Rubygems("https://gems.example.com".to_owned())
+        );
+
+        let yard = dep(&gemfile, "yard");
+        assert_eq!(yard.groups, vec!["docs"]);
+        assert_eq!(gemfile.optional_groups, vec!["docs"]);
+
+        let outside = dep(&gemfile, "outside");
+        assert_eq!(outside.groups, vec!["default"]);
+        assert_eq!(outside.source, DependencySource::Default);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = parse("source \"https://rubygems.org\"\nif ENV[\"CI\"]\n  gem \"x\"\nend\n")
+            .unwrap_err();
+        assert_eq!(
+            err.msg,
+            "`if` needs Ruby to evaluate, which rv doesn't support yet"
+        );
+        assert_eq!(err.span.offset(), 30);
+
+        let err = parse("gem \"rails\", ENV.fetch(\"RAILS_VERSION\")\n").unwrap_err();
+        assert_eq!(
+            err.msg,
+            "expected a version requirement, but this needs Ruby to evaluate"
+        );
+
+        let err = parse("group :test do\n  gem \"rspec\"\n").unwrap_err();
+        assert_eq!(err.msg, "this block is never closed with `end`");
+
+        let err = parse("end\n").unwrap_err();
+        assert_eq!(err.msg, "`end` without a matching block");
+
+        let err = parse("gem \"rails\", \"not a version\"\n").unwrap_err();
+        assert!(err.msg.starts_with("invalid version requirement"));
+    }
+
+    #[test]
+    fn test_platform_applies_to() {
+        let ruby: RubyVersion = "ruby-3.4.1".parse().unwrap();
+        let jruby: RubyVersion = "jruby-9.4.8.0".parse().unwrap();
+
+        assert_eq!(platform_applies_to("ruby", &ruby), !cfg!(windows));
+        assert_eq!(platform_applies_to("mri_34", &ruby), !cfg!(windows));
+        assert!(!platform_applies_to("mri_33", &ruby));
+        assert_eq!(platform_applies_to("windows", &ruby), cfg!(windows));
+        assert!(!platform_applies_to("jruby", &ruby));
+        assert!(platform_applies_to("jruby", &jruby));
+        assert!(!platform_applies_to("mri", &jruby));
+        // A trailing `_` with no version is just an unknown platform.
+        assert!(!platform_applies_to("mri_", &ruby));
+    }
+}
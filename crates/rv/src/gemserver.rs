@@ -1,31 +1,52 @@
-use futures_util::{StreamExt, stream::FuturesUnordered};
-use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 use std::sync::Arc;
-use std::{rc::Rc, sync::Mutex};
 
+use camino::Utf8PathBuf;
 use rv_gem_types::requirement::{Requirement, VersionConstraint};
 use rv_gem_types::{Platform, ProjectDependency, VersionPlatform};
-use rv_ruby::version::RubyVersion;
+use rv_lockfile::datatypes::ChecksumAlgorithm;
 use rv_version::Version;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
+use tokio::sync::OnceCell;
 use tracing::debug;
 use url::Url;
 
 use crate::config::Config;
-use crate::gemserver::http_fetcher::HttpFetcher;
-use crate::gemserver::storage::{FilesystemStorage, Storage};
+use crate::config::credentials::redact;
+use crate::gemserver::http_fetcher::{Fetcher, HttpFetcher};
+use crate::gemserver::storage::{Blob, FilesystemStorage, Storage};
 use crate::gemserver::updater::Updater;
+use crate::gemserver::versions::Versions;
 
 pub mod http_fetcher;
+mod legacy;
+pub mod marshal;
+pub mod sources;
 pub mod storage;
 pub mod updater;
+pub mod versions;
 
 pub struct Gemserver {
     pub url: Url,
-    // Maps gem names to their dependency lists.
-    pub gems_to_deps: HashMap<String, HashMap<VersionPlatform, GemRelease>>,
+    /// Where the index is actually fetched from, i.e. the mirror of `url` if it has one, with
+    /// its credentials.
+    index_url: Url,
+    /// The server's `versions` file, if it has one. It's only fetched once there's a cached
+    /// `info/` file to check against it, so a cold cache doesn't download the whole file.
+    versions: OnceCell<Option<Versions>>,
+    /// Whether the server has a compact index at all, checked the first time a gem is missing.
+    compact_index: OnceCell<bool>,
+    /// The full index, for servers without a compact index or dependency API. None if the
+    /// server doesn't have one either.
+    full_index: OnceCell<Option<Vec<legacy::IndexEntry>>>,
+    /// Only read the cached index, never the server.
+    offline: bool,
+    /// How many requests to the server can be in flight at once, e.g. for gemspecs.
+    max_concurrent_requests: usize,
+    /// Where this server's index is cached.
+    cache_dir: Utf8PathBuf,
+    client: HttpFetcher,
     updater: Arc<Updater>,
     storage: Arc<dyn Storage>,
 }
@@ -44,53 +65,167 @@ pub enum Error {
     CouldNotCreateCacheDir(std::io::Error),
     #[error("The url {url} unexpectedly returned an empty response")]
     EmptyResponse { url: Url },
+    #[error("Could not find {gem} on {}", .sources.join(" or "))]
+    GemNotFound { gem: String, sources: Vec<String> },
+    #[error(
+        "{gem} was found on more than one source ({}), and they can't be shown to have the same releases. Pin it to the one it should come from with a `source \"...\" do` block",
+        .sources.join(", ")
+    )]
+    AmbiguousGem { gem: String, sources: Vec<String> },
+    #[error(
+        "Could not find {gem} in the cached index of {}, and it can't be fetched offline",
+        .sources.join(" or ")
+    )]
+    NotCached { gem: String, sources: Vec<String> },
+    #[error("Could not read {url}: {reason}")]
+    InvalidIndex { url: Url, reason: String },
+}
+
+impl Error {
+    /// Did the server respond that it doesn't have this gem?
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Error::Reqwest(err) | Error::HttpError(http_fetcher::Error::Reqwest(err)) => {
+                err.status() == Some(reqwest::StatusCode::NOT_FOUND)
+            }
+            _ => false,
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-impl Gemserver {
-    pub fn new(config: &Config, mut url: Url) -> Result<Self> {
-        let cache_dir = config
-            .cache
-            .shard(rv_cache::CacheBucket::GemDeps, "compact_index")
-            .into_path_buf();
+/// Add a trailing slash to the url if not already there. Otherwise, if the gemserver is
+/// namespaced, the namespace is ignored because joining url's requires the base url with
+/// have a trailing slash, and we join url's to construct compact index endpoints
+fn base_url(mut url: Url) -> Url {
+    if !url.path().ends_with('/') {
+        url.path_segments_mut()
+            .expect("this url cannot be a base")
+            .push("");
+    }
+    url
+}
 
+impl Gemserver {
+    pub async fn new(config: &Config, url: Url) -> Result<Self> {
+        let url = base_url(url);
+        let cache_dir = Self::cache_dir(config, &url);
         fs_err::create_dir_all(&cache_dir).map_err(Error::CouldNotCreateCacheDir)?;
 
-        let client = HttpFetcher::new("install")?;
-        let storage = FilesystemStorage::new(cache_dir.into());
-        let updater = Updater::new(client);
+        let client = HttpFetcher::new("install", config.rv_settings.retry_policy())?;
+        let storage = FilesystemStorage::new(cache_dir.clone().into());
+        let updater = Updater::new(client.clone());
 
-        // Add a trailing slash to the url if not already there. Otherwise, if the gemserver is
-        // namespaced, the namespace is ignored because joining url's requires the base url with
-        // have a trailing slash, and we join url's to construct compact index endpoints
-        url.path_segments_mut()
-            .expect("this url cannot be a base")
-            .push("");
+        let index_url = config.with_credentials(&config.resolve_mirror(&url).await);
 
         Ok(Self {
             url,
+            index_url,
+            versions: OnceCell::new(),
+            compact_index: OnceCell::new(),
+            full_index: OnceCell::new(),
+            offline: config.offline,
+            max_concurrent_requests: config.rv_settings.max_concurrent_requests(),
+            cache_dir,
+            client,
             storage: Arc::new(storage),
             updater: Arc::new(updater),
-            gems_to_deps: Default::default(),
         })
     }
 
-    pub async fn add_transitive_deps(
-        &mut self,
-        root: &GemRelease,
-        ruby_to_use: &RubyVersion,
-    ) -> Result<()> {
-        debug!("Querying all transitive dependencies");
-        let mut transitive_deps = Default::default();
-        self.query_all_gem_deps(root, &mut transitive_deps, ruby_to_use)
-            .await?;
-        self.gems_to_deps.extend(transitive_deps);
-        debug!("Retrieved all transitive deps.");
-        Ok(())
+    /// Where the index of the server at `url` is cached. Each server gets its own copy of the
+    /// index, as the same gem can differ between them.
+    pub fn cache_dir(config: &Config, url: &Url) -> Utf8PathBuf {
+        let url = base_url(url.clone());
+        config
+            .cache
+            .shard(rv_cache::CacheBucket::GemDeps, "compact_index")
+            .shard(rv_cache::cache_digest(url.as_str()))
+            .into_path_buf()
+    }
+
+    /// Releases of `gem` that work on this platform, or None if the server doesn't have it.
+    /// Servers without a compact index are asked with their older APIs instead.
+    pub async fn releases(&self, gem: &str) -> Result<Option<Vec<GemRelease>>> {
+        if self.offline {
+            return self.cached_releases(gem).await;
+        }
+        if self.compact_index.get() != Some(&false) {
+            match self.get_releases_for_gem(gem).await {
+                Ok(body) => return Ok(Some(parse_release_from_body(&body)?)),
+                Err(err) if err.is_not_found() && self.has_compact_index().await => {
+                    return Ok(None);
+                }
+                Err(err) if err.is_not_found() => {}
+                Err(err) => return Err(err),
+            }
+        }
+        self.legacy_releases(gem).await
+    }
+
+    /// Bring the cached copy of `gem`'s `info/` file up to date, and return where it's cached.
+    /// Servers without a compact index have what their older APIs returned cached instead.
+    pub async fn cache_info(&self, gem: &str) -> Result<Utf8PathBuf> {
+        match self.get_releases_for_gem(gem).await {
+            Ok(_) => Ok(self.cache_dir.join("info").join(gem)),
+            Err(err) if err.is_not_found() && !self.has_compact_index().await => {
+                self.cache_legacy(gem).await
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Releases of `gem` from the cached copy of its `info/` file, or of what a server without a
+    /// compact index returned for it. None if neither is cached.
+    async fn cached_releases(&self, gem: &str) -> Result<Option<Vec<GemRelease>>> {
+        let Ok(blob) = self.storage.read_blob(&format!("info/{gem}")).await else {
+            let releases = self.legacy_releases(gem).await?;
+            if releases.is_none() {
+                debug!("{gem} from {} isn't cached", redact(&self.url));
+            }
+            return Ok(releases);
+        };
+        self.record_use(&format!("info/{gem}"));
+        let body = String::from_utf8_lossy(&blob.content);
+        Ok(Some(parse_release_from_body(&body)?))
+    }
+
+    /// Record that the cached copy of `key` was used, so pruning the cache evicts it last.
+    fn record_use(&self, key: &str) {
+        if let Err(err) = rv_cache::touch(self.cache_dir.join(key)) {
+            debug!(
+                "Couldn't record the use of {key} in {}: {err}",
+                self.cache_dir
+            );
+        }
+    }
+
+    /// Does the server have a compact index? If its `versions` file is missing, it doesn't.
+    async fn has_compact_index(&self) -> bool {
+        *self
+            .compact_index
+            .get_or_init(|| async {
+                if matches!(self.versions.get(), Some(Some(_))) {
+                    return true;
+                }
+                // Only ask for the first byte, as the whole file can be large.
+                let url = self.index_url.join("versions").expect("valid URL");
+                let headers = [("Range".to_owned(), "bytes=0-0".to_owned())].into();
+                let Err(err) = self.client.call(url.as_str(), headers).await else {
+                    return true;
+                };
+                if Error::from(err).is_not_found() {
+                    debug!("{} has no compact index", redact(&self.url));
+                    return false;
+                }
+                true
+            })
+            .await
     }
 
     /// Returns the response body from the server SERVER/info/GEM_NAME.
+    /// Uses the cached file without a request if the `versions` file says it's current.
     /// Fetches the file using etag/range requests if it's already there.
     /// Otherwise fetches a fresh copy.
     /// You probably want to call [`parse_release_from_body`] on the returned string.
@@ -98,23 +233,18 @@ impl Gemserver {
     /// Whoever calls this should own the response, and then the parser will borrow &strs from the response.
     pub async fn get_releases_for_gem(&self, gem: &str) -> Result<String> {
         let info_key = format!("info/{}", gem);
-        let info_url = self.url.join(&info_key).expect("valid info URL");
 
-        let blob = if let Ok(blob) = self.storage.read_blob(&info_key).await {
-            self.updater.update(info_url.as_str(), blob).await
-        } else {
-            self.updater.fetch(info_url.as_str()).await
+        let cached = self.storage.read_blob(&info_key).await.ok();
+        if let Some(blob) = &cached
+            && let Some(versions) = self.versions().await
+            && versions.is_current(gem, &blob.content)
+        {
+            debug!("The cached info for {gem} is current");
+            self.record_use(&info_key);
+            return Ok(String::from_utf8_lossy(&blob.content).to_string());
         }
-        .map_err(|err| {
-            if matches!(err, Error::StorageError(storage::Error::EmptyContent)) {
-                Error::EmptyResponse {
-                    url: self.url.to_owned(),
-                }
-            } else {
-                err
-            }
-        })?;
 
+        let blob = self.fetch_blob(&info_key, cached).await?;
         self.storage.write_blob(&info_key, &blob).await?;
 
         let index_body = String::from_utf8_lossy(&blob.content).to_string();
@@ -122,73 +252,48 @@ impl Gemserver {
         Ok(index_body)
     }
 
-    async fn fetch(&self, req: String) -> Result<((String, Vec<GemRelease>), Vec<String>)> {
-        debug!("Fetching {req}");
-        let dep_info_resp = self.get_releases_for_gem(&req).await?;
-        let dep_versions = parse_release_from_body(&dep_info_resp)?;
-        let transitive_deps = dep_versions
-            .iter()
-            .flat_map(|d| d.clone().deps.into_iter().map(|d| d.name))
-            .collect();
-        Ok(((req, dep_versions), transitive_deps))
+    /// The server's `versions` file, brought up to date the first time it's needed.
+    /// Servers don't have to provide it, so if it can't be fetched, every `info/` file is
+    /// checked with the server instead.
+    async fn versions(&self) -> Option<&Versions> {
+        self.versions
+            .get_or_init(|| async {
+                let cached = self.storage.read_blob("versions").await.ok();
+                let blob = match self.fetch_blob("versions", cached).await {
+                    Ok(blob) => blob,
+                    Err(err) => {
+                        debug!(
+                            "Not using the versions file from {}: {err}",
+                            redact(&self.url)
+                        );
+                        return None;
+                    }
+                };
+                if let Err(err) = self.storage.write_blob("versions", &blob).await {
+                    debug!("Could not cache the versions file: {err}");
+                }
+                Some(Versions::parse(&String::from_utf8_lossy(&blob.content)))
+            })
+            .await
+            .as_ref()
     }
 
-    pub async fn query_all_gem_deps(
-        &self,
-        root: &GemRelease,
-        gems_to_deps: &mut HashMap<String, HashMap<VersionPlatform, GemRelease>>,
-        ruby_to_use: &RubyVersion,
-    ) -> Result<()> {
-        let results = Rc::new(Mutex::new(HashMap::<
-            String,
-            HashMap<VersionPlatform, GemRelease>,
-        >::new()));
-        let mut in_flight = FuturesUnordered::new();
-        let seen_requests = Rc::new(Mutex::new(HashSet::<String>::new()));
-
-        // Initial requests
-        for d in &root.deps {
-            let req = d.name.clone();
-            debug!("Queuing {req}");
-            in_flight.push(self.fetch(req))
+    /// Fetch `key` from the index, only downloading what changed if it's already cached.
+    async fn fetch_blob(&self, key: &str, cached: Option<Blob>) -> Result<Blob> {
+        let url = self.index_url.join(key).expect("valid index URL");
+        match cached {
+            Some(blob) => self.updater.update(url.as_str(), blob).await,
+            None => self.updater.fetch(url.as_str()).await,
         }
-
-        // Keep fetching new dependencies we discover.
-        while let Some(res) = in_flight.next().await {
-            let ((dep_name, dep_info), new_deps) = res?;
-            {
-                let mut results = results.lock().expect("Lock poisoned");
-                // Skip possible versions that are incompatible with our
-                // chosen Ruby version.
-                // We should filter these out now, so that we minimize the number
-                // of deps that PubGrub has to consider.
-                let candidate_versions: HashMap<VersionPlatform, GemRelease> = dep_info
-                    .into_iter()
-                    .filter(|release| {
-                        release
-                            .metadata
-                            .ruby
-                            .satisfied_by(&rv_version::Version::from(ruby_to_use))
-                    })
-                    .map(|release| (release.version_platform.clone(), release))
-                    .collect();
-                results.insert(dep_name, candidate_versions);
-            }
-
-            for req in new_deps {
-                if seen_requests
-                    .lock()
-                    .expect("Lock poisoned")
-                    .insert(req.clone())
-                {
-                    debug!("Queuing {req}");
-                    in_flight.push(self.fetch(req));
+        .map_err(|err| {
+            if matches!(err, Error::StorageError(storage::Error::EmptyContent)) {
+                Error::EmptyResponse {
+                    url: redact(&self.url),
                 }
+            } else {
+                err
             }
-        }
-
-        *gems_to_deps = Rc::into_inner(results).unwrap().into_inner().unwrap();
-        Ok(())
+        })
     }
 }
 
@@ -269,6 +374,16 @@ impl GemRelease {
     pub fn platform(&self) -> &Platform {
         &self.version_platform.platform
     }
+
+    /// How this release's checksum should be written in a lockfile. Servers without a compact
+    /// index don't publish checksums, so those releases are locked without one, like Bundler does.
+    pub fn checksum_algorithm(&self) -> ChecksumAlgorithm<'static> {
+        if self.metadata.checksum.is_empty() {
+            ChecksumAlgorithm::None
+        } else {
+            ChecksumAlgorithm::SHA256
+        }
+    }
 }
 
 impl From<GemRelease> for VersionPlatform {
@@ -1,5 +1,8 @@
 use async_trait::async_trait;
 use rv_client::http_client::rv_http_client;
+use rv_client::retry::RetryPolicy;
+
+use crate::config::credentials::redact_error;
 use std::collections::HashMap;
 
 #[derive(Debug, Clone)]
@@ -71,6 +74,7 @@ fn strip_wrapper(s: &str, wrapper: char) -> Option<&str> {
 #[derive(Clone)]
 pub struct HttpFetcher {
     client: reqwest::Client,
+    retry: RetryPolicy,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -82,9 +86,10 @@ pub enum Error {
 pub type Result<T> = std::result::Result<T, Error>;
 
 impl HttpFetcher {
-    pub fn new(command: &'static str) -> Result<Self> {
+    pub fn new(command: &'static str, retry: RetryPolicy) -> Result<Self> {
         Ok(Self {
             client: rv_http_client(command)?,
+            retry,
         })
     }
 }
@@ -97,16 +102,24 @@ pub trait Fetcher: Send + Sync {
 
 #[async_trait]
 impl Fetcher for HttpFetcher {
-    /// Make a single HTTP call without retry logic
+    /// Make an HTTP call, retrying it if it fails in a way that might go away
     async fn call(&self, remote_path: &str, headers: HashMap<String, String>) -> Result<Response> {
-        let mut request = self.client.get(remote_path);
+        let request = || {
+            let mut request = self.client.get(remote_path);
 
-        // Add all headers to the request
-        for (key, value) in headers {
-            request = request.header(&key, value);
-        }
+            // Add all headers to the request
+            for (key, value) in &headers {
+                request = request.header(key, value);
+            }
+            request
+        };
 
-        let response = request.send().await?.error_for_status()?;
+        let response = self
+            .retry
+            .send(request)
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(redact_error)?;
         let status_code = response.status().as_u16();
 
         // Convert response headers to HashMap
@@ -117,7 +130,7 @@ impl Fetcher for HttpFetcher {
             }
         }
 
-        let body = response.bytes().await?.to_vec();
+        let body = response.bytes().await.map_err(redact_error)?.to_vec();
 
         Ok(Response {
             body,
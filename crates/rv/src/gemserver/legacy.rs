@@ -0,0 +1,432 @@
+//! Gem servers without a compact index, like old versions of geminabox or Nexus. They list
+//! releases and their dependencies with the dependency API, or failing that, with the full
+//! index (`specs.4.8.gz`) and a gemspec for every release. Both are in Ruby's Marshal format.
+//! Neither says which Ruby a release needs or gives a checksum, so those are left empty.
+//! What they return is cached like `info/` files, so it can be read offline.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::str::FromStr;
+
+use camino::Utf8PathBuf;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use futures_util::{StreamExt, TryStreamExt};
+use rv_gem_types::{ProjectDependency, Requirement, VersionPlatform};
+use tracing::debug;
+use url::Url;
+
+use super::http_fetcher::Fetcher;
+use super::marshal::{self, Value};
+use super::storage::Blob;
+use super::{Error, GemRelease, Gemserver, Metadata, Result};
+use crate::config::credentials::redact;
+
+/// Where the full index is, and is cached.
+const FULL_INDEX: &str = "specs.4.8.gz";
+
+/// Where the dependency API's response for `gem` is cached.
+fn dependencies_key(gem: &str) -> String {
+    format!("api/v1/dependencies/{gem}")
+}
+
+/// A release listed in the full index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    name: String,
+    version: String,
+    platform: String,
+}
+
+impl IndexEntry {
+    /// The file name the release's gemspec has, which leaves out the `ruby` platform.
+    fn full_name(&self) -> String {
+        if self.platform == "ruby" {
+            format!("{}-{}", self.name, self.version)
+        } else {
+            format!("{}-{}-{}", self.name, self.version, self.platform)
+        }
+    }
+}
+
+impl Gemserver {
+    /// Releases of `gem` that work on this platform, from a server without a compact index.
+    /// None if the server doesn't have the gem, or offline, if it isn't cached.
+    pub(super) async fn legacy_releases(&self, gem: &str) -> Result<Option<Vec<GemRelease>>> {
+        let releases = match self.dependency_api(gem).await? {
+            Some(releases) => releases,
+            None => {
+                debug!(
+                    "{} has no dependency API, using its full index",
+                    redact(&self.url)
+                );
+                let Some(releases) = self.full_index_releases(gem).await? else {
+                    return Ok(None);
+                };
+                releases
+            }
+        };
+        let releases: Vec<_> = releases
+            .into_iter()
+            .filter(|release| release.platform().is_local())
+            .collect();
+        Ok((!releases.is_empty()).then_some(releases))
+    }
+
+    /// Cache what `gem`'s releases are read from on a server without a compact index, and
+    /// return where it's cached. That's the whole cached index for servers with only a full
+    /// index, since the gemspecs are needed too.
+    pub(super) async fn cache_legacy(&self, gem: &str) -> Result<Utf8PathBuf> {
+        self.legacy_releases(gem).await?;
+        let dependencies = dependencies_key(gem);
+        if self.storage.exists(&dependencies).await {
+            return Ok(self.cache_dir.join(dependencies));
+        }
+        if self.storage.exists(FULL_INDEX).await {
+            return Ok(self.cache_dir.clone());
+        }
+        Err(Error::GemNotFound {
+            gem: gem.to_owned(),
+            sources: vec![redact(&self.url).to_string()],
+        })
+    }
+
+    /// Releases of `gem` from `/api/v1/dependencies`, which responds with a list of hashes
+    /// like `{name:, number:, platform:, dependencies: [[name, requirement]]}`. None if the
+    /// server has no dependency API.
+    async fn dependency_api(&self, gem: &str) -> Result<Option<Vec<GemRelease>>> {
+        let mut url = self
+            .index_url
+            .join("api/v1/dependencies")
+            .expect("valid URL");
+        url.query_pairs_mut().append_pair("gems", gem);
+        let Some(body) = self.fetch_cached(&url, &dependencies_key(gem)).await? else {
+            return Ok(None);
+        };
+        let invalid = |reason: &str| invalid_index(&url, reason);
+
+        let value = marshal::load(&body).map_err(|err| invalid(&err.to_string()))?;
+        let list = value.as_array().ok_or_else(|| invalid("expected a list"))?;
+        list.iter()
+            .filter(|release| release.get("name").and_then(Value::as_str) == Some(gem))
+            .map(|release| {
+                let field = |name| {
+                    release
+                        .get(name)
+                        .and_then(Value::as_str)
+                        .ok_or_else(|| invalid(&format!("a release has no {name}")))
+                };
+                let deps = release
+                    .get("dependencies")
+                    .and_then(Value::as_array)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|dep| match dep.as_array() {
+                        Some([name, requirement]) => Some(ProjectDependency {
+                            name: name.as_str()?.to_owned(),
+                            requirement: Requirement::new(
+                                requirement.as_str()?.split(", ").collect(),
+                            )
+                            .ok()?,
+                        }),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| invalid("a release has an invalid dependency"))?;
+                Ok(GemRelease {
+                    version_platform: version_platform(field("number")?, field("platform")?)
+                        .ok_or_else(|| invalid("a release has an invalid version"))?,
+                    deps,
+                    metadata: Metadata::default(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(Some)
+    }
+
+    /// Releases of `gem` from the full index, with their dependencies read from each
+    /// release's gemspec. None if the server has no full index, or offline, if it or any of
+    /// the gemspecs aren't cached.
+    async fn full_index_releases(&self, gem: &str) -> Result<Option<Vec<GemRelease>>> {
+        let Some(index) = self
+            .full_index
+            .get_or_try_init(|| self.fetch_full_index())
+            .await?
+        else {
+            return Ok(None);
+        };
+        let entries = index.iter().filter(|entry| {
+            entry.name == gem
+                && version_platform(&entry.version, &entry.platform)
+                    .is_some_and(|vp| vp.platform.is_local())
+        });
+        futures_util::stream::iter(entries.map(|entry| self.gemspec_release(entry)))
+            .buffered(self.max_concurrent_requests)
+            .try_collect::<Vec<_>>()
+            .await
+            .map(|releases| releases.into_iter().collect())
+    }
+
+    async fn fetch_full_index(&self) -> Result<Option<Vec<IndexEntry>>> {
+        let url = self.index_url.join(FULL_INDEX).expect("valid URL");
+        let Some(compressed) = self.fetch_cached(&url, FULL_INDEX).await? else {
+            return Ok(None);
+        };
+        let invalid = |reason: &str| invalid_index(&url, reason);
+
+        let mut body = Vec::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut body)
+            .map_err(|err| invalid(&err.to_string()))?;
+        let value = marshal::load(&body).map_err(|err| invalid(&err.to_string()))?;
+        value
+            .as_array()
+            .ok_or_else(|| invalid("expected a list"))?
+            .iter()
+            .map(|entry| match entry.as_array() {
+                Some([name, version, platform]) => Some(IndexEntry {
+                    name: name.as_str()?.to_owned(),
+                    version: gem_version(version)?,
+                    platform: platform.as_str()?.to_owned(),
+                }),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| invalid("expected a list of [name, version, platform]"))
+            .map(Some)
+    }
+
+    /// Read a release from its gemspec, which is a zlib-compressed `Gem::Specification`.
+    /// Gemspecs never change, so they're cached. Offline, None if it isn't cached.
+    async fn gemspec_release(&self, entry: &IndexEntry) -> Result<Option<GemRelease>> {
+        let path = format!("quick/Marshal.4.8/{}.gemspec.rz", entry.full_name());
+        let url = self.index_url.join(&path).expect("valid URL");
+        let compressed = match self.storage.read_blob(&path).await {
+            Ok(blob) => {
+                self.record_use(&path);
+                blob.content
+            }
+            Err(_) if self.offline => return Ok(None),
+            Err(_) => {
+                let compressed = self.get(&url).await?;
+                self.storage
+                    .write_blob(&path, &Blob::new(compressed.clone()))
+                    .await?;
+                compressed
+            }
+        };
+        let invalid = |reason: &str| invalid_index(&url, reason);
+
+        let mut body = Vec::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .read_to_end(&mut body)
+            .map_err(|err| invalid(&err.to_string()))?;
+        let spec = match marshal::load(&body).map_err(|err| invalid(&err.to_string()))? {
+            Value::UserDefined { class, data } if class == "Gem::Specification" => {
+                marshal::load(&data).map_err(|err| invalid(&err.to_string()))?
+            }
+            _ => return Err(invalid("expected a Gem::Specification")),
+        };
+        release_from_gemspec(&spec, entry)
+            .ok_or_else(|| invalid("invalid Gem::Specification"))
+            .map(Some)
+    }
+
+    /// The body of `url`, which is cached as `key` so it can be read offline. None if the
+    /// server doesn't have it, or offline, if it isn't cached.
+    async fn fetch_cached(&self, url: &Url, key: &str) -> Result<Option<Vec<u8>>> {
+        if self.offline {
+            let blob = self.storage.read_blob(key).await.ok();
+            if blob.is_some() {
+                self.record_use(key);
+            }
+            return Ok(blob.map(|blob| blob.content));
+        }
+        let body = match self.get(url).await {
+            Ok(body) => body,
+            Err(err) if err.is_not_found() => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        self.storage
+            .write_blob(key, &Blob::new(body.clone()))
+            .await?;
+        Ok(Some(body))
+    }
+
+    async fn get(&self, url: &Url) -> Result<Vec<u8>> {
+        debug!("Fetching {}", redact(url));
+        let response = self.client.call(url.as_str(), HashMap::new()).await?;
+        Ok(response.body)
+    }
+}
+
+/// A release from the fields `Gem::Specification#_dump` writes, which are
+/// `[rubygems_version, specification_version, name, version, date, summary,
+/// required_ruby_version, required_rubygems_version, original_platform, dependencies, ...]`.
+fn release_from_gemspec(spec: &Value, entry: &IndexEntry) -> Option<GemRelease> {
+    let fields = spec.as_array()?;
+    let deps = fields
+        .get(9)?
+        .as_array()?
+        .iter()
+        // Development dependencies aren't needed to install a gem.
+        .filter(|dep| {
+            dep.ivar("@type")
+                .and_then(Value::as_str)
+                .is_none_or(|kind| kind == "runtime")
+        })
+        .map(|dep| {
+            let requirement = dep
+                .ivar("@requirement")
+                .or_else(|| dep.ivar("@version_requirements"))?;
+            Some(ProjectDependency {
+                name: dep.ivar("@name")?.as_str()?.to_owned(),
+                requirement: gem_requirement(requirement)?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let metadata = Metadata {
+        ruby: fields.get(6).and_then(gem_requirement).unwrap_or_default(),
+        rubygems: fields.get(7).and_then(gem_requirement).unwrap_or_default(),
+        ..Metadata::default()
+    };
+    Some(GemRelease {
+        version_platform: version_platform(&entry.version, &entry.platform)?,
+        deps,
+        metadata,
+    })
+}
+
+/// A `Gem::Version`, which is dumped as `[version]`.
+fn gem_version(value: &Value) -> Option<String> {
+    match value {
+        Value::UserMarshal { class, data } if class == "Gem::Version" => {
+            Some(data.as_array()?.first()?.as_str()?.to_owned())
+        }
+        other => other.as_str().map(str::to_owned),
+    }
+}
+
+/// A `Gem::Requirement`, which is dumped as `[[[operator, Gem::Version], ...]]`.
+fn gem_requirement(value: &Value) -> Option<Requirement> {
+    let Value::UserMarshal { class, data } = value else {
+        return None;
+    };
+    if class != "Gem::Requirement" {
+        return None;
+    }
+    let constraints = data
+        .as_array()?
+        .first()?
+        .as_array()?
+        .iter()
+        .map(|constraint| match constraint.as_array()? {
+            [operator, version] => {
+                Some(format!("{} {}", operator.as_str()?, gem_version(version)?))
+            }
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Requirement::new(constraints).ok()
+}
+
+/// The version and platform of a release, written the way the compact index does.
+fn version_platform(version: &str, platform: &str) -> Option<VersionPlatform> {
+    let full = if platform == "ruby" {
+        version.to_owned()
+    } else {
+        format!("{version}-{platform}")
+    };
+    VersionPlatform::from_str(&full).ok()
+}
+
+fn invalid_index(url: &Url, reason: &str) -> Error {
+    Error::InvalidIndex {
+        url: redact(url),
+        reason: reason.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.as_bytes().to_vec())
+    }
+
+    fn version(v: &str) -> Value {
+        Value::UserMarshal {
+            class: "Gem::Version".to_owned(),
+            data: Box::new(Value::Array(vec![string(v)])),
+        }
+    }
+
+    fn requirement(constraints: &[(&str, &str)]) -> Value {
+        Value::UserMarshal {
+            class: "Gem::Requirement".to_owned(),
+            data: Box::new(Value::Array(vec![Value::Array(
+                constraints
+                    .iter()
+                    .map(|(op, v)| Value::Array(vec![string(op), version(v)]))
+                    .collect(),
+            )])),
+        }
+    }
+
+    fn dependency(name: &str, kind: &str, constraints: &[(&str, &str)]) -> Value {
+        Value::Object {
+            class: "Gem::Dependency".to_owned(),
+            ivars: vec![
+                ("@name".to_owned(), string(name)),
+                ("@requirement".to_owned(), requirement(constraints)),
+                ("@type".to_owned(), Value::Symbol(kind.to_owned())),
+                ("@prerelease".to_owned(), Value::Bool(false)),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_release_from_gemspec() {
+        let spec = Value::Array(vec![
+            string("3.5.0"),
+            Value::Int(4),
+            string("mygem"),
+            version("1.2.0"),
+            Value::Other,
+            string("A gem"),
+            requirement(&[(">=", "3.1")]),
+            requirement(&[(">=", "0")]),
+            string("ruby"),
+            Value::Array(vec![
+                dependency("rack", "runtime", &[("~>", "3.0"), (">=", "3.0.1")]),
+                dependency("rspec", "development", &[(">=", "0")]),
+            ]),
+        ]);
+        let entry = IndexEntry {
+            name: "mygem".to_owned(),
+            version: "1.2.0".to_owned(),
+            platform: "ruby".to_owned(),
+        };
+
+        let release = release_from_gemspec(&spec, &entry).unwrap();
+        assert_eq!(release.full_name(), "1.2.0");
+        assert_eq!(release.deps.len(), 1);
+        assert_eq!(release.deps[0].name, "rack");
+        assert_eq!(
+            release.deps[0].requirement,
+            Requirement::new(vec!["~> 3.0", ">= 3.0.1"]).unwrap()
+        );
+        assert_eq!(release.metadata.ruby, Requirement::parse(">= 3.1").unwrap());
+        assert!(release.metadata.checksum.is_empty());
+    }
+
+    #[test]
+    fn test_index_entry_full_name() {
+        let entry = IndexEntry {
+            name: "nokogiri".to_owned(),
+            version: "1.19.0".to_owned(),
+            platform: "x86_64-linux".to_owned(),
+        };
+        assert_eq!(entry.full_name(), "nokogiri-1.19.0-x86_64-linux");
+    }
+}
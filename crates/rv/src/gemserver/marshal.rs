@@ -0,0 +1,480 @@
+//! A reader for Ruby's Marshal format (version 4.8), which gem servers that predate the compact
+//! index use for their dependency API and full index. Only the data is read: objects come back
+//! as their class name and instance variables, since there's no Ruby to load them into.
+//!
+//! See <https://docs.ruby-lang.org/en/master/marshal_rdoc.html> for the format.
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum Error {
+    #[error("Unsupported Marshal version {0}.{1}")]
+    UnsupportedVersion(u8, u8),
+    #[error("Unexpected end of Marshal data")]
+    UnexpectedEnd,
+    #[error("Unsupported Marshal type {0:?}")]
+    UnsupportedType(char),
+    #[error("Marshal data refers to a {0} that hasn't been read")]
+    BadLink(&'static str),
+    #[error("Marshal data is nested too deeply")]
+    TooDeep,
+    #[error("Marshal data is too large once its links are expanded")]
+    TooLarge,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Deeper than any gem server response needs, but shallow enough not to overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+/// How many times larger than its input the values read from Marshal data can be. Links to
+/// values that were already read are copied, so without a limit, a few kilobytes of values
+/// that each link to the previous one twice would expand exponentially.
+const MAX_EXPANSION: usize = 64;
+
+/// A value read from Marshal data.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    /// Strings are bytes, because Ruby strings don't have to be UTF-8.
+    String(Vec<u8>),
+    Symbol(String),
+    Array(Vec<Value>),
+    Hash(Vec<(Value, Value)>),
+    /// A plain object, e.g. a `Gem::Dependency`.
+    Object {
+        class: String,
+        ivars: Vec<(String, Value)>,
+    },
+    /// An object dumped with `_dump`, e.g. a `Gem::Specification` or `Time`, whose data is
+    /// whatever that method returned.
+    UserDefined {
+        class: String,
+        data: Vec<u8>,
+    },
+    /// An object dumped with `marshal_dump`, e.g. a `Gem::Version`.
+    UserMarshal {
+        class: String,
+        data: Box<Value>,
+    },
+    /// Classes, modules, regexps and other values that gem servers never need.
+    Other,
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(bytes) => std::str::from_utf8(bytes).ok(),
+            Value::Symbol(symbol) => Some(symbol),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Look up `key` in a hash, where keys are symbols or strings.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Hash(pairs) => pairs
+                .iter()
+                .find(|(k, _)| k.as_str() == Some(key))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Look up an instance variable of an object, e.g. `@name`.
+    pub fn ivar(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Object { ivars, .. } => ivars.iter().find(|(k, _)| k == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+/// Read a single value from Marshal data.
+pub fn load(bytes: &[u8]) -> Result<Value> {
+    let mut reader = Reader {
+        bytes,
+        pos: 0,
+        symbols: Vec::new(),
+        objects: Vec::new(),
+        depth: 0,
+        size: 0,
+        max_size: bytes.len().saturating_mul(MAX_EXPANSION),
+    };
+    let major = reader.byte()?;
+    let minor = reader.byte()?;
+    if (major, minor) != (4, 8) {
+        return Err(Error::UnsupportedVersion(major, minor));
+    }
+    reader.value()
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    symbols: Vec<String>,
+    /// Every value that can be linked to later with `@`, in the order they were started, with
+    /// its size. Values still being read are None.
+    objects: Vec<Option<(Value, usize)>>,
+    depth: usize,
+    /// The size of everything read so far, counting each value and each byte of its strings,
+    /// including the copies made for links.
+    size: usize,
+    max_size: usize,
+}
+
+/// A value that can be linked to, which is being read.
+struct Started {
+    index: usize,
+    size: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8> {
+        let byte = *self.bytes.get(self.pos).ok_or(Error::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        let end = self.pos.checked_add(len).ok_or(Error::UnexpectedEnd)?;
+        let bytes = self.bytes.get(self.pos..end).ok_or(Error::UnexpectedEnd)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// Marshal's variable-length integers.
+    fn int(&mut self) -> Result<i64> {
+        let first = self.byte()? as i8;
+        Ok(match first {
+            0 => 0,
+            5..=127 => i64::from(first) - 5,
+            -128..=-5 => i64::from(first) + 5,
+            1..=4 => {
+                let mut n = 0i64;
+                for i in 0..first {
+                    n |= i64::from(self.byte()?) << (8 * i);
+                }
+                n
+            }
+            -4..=-1 => {
+                let mut n = -1i64;
+                for i in 0..-first {
+                    n &= !(0xff << (8 * i));
+                    n |= i64::from(self.byte()?) << (8 * i);
+                }
+                n
+            }
+        })
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        usize::try_from(self.int()?).map_err(|_| Error::UnexpectedEnd)
+    }
+
+    /// Count `size` towards the size of everything read, failing once it's too large.
+    fn grow(&mut self, size: usize) -> Result<()> {
+        self.size = self.size.saturating_add(size);
+        if self.size > self.max_size {
+            return Err(Error::TooLarge);
+        }
+        Ok(())
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>> {
+        let len = self.len()?;
+        self.grow(len)?;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    /// A symbol, or a link to one that was already read.
+    fn symbol(&mut self) -> Result<String> {
+        match self.byte()? {
+            b':' => self.new_symbol(),
+            b';' => {
+                let index = self.len()?;
+                let symbol = self
+                    .symbols
+                    .get(index)
+                    .cloned()
+                    .ok_or(Error::BadLink("symbol"))?;
+                self.grow(symbol.len())?;
+                Ok(symbol)
+            }
+            other => Err(Error::UnsupportedType(other as char)),
+        }
+    }
+
+    fn new_symbol(&mut self) -> Result<String> {
+        let symbol = String::from_utf8_lossy(&self.bytes()?).into_owned();
+        self.symbols.push(symbol.clone());
+        Ok(symbol)
+    }
+
+    /// Reserve a slot for a value that can be linked to.
+    fn start_object(&mut self) -> Started {
+        self.objects.push(None);
+        Started {
+            index: self.objects.len() - 1,
+            size: self.size,
+        }
+    }
+
+    fn finish_object(&mut self, started: Started, value: Value) -> Value {
+        let size = self.size - started.size;
+        self.objects[started.index] = Some((value.clone(), size));
+        value
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(Error::TooDeep);
+        }
+        self.grow(1)?;
+        let value = self.value_inner();
+        self.depth -= 1;
+        value
+    }
+
+    fn value_inner(&mut self) -> Result<Value> {
+        let value = match self.byte()? {
+            b'0' => Value::Nil,
+            b'T' => Value::Bool(true),
+            b'F' => Value::Bool(false),
+            b'i' => Value::Int(self.int()?),
+            b':' => Value::Symbol(self.new_symbol()?),
+            b';' => {
+                self.pos -= 1;
+                Value::Symbol(self.symbol()?)
+            }
+            b'@' => {
+                let index = self.len()?;
+                let (value, size) = self
+                    .objects
+                    .get(index)
+                    .and_then(Option::as_ref)
+                    .ok_or(Error::BadLink("object"))?;
+                let (value, size) = (value.clone(), *size);
+                self.grow(size)?;
+                value
+            }
+            b'"' => {
+                let started = self.start_object();
+                let bytes = self.bytes()?;
+                self.finish_object(started, Value::String(bytes))
+            }
+            b'f' => {
+                let started = self.start_object();
+                let float = match String::from_utf8_lossy(&self.bytes()?).as_ref() {
+                    "inf" => f64::INFINITY,
+                    "-inf" => f64::NEG_INFINITY,
+                    "nan" => f64::NAN,
+                    other => other.parse().unwrap_or(f64::NAN),
+                };
+                self.finish_object(started, Value::Float(float))
+            }
+            b'l' => {
+                // Bignums are only used for huge integers, which gem servers don't send.
+                let started = self.start_object();
+                self.byte()?;
+                let len = self.len()?;
+                self.take(len.saturating_mul(2))?;
+                self.finish_object(started, Value::Other)
+            }
+            b'[' => {
+                let started = self.start_object();
+                let len = self.len()?;
+                let mut values = Vec::new();
+                for _ in 0..len {
+                    values.push(self.value()?);
+                }
+                self.finish_object(started, Value::Array(values))
+            }
+            tag @ (b'{' | b'}') => {
+                let started = self.start_object();
+                let len = self.len()?;
+                let mut pairs = Vec::new();
+                for _ in 0..len {
+                    let key = self.value()?;
+                    let value = self.value()?;
+                    pairs.push((key, value));
+                }
+                if tag == b'}' {
+                    // The hash's default value.
+                    self.value()?;
+                }
+                self.finish_object(started, Value::Hash(pairs))
+            }
+            b'o' => {
+                let started = self.start_object();
+                let class = self.symbol()?;
+                let ivars = self.ivars()?;
+                self.finish_object(started, Value::Object { class, ivars })
+            }
+            b'u' => {
+                // It's only linkable once its data is read, but that data is part of its size.
+                let size = self.size;
+                let class = self.symbol()?;
+                let data = self.bytes()?;
+                let started = Started {
+                    size,
+                    ..self.start_object()
+                };
+                self.finish_object(started, Value::UserDefined { class, data })
+            }
+            b'U' => {
+                let started = self.start_object();
+                let class = self.symbol()?;
+                let data = Box::new(self.value()?);
+                self.finish_object(started, Value::UserMarshal { class, data })
+            }
+            b'I' => {
+                // A value with instance variables, usually a string with its encoding.
+                let value = self.value()?;
+                self.ivars()?;
+                value
+            }
+            b'e' | b'C' => {
+                // A value extended with a module, or an instance of a subclass of a core class.
+                self.symbol()?;
+                self.value()?
+            }
+            b'/' => {
+                let started = self.start_object();
+                self.bytes()?;
+                self.byte()?;
+                self.finish_object(started, Value::Other)
+            }
+            b'c' | b'm' | b'M' => {
+                let started = self.start_object();
+                self.bytes()?;
+                self.finish_object(started, Value::Other)
+            }
+            b'S' => {
+                let started = self.start_object();
+                self.symbol()?;
+                self.ivars()?;
+                self.finish_object(started, Value::Other)
+            }
+            other => return Err(Error::UnsupportedType(other as char)),
+        };
+        Ok(value)
+    }
+
+    fn ivars(&mut self) -> Result<Vec<(String, Value)>> {
+        let len = self.len()?;
+        let mut ivars = Vec::new();
+        for _ in 0..len {
+            let name = self.symbol()?;
+            let value = self.value()?;
+            ivars.push((name, value));
+        }
+        Ok(ivars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_ints() {
+        // Marshal.dump of each integer.
+        for (bytes, expected) in [
+            (&b"\x04\x08i\x00"[..], 0),
+            (b"\x04\x08i\x06", 1),
+            (b"\x04\x08i\x7f", 122),
+            (b"\x04\x08i\x01\x7b", 123),
+            (b"\x04\x08i\x02\x00\x01", 256),
+            (b"\x04\x08i\xfa", -1),
+            (b"\x04\x08i\x80", -123),
+            (b"\x04\x08i\xff\x84", -124),
+            (b"\x04\x08i\xfe\x00\xff", -256),
+        ] {
+            assert_eq!(load(bytes), Ok(Value::Int(expected)), "{bytes:?}");
+        }
+    }
+
+    #[test]
+    fn test_dependency_api_response() {
+        // Marshal.dump([{name: "rack", number: "1.0", platform: "ruby", dependencies: [["a", ">= 1"]]}])
+        let bytes = b"\x04\x08[\x06{\x09:\x09nameI\"\x09rack\x06:\x06ET:\x0bnumberI\"\x081.0\x06;\x06T:\x0dplatformI\"\x09ruby\x06;\x06T:\x11dependencies[\x06[\x07I\"\x06a\x06;\x06TI\"\x09>= 1\x06;\x06T";
+        let value = load(bytes).unwrap();
+        let release = &value.as_array().unwrap()[0];
+        assert_eq!(release.get("name"), Some(&string("rack")));
+        assert_eq!(release.get("number"), Some(&string("1.0")));
+        assert_eq!(release.get("platform"), Some(&string("ruby")));
+        assert_eq!(
+            release.get("dependencies"),
+            Some(&Value::Array(vec![Value::Array(vec![
+                string("a"),
+                string(">= 1")
+            ])]))
+        );
+    }
+
+    #[test]
+    fn test_objects_and_links() {
+        // v = Gem::Version.new("1.0"); Marshal.dump([v, v, Gem::Dependency.allocate])
+        // with the dependency's ivars trimmed to @name.
+        let bytes = b"\x04\x08[\x08U:\x11Gem::Version[\x06I\"\x081.0\x06:\x06ET@\x06o:\x14Gem::Dependency\x06:\x0a@nameI\"\x09rake\x06;\x06T";
+        let value = load(bytes).unwrap();
+        let values = value.as_array().unwrap();
+        let version = Value::UserMarshal {
+            class: "Gem::Version".to_owned(),
+            data: Box::new(Value::Array(vec![string("1.0")])),
+        };
+        assert_eq!(values[0], version);
+        assert_eq!(values[1], version);
+        assert_eq!(values[2].ivar("@name"), Some(&string("rake")));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(load(b"\x04\x07i\x00"), Err(Error::UnsupportedVersion(4, 7)));
+        assert_eq!(load(b"\x04\x08[\x07i\x00"), Err(Error::UnexpectedEnd));
+        assert_eq!(load(b"\x04\x08@\x06"), Err(Error::BadLink("object")));
+        assert_eq!(load(b"\x04\x08X"), Err(Error::UnsupportedType('X')));
+        let deep = [&b"\x04\x08"[..], &[b'[', 6].repeat(100)].concat();
+        assert_eq!(load(&deep), Err(Error::TooDeep));
+    }
+
+    #[test]
+    fn test_links_cant_expand_exponentially() {
+        // [s = "x" * 100, a1 = [s, s], a2 = [a1, a1], ...], where a40 would have 2**40 copies
+        // of s.
+        let mut bytes = b"\x04\x08[".to_vec();
+        bytes.push(5 + 41);
+        bytes.extend(b"\"\x69");
+        bytes.extend([b'x'; 100]);
+        for i in 1..=40 {
+            bytes.extend([b'[', 7, b'@', 5 + i, b'@', 5 + i]);
+        }
+        assert_eq!(load(&bytes), Err(Error::TooLarge));
+
+        // A few levels are fine.
+        let mut bytes = b"\x04\x08[".to_vec();
+        bytes.push(5 + 4);
+        bytes.extend(b"\"\x69");
+        bytes.extend([b'x'; 100]);
+        for i in 1..=3 {
+            bytes.extend([b'[', 7, b'@', 5 + i, b'@', 5 + i]);
+        }
+        let value = load(&bytes).unwrap();
+        let a3 = &value.as_array().unwrap()[3];
+        assert_eq!(a3.as_array().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,342 @@
+//! Looking up gems on several gem servers at once, like a Gemfile with more than one `source`.
+//!
+//! Every gem's releases come from exactly one server. Gems pinned to a server, with a
+//! `source "..." do` block or the `source:` option, are only looked up there. Other gems are
+//! looked up on every global source, and on the server of the gem that depends on them. If
+//! such a gem is found on more than one of those servers with different releases, it's an
+//! error rather than a guess, because otherwise anyone could publish a gem with the same name
+//! as a private one to a public server and have it installed instead ("dependency confusion").
+//! Servers with the same releases, like a private server that proxies rubygems.org, aren't
+//! ambiguous, and the gem comes from a global source like Bundler would take it from. Only
+//! checksums can show that releases are the same, so servers without them always are.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use futures_util::{StreamExt, stream::FuturesUnordered};
+use rv_gem_types::VersionPlatform;
+use rv_ruby::version::RubyVersion;
+use tracing::debug;
+use url::Url;
+
+use super::{Error, GemName, GemRelease, Gemserver, Result};
+use crate::config::{Config, credentials::redact};
+
+/// Several gem servers, and which gems come from which of them.
+#[derive(Default)]
+pub struct GemSources {
+    servers: Vec<Gemserver>,
+    /// Servers that gems not pinned anywhere else are looked up on.
+    global: Vec<usize>,
+    /// Gems that must come from one particular server.
+    pinned: HashMap<GemName, usize>,
+    // Maps gem names to their dependency lists.
+    pub gems_to_deps: HashMap<GemName, HashMap<VersionPlatform, GemRelease>>,
+    /// The server each gem in `gems_to_deps` came from.
+    origins: HashMap<GemName, usize>,
+}
+
+impl GemSources {
+    /// Look up gems that aren't pinned to a server on `url`.
+    pub async fn add_global(&mut self, config: &Config, url: Url) -> Result<()> {
+        let server = self.server_index(config, url).await?;
+        if !self.global.contains(&server) {
+            self.global.push(server);
+        }
+        Ok(())
+    }
+
+    /// Only look up `gem` on `url`.
+    pub async fn pin(&mut self, config: &Config, gem: &str, url: Url) -> Result<()> {
+        let server = self.server_index(config, url).await?;
+        self.pinned.insert(gem.to_owned(), server);
+        Ok(())
+    }
+
+    pub fn is_pinned(&self, gem: &str) -> bool {
+        self.pinned.contains_key(gem)
+    }
+
+    /// Every server, in the order they were added.
+    pub fn servers(&self) -> impl Iterator<Item = &Gemserver> {
+        self.servers.iter()
+    }
+
+    /// The server `gem`'s releases came from.
+    pub fn source_of(&self, gem: &str) -> Option<&Gemserver> {
+        self.origins.get(gem).map(|&server| &self.servers[server])
+    }
+
+    /// All releases of `gem`, from the one server it's available on.
+    pub async fn releases_for_gem(&self, gem: &str) -> Result<(&Gemserver, Vec<GemRelease>)> {
+        let candidates = self.candidates(gem, None);
+        let mut found = Vec::new();
+        for server in candidates {
+            if let Some(releases) = self.servers[server].releases(gem).await? {
+                found.push((server, releases));
+            }
+        }
+        let (server, releases) = self.only_one(gem, found)?;
+        Ok((&self.servers[server], releases))
+    }
+
+    /// Remember that the releases of `gem` came from `url`, e.g. after [`Self::releases_for_gem`].
+    pub fn insert(&mut self, gem: GemName, url: &Url, releases: Vec<GemRelease>) {
+        let server = self
+            .servers
+            .iter()
+            .position(|server| &server.url == url)
+            .expect("gems are only inserted from known servers");
+        self.origins.insert(gem.clone(), server);
+        self.gems_to_deps.insert(
+            gem,
+            releases
+                .into_iter()
+                .map(|release| (release.version_platform.clone(), release))
+                .collect(),
+        );
+    }
+
+    /// Look up every gem `root` depends on, directly or not. `root` came from the server at
+    /// `from`, if any, which its dependencies are looked up on too.
+    pub async fn add_transitive_deps(
+        &mut self,
+        root: &GemRelease,
+        from: Option<&Url>,
+        ruby_to_use: &RubyVersion,
+    ) -> Result<()> {
+        debug!("Querying all transitive dependencies");
+        let from = from.and_then(|url| self.servers.iter().position(|s| &s.url == url));
+        let ruby = rv_version::Version::from(ruby_to_use);
+
+        // Every server each gem was found on, with the releases there that work with our Ruby.
+        let mut found: HashMap<GemName, Vec<(usize, Vec<GemRelease>)>> = HashMap::new();
+        let mut seen = HashSet::new();
+        let mut in_flight = FuturesUnordered::new();
+
+        let servers = &self.servers;
+        let queue = |gem: &str, from: Option<usize>, seen: &mut HashSet<(GemName, usize)>| {
+            self.candidates(gem, from)
+                .into_iter()
+                .filter(|&server| seen.insert((gem.to_owned(), server)))
+                .map(|server| {
+                    let gem = gem.to_owned();
+                    debug!("Queuing {gem} from {}", redact(&servers[server].url));
+                    async move {
+                        debug!("Fetching {gem}");
+                        let releases = servers[server].releases(&gem).await;
+                        (gem, server, releases)
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for dep in &root.deps {
+            in_flight.extend(queue(&dep.name, from, &mut seen));
+        }
+
+        // Keep fetching new dependencies we discover.
+        while let Some((gem, server, releases)) = in_flight.next().await {
+            let Some(releases) = releases? else {
+                continue;
+            };
+            let new_deps: BTreeSet<&str> = releases
+                .iter()
+                .flat_map(|release| release.deps.iter().map(|dep| dep.name.as_str()))
+                .collect();
+            for dep in new_deps {
+                in_flight.extend(queue(dep, Some(server), &mut seen));
+            }
+
+            // Skip possible versions that are incompatible with our
+            // chosen Ruby version.
+            // We should filter these out now, so that we minimize the number
+            // of deps that PubGrub has to consider.
+            let candidate_versions = releases
+                .into_iter()
+                .filter(|release| release.metadata.ruby.satisfied_by(&ruby))
+                .collect();
+            found
+                .entry(gem)
+                .or_default()
+                .push((server, candidate_versions));
+        }
+        drop(in_flight);
+
+        // Anything that was looked up but not found anywhere.
+        let mut missing: Vec<&(GemName, usize)> = seen
+            .iter()
+            .filter(|(gem, _)| !found.contains_key(gem))
+            .collect();
+        missing.sort();
+        if let Some((gem, _)) = missing.first() {
+            let looked_on: Vec<usize> = missing
+                .iter()
+                .filter(|(name, _)| name == gem)
+                .map(|(_, server)| *server)
+                .collect();
+            return Err(self.not_found(gem, &looked_on));
+        }
+
+        for (gem, found_on) in found {
+            let (server, releases) = self.only_one(&gem, found_on)?;
+            let url = self.servers[server].url.clone();
+            self.insert(gem, &url, releases);
+        }
+        debug!("Retrieved all transitive deps.");
+        Ok(())
+    }
+
+    /// Which servers `gem` should be looked up on, if a gem from `from` depends on it.
+    fn candidates(&self, gem: &str, from: Option<usize>) -> Vec<usize> {
+        if let Some(&server) = self.pinned.get(gem) {
+            return vec![server];
+        }
+        let mut candidates = self.global.clone();
+        if let Some(from) = from
+            && !candidates.contains(&from)
+        {
+            candidates.push(from);
+        }
+        candidates
+    }
+
+    /// Check that `gem` was found with the same releases on every server it was found on, and
+    /// pick the one it comes from, preferring global sources.
+    fn only_one(
+        &self,
+        gem: &str,
+        mut found: Vec<(usize, Vec<GemRelease>)>,
+    ) -> Result<(usize, Vec<GemRelease>)> {
+        let Some((_, first)) = found.first() else {
+            return Err(self.not_found(gem, &self.candidates(gem, None)));
+        };
+        if !found
+            .iter()
+            .all(|(_, releases)| same_releases(first, releases))
+        {
+            let servers: Vec<usize> = found.iter().map(|(server, _)| *server).collect();
+            return Err(Error::AmbiguousGem {
+                gem: gem.to_owned(),
+                sources: self.describe(&servers),
+            });
+        }
+        let preferred = found
+            .iter()
+            .position(|(server, _)| self.global.contains(server))
+            .unwrap_or(0);
+        Ok(found.swap_remove(preferred))
+    }
+
+    /// The error for `gem` not being on any of `servers`. Offline, that only means it isn't in
+    /// their cached indexes.
+    fn not_found(&self, gem: &str, servers: &[usize]) -> Error {
+        let gem = gem.to_owned();
+        let sources = self.describe(servers);
+        if servers.iter().any(|&server| self.servers[server].offline) {
+            Error::NotCached { gem, sources }
+        } else {
+            Error::GemNotFound { gem, sources }
+        }
+    }
+
+    /// The URLs of `servers`, safe to show to the user.
+    fn describe(&self, servers: &[usize]) -> Vec<String> {
+        let mut urls: Vec<String> = servers
+            .iter()
+            .map(|&server| redact(&self.servers[server].url).to_string())
+            .collect();
+        urls.sort();
+        urls
+    }
+
+    async fn server_index(&mut self, config: &Config, url: Url) -> Result<usize> {
+        let server = Gemserver::new(config, url).await?;
+        if let Some(index) = self.servers.iter().position(|s| s.url == server.url) {
+            return Ok(index);
+        }
+        self.servers.push(server);
+        Ok(self.servers.len() - 1)
+    }
+}
+
+/// Whether two servers have the same releases of a gem, i.e. the same versions and platforms,
+/// with the same dependencies and checksums. A release without a checksum could be anything, so
+/// it's never the same as another.
+fn same_releases(a: &[GemRelease], b: &[GemRelease]) -> bool {
+    let by_version = |releases: &[GemRelease]| -> HashMap<VersionPlatform, &GemRelease> {
+        releases
+            .iter()
+            .map(|release| (release.version_platform.clone(), release))
+            .collect()
+    };
+    let (a, b) = (by_version(a), by_version(b));
+    a.len() == b.len()
+        && a.iter().all(|(version, release)| {
+            b.get(version).is_some_and(|other| {
+                !release.metadata.checksum.is_empty()
+                    && release.metadata.checksum == other.metadata.checksum
+                    && release.deps.iter().collect::<BTreeSet<_>>()
+                        == other.deps.iter().collect::<BTreeSet<_>>()
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gemserver::Metadata;
+    use rv_gem_types::{ProjectDependency, Requirement};
+
+    fn release(version: &str, checksum: &[u8]) -> GemRelease {
+        GemRelease {
+            version_platform: version.parse().unwrap(),
+            deps: Vec::new(),
+            metadata: Metadata {
+                checksum: checksum.to_vec(),
+                ..Metadata::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_same_releases() {
+        let public = [release("1.1.0", b"a"), release("1.2.0", b"b")];
+
+        // A proxy, in any order.
+        assert!(same_releases(
+            &public,
+            &[release("1.2.0", b"b"), release("1.1.0", b"a")]
+        ));
+        // A server without checksums, which could be serving anything.
+        assert!(!same_releases(
+            &public,
+            &[release("1.1.0", b""), release("1.2.0", b"")]
+        ));
+        assert!(!same_releases(
+            &[release("1.1.0", b"")],
+            &[release("1.1.0", b"")]
+        ));
+        // The same checksums, but different dependencies.
+        let mut with_deps = release("1.1.0", b"a");
+        with_deps.deps.push(ProjectDependency {
+            name: "evil".to_owned(),
+            requirement: Requirement::default(),
+        });
+        assert!(!same_releases(&[release("1.1.0", b"a")], &[with_deps]));
+        // A different gem with the same name and versions.
+        assert!(!same_releases(
+            &public,
+            &[release("1.1.0", b"a"), release("1.2.0", b"c")]
+        ));
+        // A different gem with a newer version, to win the resolution.
+        assert!(!same_releases(
+            &public,
+            &[
+                release("1.1.0", b"a"),
+                release("1.2.0", b"b"),
+                release("99.0.0", b"d")
+            ]
+        ));
+        assert!(!same_releases(&public, &[release("1.1.0", b"a")]));
+    }
+}
@@ -50,6 +50,23 @@ impl FilesystemStorage {
         Self { base_path }
     }
 
+    /// Lock the whole storage, so that a blob and its metadata are always read and written
+    /// together, even by several rv processes sharing the cache.
+    async fn lock(&self, shared: bool) -> Result<rv_cache::CacheLock> {
+        let mut lock_path = self.base_path.clone().into_os_string();
+        lock_path.push(".lock");
+        let lock = tokio::task::spawn_blocking(move || {
+            if shared {
+                rv_cache::CacheLock::shared(lock_path)
+            } else {
+                rv_cache::CacheLock::exclusive(lock_path)
+            }
+        })
+        .await
+        .map_err(std::io::Error::other)??;
+        Ok(lock)
+    }
+
     fn resolve_path(&self, key: &str) -> PathBuf {
         self.base_path.join(key)
     }
@@ -68,27 +85,12 @@ impl FilesystemStorage {
     }
 
     async fn write(&self, key: &str, content: &[u8]) -> Result<()> {
-        let path = self.resolve_path(key);
-
-        // Create parent directory if needed
-        if let Some(parent) = path.parent() {
-            tokio::fs::create_dir_all(parent).await?;
-        }
-
-        Ok(tokio::fs::write(&path, content).await?)
+        write_atomic(self.resolve_path(key), content.to_vec()).await
     }
 
     async fn write_metadata_file(&self, key: &str, metadata: &MetadataFile) -> Result<()> {
-        let path = self.metadata_path(key);
-
-        // Create metadata directory if needed
-        if let Some(parent) = path.parent() {
-            tokio::fs::create_dir_all(parent).await?;
-        }
-
         let json = serde_json::to_string_pretty(metadata)?;
-
-        Ok(tokio::fs::write(&path, json).await?)
+        write_atomic(self.metadata_path(key), json.into_bytes()).await
     }
 
     async fn read_metadata_file(&self, key: &str) -> Option<MetadataFile> {
@@ -106,6 +108,15 @@ impl FilesystemStorage {
     }
 }
 
+/// Write `content` to `path` without blocking, so readers never see it half-written.
+async fn write_atomic(path: PathBuf, content: Vec<u8>) -> Result<()> {
+    let path = camino::Utf8PathBuf::try_from(path).map_err(std::io::Error::other)?;
+    tokio::task::spawn_blocking(move || rv_cache::write_atomic(path, content))
+        .await
+        .map_err(std::io::Error::other)??;
+    Ok(())
+}
+
 #[async_trait]
 impl Storage for FilesystemStorage {
     async fn exists(&self, key: &str) -> bool {
@@ -113,6 +124,7 @@ impl Storage for FilesystemStorage {
     }
 
     async fn read_blob(&self, key: &str) -> Result<Blob> {
+        let _lock = self.lock(true).await?;
         let content = self.read(key).await?;
         let metadata_file = self.read_metadata_file(key).await;
 
@@ -124,6 +136,7 @@ impl Storage for FilesystemStorage {
     }
 
     async fn write_blob(&self, key: &str, blob: &Blob) -> Result<()> {
+        let _lock = self.lock(false).await?;
         self.write(key, &blob.content).await?;
 
         let metadata = MetadataFile {
@@ -0,0 +1,78 @@
+//! The compact index's `versions` file, which lists every gem on a server along with the MD5 of
+//! its `info/` file. The server only ever appends to it, so keeping a cached copy up to date is
+//! a single small range request. After that, every cached `info/` file whose MD5 still matches
+//! is known to be current without asking the server about it.
+
+use std::collections::HashMap;
+
+use md5::{Digest, Md5};
+
+/// The MD5 of each gem's `info/` file, as listed in a `versions` file.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Versions {
+    info_checksums: HashMap<String, String>,
+}
+
+impl Versions {
+    /// Parses a `versions` file, which looks like this:
+    ///
+    /// ```text
+    /// created_at: 2024-04-01T00:00:05Z
+    /// ---
+    /// rack 1.0.0,1.1.0 0ad6f7a6d1b5f4b1e1b3c1d0e5b7c8a9
+    /// rails 7.1.0,-7.0.0 3e5b0f2a7c9d8e1f4a6b2c0d9e8f7a6b
+    /// rack 3.1.0 d0e5b7c8a90ad6f7a6d1b5f4b1e1b3c1
+    /// ```
+    ///
+    /// Gems are listed again whenever they change, and the last line for a gem is current.
+    pub fn parse(body: &str) -> Self {
+        let info_checksums = body
+            .lines()
+            .skip_while(|line| *line != "---")
+            .skip(1)
+            .filter_map(|line| {
+                let mut fields = line.split(' ');
+                let name = fields.next()?;
+                let _versions = fields.next()?;
+                let checksum = fields.next()?;
+                Some((name.to_owned(), checksum.to_owned()))
+            })
+            .collect();
+        Self { info_checksums }
+    }
+
+    /// Is `info` the current contents of `gem`'s `info/` file?
+    pub fn is_current(&self, gem: &str, info: &[u8]) -> bool {
+        self.info_checksums
+            .get(gem)
+            .is_some_and(|checksum| *checksum == hex::encode(Md5::digest(info)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let info = b"---\n1.0.0 |checksum:abc\n";
+        let checksum = hex::encode(Md5::digest(info));
+        let body = format!(
+            "created_at: 2024-04-01T00:00:05Z\n---\nrack 1.0.0 00000000000000000000000000000000\nrails 7.1.0,-7.0.0 {checksum}\nrack 1.0.0,1.1.0 {checksum}\n"
+        );
+
+        let versions = Versions::parse(&body);
+        assert_eq!(versions.info_checksums.len(), 2);
+        // The last line for a gem wins.
+        assert!(versions.is_current("rack", info));
+        assert!(versions.is_current("rails", info));
+        assert!(!versions.is_current("rails", b"---\n"));
+        assert!(!versions.is_current("missing", info));
+    }
+
+    #[test]
+    fn test_parse_ignores_header() {
+        let versions = Versions::parse("created_at: 2024-04-01T00:00:05Z\nfoo 1.0 abc\n");
+        assert_eq!(versions, Versions::default());
+    }
+}
@@ -10,8 +10,10 @@ use tokio::main;
 use tracing_indicatif::IndicatifLayer;
 use tracing_subscriber::{EnvFilter, layer::SubscriberExt as _, util::SubscriberInitExt as _};
 
+pub mod checksum;
 pub mod commands;
 pub mod config;
+pub mod gemfile;
 pub mod gemserver;
 pub mod output_format;
 pub mod progress;
@@ -22,6 +24,7 @@ pub mod update;
 
 use crate::commands::cache::{CacheCommandArgs, cache};
 use crate::commands::clean_install::{CleanInstallArgs, ci};
+use crate::commands::lock::{LockArgs, lock};
 use crate::commands::ruby::{RubyArgs, ruby};
 use crate::commands::run::{RunArgs, run};
 use crate::commands::self_cmd::{SelfArgs, self_cmd};
@@ -85,9 +88,13 @@ struct Cli {
     #[arg(long, env = "RV_COLOR")]
     color: Option<ColorMode>,
 
-    /// Run rv in offline mode if possible
-    /// TODO: Hide until really apply offline mode in all parts of rv.
-    #[arg(long, hide = true, global = true)]
+    /// Don't access the network, only use what's already in rv's cache
+    #[arg(
+        long,
+        global = true,
+        value_parser = clap::builder::BoolishValueParser::new(),
+        env = "RV_OFFLINE"
+    )]
     offline: bool,
 
     #[command(flatten)]
@@ -117,6 +124,8 @@ enum Commands {
     Shell(ShellArgs),
     #[command(about = "Clean install from a Gemfile.lock", visible_alias = "ci")]
     CleanInstall(CleanInstallArgs),
+    #[command(about = "Resolve a Gemfile into a Gemfile.lock")]
+    Lock(LockArgs),
     #[command(
         name = "self",
         about = "Manage rv itself",
@@ -187,6 +196,9 @@ pub enum Error {
     #[error(transparent)]
     CiError(#[from] commands::clean_install::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
+    LockError(#[from] commands::lock::Error),
+    #[error(transparent)]
     RunError(#[from] commands::ruby::run::Error),
     #[error(transparent)]
     ScriptRunError(#[from] commands::run::Error),
@@ -304,7 +316,8 @@ async fn run_cmd(global_args: &GlobalArgs, command: Commands) -> Result<()> {
     match command {
         Commands::Ruby(ruby_args) => ruby(global_args, ruby_args).await?,
         Commands::CleanInstall(ci_args) => ci(global_args, ci_args).await?,
-        Commands::Cache(cache_args) => cache(global_args, cache_args)?,
+        Commands::Lock(lock_args) => lock(global_args, lock_args).await?,
+        Commands::Cache(cache_args) => cache(global_args, cache_args).await?,
         Commands::SelfCmd(self_args) => self_cmd(global_args, self_args).await?,
         Commands::Shell(shell_args) => shell(global_args, &mut Cli::command(), shell_args)?,
         Commands::Tool(tool_args) => tool(global_args, tool_args).await?,
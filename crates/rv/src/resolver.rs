@@ -1,8 +1,8 @@
 use std::collections::HashMap;
 
-use rv_gem_types::{ReleaseTuple, VersionPlatform};
+use rv_gem_types::{Platform, ProjectDependency, ReleaseTuple, VersionPlatform};
 
-use super::gemserver::{GemName, GemRelease};
+use super::gemserver::{GemName, GemRelease, Metadata};
 
 use pubgrub::Ranges;
 
@@ -32,6 +32,39 @@ pub fn solve(
         .collect())
 }
 
+/// Name of the virtual package that depends on everything in a Gemfile.
+/// It contains a space, so it can never clash with a real gem name.
+pub const GEMFILE_ROOT: &str = "your Gemfile";
+
+/// Like [`solve`], but resolves every dependency of a Gemfile at once, instead of a single gem.
+/// The virtual root package is not included in the solution.
+pub fn solve_gemfile(
+    deps: Vec<ProjectDependency>,
+    mut gem_info: HashMap<GemName, HashMap<VersionPlatform, GemRelease>>,
+) -> Result<Vec<(ReleaseTuple, GemRelease)>, ResolutionError> {
+    let root = gemfile_root(deps);
+    gem_info.insert(
+        GEMFILE_ROOT.to_owned(),
+        [(root.version_platform.clone(), root.clone())].into(),
+    );
+
+    let mut solution = solve(GEMFILE_ROOT.to_owned(), root, gem_info)?;
+    solution.retain(|(release_tuple, _)| release_tuple.name != GEMFILE_ROOT);
+    Ok(solution)
+}
+
+/// A fake gem release which depends on everything the Gemfile depends on.
+pub fn gemfile_root(deps: Vec<ProjectDependency>) -> GemRelease {
+    GemRelease {
+        version_platform: VersionPlatform {
+            version: "0".parse().expect("0 is a valid version"),
+            platform: Platform::Ruby,
+        },
+        deps,
+        metadata: Metadata::default(),
+    }
+}
+
 /// Build a PubGrub "dependency provider", i.e. something that can be queried
 /// with all the information of a GemServer (which gems are available, what versions that gem has,
 /// and what dependencies that gem-version pair has).
@@ -0,0 +1,204 @@
+use crate::common::RvTest;
+
+#[test]
+fn test_cache_warm_then_install_offline() {
+    let mut test = RvTest::new();
+    test.enable_cache();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    let info_mock = test
+        .mock_request("GET", "info/test-gem")
+        .with_body(format!("---\n1.0.0 |checksum:{}\n", "a".repeat(64)))
+        .expect(1)
+        .create();
+    let gem_mock = test
+        .mock_gem_download("test-gem-1.0.0.gem")
+        .expect(1)
+        .create();
+    let ruby_mock = test.mock_ruby_download("3.4.5").expect(1).create();
+
+    let manifest = test.temp_root().join("manifest.json");
+    let output = test.rv(&[
+        "cache",
+        "warm",
+        "--lockfile",
+        "Gemfile.lock",
+        "--ruby",
+        "3.4.5",
+        "--manifest",
+        manifest.as_str(),
+    ]);
+    output.assert_success();
+    output.assert_stdout_contains("Cached 3 files in");
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs_err::read_to_string(manifest).unwrap()).unwrap();
+    let entries: Vec<(&str, &str)> = manifest
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| {
+            assert!(
+                fs_err::exists(entry["path"].as_str().unwrap()).unwrap(),
+                "{entry} should be cached"
+            );
+            (
+                entry["kind"].as_str().unwrap(),
+                entry["name"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            ("ruby", "3.4.5"),
+            ("index", "test-gem"),
+            ("gem", "test-gem-1.0.0")
+        ]
+    );
+
+    // Nothing was installed.
+    assert!(!test.current_dir().join("app").exists());
+
+    // Everything can now be installed without the network.
+    test.ci(&["--offline"]).assert_success();
+    test.rv(&["--offline", "ruby", "install", "3.4.5"])
+        .assert_success();
+
+    info_mock.assert();
+    gem_mock.assert();
+    ruby_mock.assert();
+}
+
+#[test]
+fn test_cache_export_then_import_elsewhere() {
+    let mut test = RvTest::new();
+    test.enable_cache();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    test.mock_request("GET", "info/test-gem")
+        .with_body(format!("---\n1.0.0 |checksum:{}\n", "a".repeat(64)))
+        .create();
+    let gem_mock = test
+        .mock_gem_download("test-gem-1.0.0.gem")
+        .expect(1)
+        .create();
+    let ruby_mock = test.mock_ruby_download("3.4.5").expect(1).create();
+
+    let bundle = test.temp_root().join("bundle.tar.zst");
+    let output = test.rv(&[
+        "cache",
+        "export",
+        "--lockfile",
+        "Gemfile.lock",
+        "--ruby",
+        "3.4.5",
+        "-o",
+        bundle.as_str(),
+    ]);
+    output.assert_success();
+    output.assert_stdout_contains("Exported 3 files in");
+
+    // Another machine, with an empty cache.
+    let other_cache = test.temp_root().join("other-cache");
+    test.env.insert("RV_CACHE_DIR".into(), other_cache.into());
+    let output = test.rv(&["cache", "import", bundle.as_str()]);
+    output.assert_success();
+    output.assert_stdout_contains("Imported 3 files in");
+
+    test.ci(&["--offline"]).assert_success();
+    test.rv(&["--offline", "ruby", "install", "3.4.5"])
+        .assert_success();
+
+    gem_mock.assert();
+    ruby_mock.assert();
+}
+
+#[test]
+fn test_cache_import_rejects_invalid_bundle() {
+    let mut test = RvTest::new();
+    let cache_dir = test.enable_cache();
+
+    let bundle = test.temp_root().join("bundle.tar.zst");
+    fs_err::write(&bundle, "not a bundle").unwrap();
+
+    let output = test.rv(&["cache", "import", bundle.as_str()]);
+    output.assert_failure();
+    output.assert_stderr_contains("InvalidBundle");
+    assert_eq!(fs_err::read_dir(cache_dir).unwrap().count(), 0);
+}
+
+#[test]
+fn test_cache_prune_max_size_keeps_lockfile() {
+    let mut test = RvTest::new();
+    let cache_dir = test.enable_cache();
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    test.mock_request("GET", "info/test-gem")
+        .with_body(format!("---\n1.0.0 |checksum:{}\n", "a".repeat(64)))
+        .create();
+    test.mock_gem_download("test-gem-1.0.0.gem").create();
+    test.mock_ruby_download("3.4.5").create();
+
+    test.rv(&[
+        "cache",
+        "warm",
+        "--lockfile",
+        "Gemfile.lock",
+        "--ruby",
+        "3.4.5",
+    ])
+    .assert_success();
+    let gems = cache_dir.join("gem-v0/gems");
+    let tarballs = cache_dir.join("ruby-v0/tarballs");
+    assert_eq!(fs_err::read_dir(&gems).unwrap().count(), 1);
+    assert_eq!(fs_err::read_dir(&tarballs).unwrap().count(), 1);
+
+    // Everything the lockfile needs stays, however small the cache has to be.
+    let output = test.rv(&[
+        "cache",
+        "prune",
+        "--max-size",
+        "0B",
+        "--keep-lockfile",
+        "Gemfile.lock",
+    ]);
+    output.assert_success();
+    output.assert_stdout_contains("Removed");
+    assert_eq!(fs_err::read_dir(&gems).unwrap().count(), 1);
+    assert!(
+        cache_dir
+            .join("gemdeps-v0/compact_index")
+            .read_dir()
+            .unwrap()
+            .next()
+            .is_some()
+    );
+    assert_eq!(fs_err::read_dir(&tarballs).unwrap().count(), 0);
+
+    test.rv(&["cache", "prune", "--max-size", "0B"])
+        .assert_success();
+    assert_eq!(fs_err::read_dir(&gems).unwrap().count(), 0);
+
+    // Buckets stay, even once all their entries are evicted.
+    assert!(cache_dir.join("gemdeps-v0").exists());
+}
+
+#[test]
+fn test_cache_prune_rejects_invalid_max_age() {
+    let test = RvTest::new();
+    let output = test.rv(&["cache", "prune", "--max-age", "30 days"]);
+    output.assert_failure();
+    output.assert_stderr_contains("expected a number followed by s, m, h, d or w");
+}
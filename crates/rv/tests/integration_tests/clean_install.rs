@@ -26,6 +26,86 @@ fn test_clean_install_download_test_gem() {
     mock.assert();
 }
 
+#[test]
+fn test_clean_install_offline_uses_cached_gems() {
+    let mut test = RvTest::new();
+    test.enable_cache();
+
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    let mock = test
+        .mock_gem_download("test-gem-1.0.0.gem")
+        .expect(1)
+        .create();
+
+    let output = test.ci(&["--offline"]);
+    output.assert_failure();
+    output.assert_stderr_contains("NotCached");
+    output.assert_stderr_contains("test-gem-1.0.0");
+
+    test.ci(&[]).assert_success();
+    test.ci(&["--offline", "--force"]).assert_success();
+
+    mock.assert();
+}
+
+#[test]
+fn test_clean_install_downloads_from_rv_mirror() {
+    let mut test = RvTest::new();
+
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    let config = format!(
+        "rv {{\n  mirror {{\n    \"http://gems.example.com\" \"{}/mirror\"\n  }}\n}}\n",
+        test.server_url()
+    );
+    fs_err::write(test.current_dir().join("rv.kdl"), config).unwrap();
+
+    let path = format!(
+        "mirror/{}",
+        test.gem_package_download_path("test-gem-1.0.0.gem")
+    );
+    let content = fs_err::read("../rv-gem-package/tests/fixtures/test-gem-1.0.0.gem").unwrap();
+    let mock = test.mock_tarball_download(&path, &content).create();
+
+    let output = test.ci(&[]);
+
+    output.assert_success();
+    mock.assert();
+}
+
+#[test]
+fn test_clean_install_downloads_from_bundler_mirror() {
+    let mut test = RvTest::new();
+
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.env.insert(
+        "BUNDLE_MIRROR__GEMS__EXAMPLE__COM".into(),
+        format!("{}/mirror", test.server_url()),
+    );
+
+    let path = format!(
+        "mirror/{}",
+        test.gem_package_download_path("test-gem-1.0.0.gem")
+    );
+    let content = fs_err::read("../rv-gem-package/tests/fixtures/test-gem-1.0.0.gem").unwrap();
+    let mock = test.mock_tarball_download(&path, &content).create();
+
+    let output = test.ci(&[]);
+
+    output.assert_success();
+    mock.assert();
+}
+
 #[test]
 fn test_clean_install_input_validation() {
     let mut test = RvTest::new();
@@ -330,6 +410,24 @@ fn test_clean_install_failed_rakefile_extension() {
     mock.assert();
 }
 
+#[test]
+fn test_clean_install_keep_going_summarizes_failures() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.rake-ext-fails");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.rake-ext-fails.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    let mock = test.mock_gem_download("rake-ext-fails-0.1.gem").create();
+
+    let output = test.ci(&["--keep-going"]);
+    output.assert_failure();
+    output.assert_stderr_contains("Could not compile native extensions for 1 gem:");
+    output.assert_stderr_contains("rake-ext-fails-0.1");
+    mock.assert();
+}
+
 /// Find the unpacked gem directory under BUNDLE_PATH.
 /// Gems are installed to `<cwd>/app/ruby/<version>/gems/<gem-full-name>/`.
 fn find_gem_dir(cwd: &std::path::Path, gem_full_name: &str) -> camino::Utf8PathBuf {
@@ -367,3 +465,90 @@ fn find_all_files_in_dir(cwd: &std::path::Path) -> String {
     lines.sort();
     lines.join("\n")
 }
+
+#[test]
+fn test_clean_install_without_groups() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.groups");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.groups.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    let test_gem = test.mock_gem_download("test-gem-1.0.0.gem").create();
+    let development_gem = test
+        .mock_gem_download("symlink-test-1.0.0.gem")
+        .expect(0)
+        .create();
+
+    let output = test.ci(&["--without", "development"]);
+    output.assert_success();
+    test_gem.assert();
+    development_gem.assert();
+}
+
+#[test]
+fn test_clean_install_without_groups_from_named_gemfile() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.groups");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.groups.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+    let dir = test.current_dir();
+    fs_err::rename(dir.join("Gemfile"), dir.join("gems.rb")).unwrap();
+    fs_err::rename(dir.join("Gemfile.lock"), dir.join("gems.rb.lock")).unwrap();
+
+    let test_gem = test.mock_gem_download("test-gem-1.0.0.gem").create();
+    let development_gem = test
+        .mock_gem_download("symlink-test-1.0.0.gem")
+        .expect(0)
+        .create();
+
+    let output = test.ci(&["--gemfile", "gems.rb", "--without", "development"]);
+    output.assert_success();
+    test_gem.assert();
+    development_gem.assert();
+}
+
+#[test]
+fn test_clean_install_respects_bundle_without() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.groups");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.groups.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+    test.env
+        .insert("BUNDLE_WITHOUT".into(), "development:test".into());
+
+    let test_gem = test.mock_gem_download("test-gem-1.0.0.gem").create();
+    let development_gem = test
+        .mock_gem_download("symlink-test-1.0.0.gem")
+        .expect(0)
+        .create();
+
+    test.ci(&[]).assert_success();
+    test_gem.assert();
+    development_gem.assert();
+}
+
+#[test]
+fn test_clean_install_only_groups() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.groups");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.groups.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    let default_gem = test
+        .mock_gem_download("test-gem-1.0.0.gem")
+        .expect(0)
+        .create();
+    let development_gem = test.mock_gem_download("symlink-test-1.0.0.gem").create();
+
+    test.ci(&["--only", "development"]).assert_success();
+    default_gem.assert();
+    development_gem.assert();
+}
@@ -314,6 +314,103 @@ impl RvTest {
             .create()
     }
 
+    /// Mocks the JRuby /releases endpoint, with a binary distribution of each version.
+    pub fn mock_jruby_releases(&mut self, versions: Vec<&str>) -> Mock {
+        use indoc::formatdoc;
+
+        self.env.insert(
+            "RV_JRUBY_LIST_URL".into(),
+            format!("{}/{}", self.server_url(), "repos/jruby/jruby/releases"),
+        );
+
+        let releases: Vec<String> = versions
+            .iter()
+            .map(|v| {
+                formatdoc!(
+                    r#"
+            {{
+                "name": "JRuby {v}",
+                "assets": [
+                    {{
+                        "name": "jruby-dist-{v}-bin.tar.gz",
+                        "browser_download_url": "http://..."
+                    }}
+                ]
+            }}"#
+                )
+            })
+            .collect();
+
+        let body = format!("[{}]", releases.join(",\n"));
+
+        self.mock_request("GET", "repos/jruby/jruby/releases")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(body)
+            .create()
+    }
+
+    /// Mock a JRuby binary distribution download for testing
+    pub fn mock_jruby_download(&mut self, version: &str) -> Mock {
+        let path = format!("jruby-dist/{version}/jruby-dist-{version}-bin.tar.gz");
+        let content = self.create_mock_jruby_tarball(version);
+        self.mock_tarball_download(&path, &content)
+    }
+
+    /// Mocks the TruffleRuby /releases endpoint, with linux-amd64 and macos-aarch64
+    /// standalones of each version.
+    pub fn mock_truffleruby_releases(&mut self, versions: Vec<&str>) -> Mock {
+        use indoc::formatdoc;
+
+        self.env.insert(
+            "RV_TRUFFLERUBY_LIST_URL".into(),
+            format!(
+                "{}/{}",
+                self.server_url(),
+                "repos/truffleruby/truffleruby/releases"
+            ),
+        );
+
+        let releases: Vec<String> = versions
+            .iter()
+            .map(|v| {
+                formatdoc!(
+                    r#"
+            {{
+                "name": "TruffleRuby {v}",
+                "assets": [
+                    {{
+                        "name": "truffleruby-community-{v}-linux-amd64.tar.gz",
+                        "browser_download_url": "http://..."
+                    }},
+                    {{
+                        "name": "truffleruby-community-{v}-macos-aarch64.tar.gz",
+                        "browser_download_url": "http://..."
+                    }}
+                ]
+            }}"#
+                )
+            })
+            .collect();
+
+        let body = format!("[{}]", releases.join(",\n"));
+
+        self.mock_request("GET", "repos/truffleruby/truffleruby/releases")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(body)
+            .create()
+    }
+
+    /// Mock a linux-amd64 TruffleRuby standalone download for testing
+    pub fn mock_truffleruby_download(&mut self, version: &str) -> Mock {
+        let path = format!(
+            "truffleruby-dist/graal-{version}/truffleruby-community-{version}-linux-amd64.tar.gz"
+        );
+        let content = self.create_mock_truffleruby_tarball(version);
+        self.mock_tarball_download(&path, &content)
+    }
+
     /// Mock a ruby tarball download for testing
     pub fn mock_ruby_download(&mut self, version: &str) -> Mock {
         let path = self.ruby_tarball_download_path(version);
@@ -383,6 +480,29 @@ impl RvTest {
     }
 
     pub fn create_mock_tarball(&self, version: &str) -> Vec<u8> {
+        let root = format!("ruby-{version}");
+        self.mock_tarball(&root, "ruby", self.ruby_executable_name(), version)
+    }
+
+    /// A JRuby binary distribution, whose launcher is `bin/jruby`.
+    pub fn create_mock_jruby_tarball(&self, version: &str) -> Vec<u8> {
+        let root = format!("jruby-{version}");
+        self.mock_tarball(&root, "jruby", "jruby", version)
+    }
+
+    /// A TruffleRuby standalone for linux-amd64.
+    pub fn create_mock_truffleruby_tarball(&self, version: &str) -> Vec<u8> {
+        let root = format!("truffleruby-community-{version}-linux-amd64");
+        self.mock_tarball(&root, "truffleruby", self.ruby_executable_name(), version)
+    }
+
+    fn mock_tarball(
+        &self,
+        root: &str,
+        engine: &str,
+        executable_name: &str,
+        version: &str,
+    ) -> Vec<u8> {
         use flate2::Compression;
         use flate2::write::GzEncoder;
         use std::io::Write;
@@ -392,7 +512,7 @@ impl RvTest {
         {
             let mut builder = Builder::new(&mut archive_data);
 
-            let root = format!("ruby-{version}/");
+            let root = format!("{root}/");
             let mut dir_header = tar::Header::new_gnu();
             dir_header.set_path(&root).unwrap();
             dir_header.set_size(0);
@@ -410,10 +530,9 @@ impl RvTest {
             builder.append(&bin_dir_header, std::io::empty()).unwrap();
 
             let mut ruby_header = tar::Header::new_gnu();
-            let ruby_executable_name = self.ruby_executable_name();
-            let ruby_content = &self.ruby_mock_script("ruby", version);
+            let ruby_content = &self.ruby_mock_script(engine, version);
             ruby_header
-                .set_path(format!("{root}bin/{ruby_executable_name}"))
+                .set_path(format!("{root}bin/{executable_name}"))
                 .unwrap();
             ruby_header.set_size(ruby_content.len() as u64);
             ruby_header.set_mode(0o755);
@@ -435,6 +554,44 @@ impl RvTest {
         gz_data
     }
 
+    /// A Ruby source release, whose `./configure` writes a Makefile that installs a mock Ruby.
+    #[cfg(unix)]
+    pub fn create_mock_ruby_source_tarball(&self, version: &str) -> Vec<u8> {
+        use flate2::Compression;
+        use flate2::write::GzEncoder;
+
+        let configure = indoc::indoc! {r#"
+            #!/bin/sh
+            for arg in "$@"; do
+              case "$arg" in
+                --prefix=*) prefix="${arg#--prefix=}" ;;
+              esac
+            done
+            printf 'all:\n\t@echo built\ninstall:\n\tmkdir -p %s/bin\n\tcp ruby %s/bin/ruby\n' "$prefix" "$prefix" > Makefile
+        "#};
+        let ruby = self.ruby_mock_script("ruby", version);
+
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let mut dir_header = tar::Header::new_gnu();
+        dir_header.set_path(format!("ruby-{version}/")).unwrap();
+        dir_header.set_size(0);
+        dir_header.set_mode(0o755);
+        dir_header.set_entry_type(tar::EntryType::Directory);
+        dir_header.set_cksum();
+        builder.append(&dir_header, std::io::empty()).unwrap();
+
+        for (name, content) in [("configure", configure), ("ruby", ruby.as_str())] {
+            let mut header = tar::Header::new_gnu();
+            header.set_path(format!("ruby-{version}/{name}")).unwrap();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append(&header, content.as_bytes()).unwrap();
+        }
+
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
     pub fn ruby_tarball_url(&self, version: &str) -> String {
         format!(
             "{}/{}",
@@ -576,6 +733,19 @@ impl RvTest {
                 "repos/oneclick/rubyinstaller2/releases"
             ),
         );
+        // Most tests don't care about JRuby, so don't list any. See `mock_jruby_releases()`.
+        self.env.insert("RV_JRUBY_LIST_URL".into(), "-".into());
+        self.env.insert(
+            "RV_JRUBY_INSTALL_URL".into(),
+            format!("{}/{}", self.server_url(), "jruby-dist"),
+        );
+        // Likewise TruffleRuby. See `mock_truffleruby_releases()`.
+        self.env
+            .insert("RV_TRUFFLERUBY_LIST_URL".into(), "-".into());
+        self.env.insert(
+            "RV_TRUFFLERUBY_INSTALL_URL".into(),
+            format!("{}/{}", self.server_url(), "truffleruby-dist"),
+        );
 
         // Override the rubies directory so rv looks in the test temp dir.
         // On Windows, etcetera resolves data_dir via the Win32 SHGetKnownFolderPath
@@ -0,0 +1,516 @@
+use indoc::formatdoc;
+use md5::Digest;
+use rv_gem_types::Platform;
+
+use crate::common::{RvOutput, RvTest};
+
+impl RvTest {
+    pub fn lock(&mut self, args: &[&str]) -> RvOutput {
+        self.rv(&[&["lock"], args].concat())
+    }
+
+    pub fn write_gemfile(&self, contents: &str) {
+        fs_err::write(self.current_dir().join("Gemfile"), contents).unwrap();
+    }
+}
+
+#[test]
+fn test_lock_writes_gemfile_lock() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.write_gemfile(&formatdoc! {r#"
+        source "{}"
+
+        gem "indirect", "~> 1.1"
+
+        group :development do
+          gem "alba"
+        end
+    "#, test.gemserver_url()});
+
+    let indirect_mock = test.mock_info_endpoint("indirect").create();
+    let alba_mock = test.mock_info_endpoint("alba").create();
+
+    let output = test.lock(&[]);
+    output.assert_success();
+    output.assert_stdout_contains("Locked 2 gems in");
+
+    indirect_mock.assert();
+    alba_mock.assert();
+
+    let lockfile = fs_err::read_to_string(test.current_dir().join("Gemfile.lock")).unwrap();
+    assert_eq!(
+        lockfile,
+        formatdoc! {"
+            GEM
+              remote: {}/
+              specs:
+                alba (3.10.0)
+                indirect (1.2.0)
+
+            PLATFORMS
+              {}
+
+            DEPENDENCIES
+              alba
+              indirect (~> 1.1)
+
+            CHECKSUMS
+              alba (3.10.0) sha256=52769d2328da35c4f1bbcfe96b3931b9c8595667307a902573868b1cf6d65d79
+              indirect (1.2.0) sha256=db84552fdc9b5d67dd64227ab60a05201554085c00ca5973ec96605af25edc73
+        ", test.gemserver_url(), Platform::local()}
+    );
+}
+
+#[test]
+fn test_lock_respects_version_requirements() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.write_gemfile(&formatdoc! {r#"
+        source "{}"
+        gem "indirect", "< 1.2"
+    "#, test.gemserver_url()});
+
+    test.mock_info_endpoint("indirect").create();
+
+    test.lock(&[]).assert_success();
+
+    let lockfile = fs_err::read_to_string(test.current_dir().join("Gemfile.lock")).unwrap();
+    assert!(lockfile.contains("    indirect (1.1.0)\n"), "{lockfile}");
+    assert!(lockfile.contains("  indirect (< 1.2)\n"), "{lockfile}");
+}
+
+#[test]
+fn test_lock_requires_a_source() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.write_gemfile("gem \"indirect\"\n");
+
+    let output = test.lock(&[]);
+    output.assert_failure();
+    assert_eq!(output.normalized_stderr(), "Error: LockError(NoSource)\n");
+}
+
+/// A gem on the private source at `/private/`, which depends on `indirect`.
+const PRIVATE_GEM_INFO: &str = "---
+1.0.0 indirect:>= 1.0|checksum:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa
+";
+
+#[test]
+fn test_lock_with_scoped_source() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.write_gemfile(&formatdoc! {r#"
+        source "{0}"
+
+        gem "alba"
+
+        source "{0}/private" do
+          gem "secret"
+        end
+    "#, test.gemserver_url()});
+
+    let alba_mock = test.mock_info_endpoint("alba").create();
+    let indirect_mock = test.mock_info_endpoint("indirect").create();
+    let secret_mock = test
+        .mock_request("GET", "private/info/secret")
+        .with_body(PRIVATE_GEM_INFO)
+        .create();
+    // Dependencies of a gem from a scoped source are looked up there too.
+    let private_indirect_mock = test
+        .mock_request("GET", "private/info/indirect")
+        .with_status(404)
+        .create();
+    // The global source is never asked for pinned gems.
+    let public_secret_mock = test.mock_request("GET", "info/secret").expect(0).create();
+
+    let output = test.lock(&[]);
+    output.assert_success();
+    output.assert_stdout_contains("Locked 3 gems in");
+
+    alba_mock.assert();
+    indirect_mock.assert();
+    secret_mock.assert();
+    private_indirect_mock.assert();
+    public_secret_mock.assert();
+
+    let lockfile = fs_err::read_to_string(test.current_dir().join("Gemfile.lock")).unwrap();
+    assert_eq!(
+        lockfile,
+        formatdoc! {"
+            GEM
+              remote: {0}/
+              specs:
+                alba (3.10.0)
+                indirect (1.2.0)
+
+            GEM
+              remote: {0}/private/
+              specs:
+                secret (1.0.0)
+                  indirect (>= 1.0)
+
+            PLATFORMS
+              {1}
+
+            DEPENDENCIES
+              alba
+              secret!
+
+            CHECKSUMS
+              alba (3.10.0) sha256=52769d2328da35c4f1bbcfe96b3931b9c8595667307a902573868b1cf6d65d79
+              indirect (1.2.0) sha256=db84552fdc9b5d67dd64227ab60a05201554085c00ca5973ec96605af25edc73
+              secret (1.0.0) sha256=aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa
+        ", test.gemserver_url(), Platform::local()}
+    );
+}
+
+#[test]
+fn test_lock_refuses_gems_found_on_several_sources() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.write_gemfile(&formatdoc! {r#"
+        source "{0}"
+
+        gem "secret", source: "{0}/private"
+    "#, test.gemserver_url()});
+
+    test.mock_request("GET", "private/info/secret")
+        .with_body(PRIVATE_GEM_INFO)
+        .create();
+    // Someone published a gem with the same name as a private one to the public server.
+    test.mock_info_endpoint("indirect").create();
+    test.mock_request("GET", "private/info/indirect")
+        .with_body("---\n1.0.0 |checksum:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\n")
+        .create();
+
+    let output = test.lock(&[]);
+    output.assert_failure();
+    output.assert_stderr_contains(&format!(
+        "AmbiguousGem {{ gem: \"indirect\", sources: [\"{0}/\", \"{0}/private/\"] }}",
+        test.gemserver_url()
+    ));
+    assert!(!test.current_dir().join("Gemfile.lock").exists());
+}
+
+#[test]
+fn test_lock_refuses_gems_on_a_server_without_checksums() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.write_gemfile(&formatdoc! {r#"
+        source "{0}"
+
+        gem "secret", source: "{0}/private"
+    "#, test.gemserver_url()});
+
+    test.mock_request("GET", "private/info/secret")
+        .with_body(PRIVATE_GEM_INFO)
+        .create();
+    test.mock_info_endpoint("indirect").create();
+    // The same versions, but without checksums there's no telling if they're the same gem.
+    test.mock_request("GET", "private/info/indirect")
+        .with_body("---\n1.2.0 |\n1.1.0 |\n")
+        .create();
+
+    let output = test.lock(&[]);
+    output.assert_failure();
+    output.assert_stderr_contains(&format!(
+        "AmbiguousGem {{ gem: \"indirect\", sources: [\"{0}/\", \"{0}/private/\"] }}",
+        test.gemserver_url()
+    ));
+    assert!(!test.current_dir().join("Gemfile.lock").exists());
+}
+
+#[test]
+fn test_lock_takes_gems_on_a_proxy_from_the_global_source() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.write_gemfile(&formatdoc! {r#"
+        source "{0}"
+
+        gem "secret", source: "{0}/private"
+    "#, test.gemserver_url()});
+
+    test.mock_request("GET", "private/info/secret")
+        .with_body(PRIVATE_GEM_INFO)
+        .create();
+    // The private server proxies the public one, so it has the same releases.
+    let public_mock = test.mock_info_endpoint("indirect").create();
+    let private_mock = test
+        .mock_request("GET", "private/info/indirect")
+        .with_body(fs_err::read("tests/fixtures/info-indirect-gem").unwrap())
+        .create();
+
+    test.lock(&[]).assert_success();
+    public_mock.assert();
+    private_mock.assert();
+
+    let lockfile = fs_err::read_to_string(test.current_dir().join("Gemfile.lock")).unwrap();
+    assert!(
+        lockfile.contains(&formatdoc! {"
+            GEM
+              remote: {0}/
+              specs:
+                indirect (1.2.0)
+
+            GEM
+              remote: {0}/private/
+              specs:
+                secret (1.0.0)
+                  indirect (>= 1.0)
+        ", test.gemserver_url()}),
+        "{lockfile}"
+    );
+}
+
+#[test]
+fn test_lock_skips_info_requests_the_versions_file_says_are_current() {
+    let mut test = RvTest::new();
+    test.enable_cache();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.write_gemfile(&formatdoc! {r#"
+        source "{}"
+        gem "indirect"
+        gem "alba"
+    "#, test.gemserver_url()});
+
+    // Nothing is cached yet, so the versions file isn't needed.
+    let versions_mock = test.mock_request("GET", "versions").expect(0).create();
+    let indirect_mock = test.mock_info_endpoint("indirect").expect(1).create();
+    let alba_mock = test.mock_info_endpoint("alba").expect(2).create();
+    test.lock(&[]).assert_success();
+    versions_mock.assert();
+    versions_mock.remove();
+
+    let md5 = |name: &str| {
+        let info = fs_err::read(format!("tests/fixtures/info-{name}-gem")).unwrap();
+        hex::encode(md5::Md5::digest(info))
+    };
+    // alba has changed since it was cached.
+    let versions_mock = test
+        .mock_request("GET", "versions")
+        .with_body(format!(
+            "created_at: 2024-04-01T00:00:05Z\n---\nindirect 1.1.0,1.2.0 {}\nalba 3.10.0 {}\nalba 3.10.0,3.11.0 00000000000000000000000000000000\n",
+            md5("indirect"),
+            md5("alba"),
+        ))
+        .expect(1)
+        .create();
+    test.lock(&[]).assert_success();
+
+    versions_mock.assert();
+    indirect_mock.assert();
+    alba_mock.assert();
+}
+
+#[test]
+fn test_lock_offline_uses_cached_index() {
+    let mut test = RvTest::new();
+    test.enable_cache();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.write_gemfile(&formatdoc! {r#"
+        source "{}"
+        gem "indirect"
+    "#, test.gemserver_url()});
+
+    // Nothing is cached yet.
+    let output = test.lock(&["--offline"]);
+    output.assert_failure();
+    output.assert_stderr_contains("NotCached");
+
+    let indirect_mock = test.mock_info_endpoint("indirect").expect(1).create();
+    test.lock(&[]).assert_success();
+    let lockfile = fs_err::read_to_string(test.current_dir().join("Gemfile.lock")).unwrap();
+
+    // Neither the info file nor the versions file are requested again.
+    let versions_mock = test.mock_request("GET", "versions").expect(0).create();
+    fs_err::remove_file(test.current_dir().join("Gemfile.lock")).unwrap();
+    test.lock(&["--offline"]).assert_success();
+    assert_eq!(
+        fs_err::read_to_string(test.current_dir().join("Gemfile.lock")).unwrap(),
+        lockfile
+    );
+
+    indirect_mock.assert();
+    versions_mock.assert();
+}
+
+#[test]
+fn test_lock_with_server_without_compact_index() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.write_gemfile(&formatdoc! {r#"
+        source "{}/legacy"
+        gem "rack"
+    "#, test.gemserver_url()});
+
+    let info_mock = test
+        .mock_request("GET", "legacy/info/rack")
+        .with_status(404)
+        .expect(1)
+        .create();
+    let versions_mock = test
+        .mock_request("GET", "legacy/versions")
+        .with_status(404)
+        .expect(1)
+        .create();
+    // Marshal.dump([{name: "rack", number: "1.0", platform: "ruby", dependencies: []}])
+    let dependencies_mock = test
+        .mock_request("GET", "legacy/api/v1/dependencies")
+        .match_query(mockito::Matcher::UrlEncoded("gems".into(), "rack".into()))
+        .with_body(&b"\x04\x08[\x06{\x09:\x09nameI\"\x09rack\x06:\x06ET:\x0bnumberI\"\x081.0\x06;\x06T:\x0dplatformI\"\x09ruby\x06;\x06T:\x11dependencies[\x00"[..])
+        .expect(1)
+        .create();
+
+    let output = test.lock(&[]);
+    output.assert_success();
+    output.assert_stdout_contains("Locked 1 gems in");
+
+    info_mock.assert();
+    versions_mock.assert();
+    dependencies_mock.assert();
+
+    // The dependency API doesn't give checksums, so rack is locked without one.
+    let lockfile = fs_err::read_to_string(test.current_dir().join("Gemfile.lock")).unwrap();
+    assert_eq!(
+        lockfile,
+        formatdoc! {"
+            GEM
+              remote: {0}/legacy/
+              specs:
+                rack (1.0)
+
+            PLATFORMS
+              {1}
+
+            DEPENDENCIES
+              rack
+
+            CHECKSUMS
+              rack (1.0)
+        ", test.gemserver_url(), Platform::local()}
+    );
+}
+
+#[test]
+fn test_lock_offline_with_server_without_compact_index() {
+    let mut test = RvTest::new();
+    test.enable_cache();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.write_gemfile(&formatdoc! {r#"
+        source "{}/legacy"
+        gem "rack"
+    "#, test.gemserver_url()});
+
+    test.mock_request("GET", "legacy/info/rack")
+        .with_status(404)
+        .create();
+    test.mock_request("GET", "legacy/versions")
+        .with_status(404)
+        .create();
+    // Marshal.dump([{name: "rack", number: "1.0", platform: "ruby", dependencies: []}])
+    let dependencies_mock = test
+        .mock_request("GET", "legacy/api/v1/dependencies")
+        .match_query(mockito::Matcher::UrlEncoded("gems".into(), "rack".into()))
+        .with_body(&b"\x04\x08[\x06{\x09:\x09nameI\"\x09rack\x06:\x06ET:\x0bnumberI\"\x081.0\x06;\x06T:\x0dplatformI\"\x09ruby\x06;\x06T:\x11dependencies[\x00"[..])
+        .expect(1)
+        .create();
+    test.lock(&[]).assert_success();
+    let lockfile = fs_err::read_to_string(test.current_dir().join("Gemfile.lock")).unwrap();
+
+    // The dependency API's response is read from the cache.
+    fs_err::remove_file(test.current_dir().join("Gemfile.lock")).unwrap();
+    test.lock(&["--offline"]).assert_success();
+    assert_eq!(
+        fs_err::read_to_string(test.current_dir().join("Gemfile.lock")).unwrap(),
+        lockfile
+    );
+
+    dependencies_mock.assert();
+}
+
+#[test]
+fn test_lock_missing_gemfile() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-4.0.1");
+
+    let output = test.lock(&[]);
+    output.assert_failure();
+    output.assert_stderr_contains("MissingGemfile");
+}
+
+const UNSORTED_LOCKFILE: &str = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    rake (13.3.0)
+    rack (3.2.3)
+
+PLATFORMS
+  ruby
+
+DEPENDENCIES
+  rack
+  rake
+
+BUNDLED WITH
+   2.7.2
+";
+
+#[test]
+fn test_lock_check_reports_violations() {
+    let mut test = RvTest::new();
+    let lockfile_path = test.current_dir().join("Gemfile.lock");
+    fs_err::write(&lockfile_path, UNSORTED_LOCKFILE).unwrap();
+
+    let output = test.lock(&["--check"]);
+    output.assert_failure();
+    output.assert_stderr_contains("UnsortedSpecs");
+
+    // Checking never changes the lockfile.
+    assert_eq!(
+        fs_err::read_to_string(&lockfile_path).unwrap(),
+        UNSORTED_LOCKFILE
+    );
+}
+
+#[test]
+fn test_lock_normalize_rewrites_lockfile() {
+    let mut test = RvTest::new();
+    let lockfile_path = test.current_dir().join("Gemfile.lock");
+    fs_err::write(&lockfile_path, UNSORTED_LOCKFILE).unwrap();
+
+    let output = test.lock(&["--normalize"]);
+    output.assert_success();
+    output.assert_stdout_contains("Normalized");
+
+    let normalized = UNSORTED_LOCKFILE.replace(
+        "    rake (13.3.0)\n    rack (3.2.3)\n",
+        "    rack (3.2.3)\n    rake (13.3.0)\n",
+    );
+    assert_eq!(fs_err::read_to_string(&lockfile_path).unwrap(), normalized);
+
+    let output = test.lock(&["--check"]);
+    output.assert_success();
+    output.assert_stdout_contains("is in canonical form");
+}
+
+#[test]
+fn test_lock_check_missing_lockfile() {
+    let mut test = RvTest::new();
+
+    let output = test.lock(&["--check"]);
+    output.assert_failure();
+    output.assert_stderr_contains("MissingLockfile");
+}
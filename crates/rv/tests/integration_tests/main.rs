@@ -1,5 +1,7 @@
+mod cache;
 mod clean_install;
 mod common;
+mod lock;
 mod ruby;
 mod run;
 mod shell;
@@ -1,4 +1,5 @@
 use crate::common::RvTest;
+use rv_platform::HostPlatform;
 use std::fs;
 
 #[test]
@@ -94,6 +95,81 @@ fn test_ruby_install_successful_download() {
     );
 }
 
+#[test]
+fn test_ruby_install_retries_server_errors() {
+    let mut test = RvTest::new();
+
+    let tarball_content = test.create_mock_tarball("3.4.5");
+    let download_path = test.ruby_tarball_download_path("3.4.5");
+    let unavailable_mock = test
+        .mock_request("GET", download_path.as_str())
+        .with_status(503)
+        .with_header("retry-after", "0")
+        .expect(1)
+        .create();
+    let ruby_mock = test
+        .mock_tarball_download(&download_path, &tarball_content)
+        .expect(1)
+        .create();
+
+    let cache_dir = test.enable_cache();
+
+    let output = test.rv(&["ruby", "install", "3.4.5"]);
+
+    unavailable_mock.assert();
+    ruby_mock.assert();
+    output.assert_success();
+
+    let cache_key = rv_cache::cache_digest(test.ruby_tarball_url("3.4.5"));
+    let tarball_path = cache_dir
+        .join("ruby-v0")
+        .join("tarballs")
+        .join(format!("{}.tar.gz", cache_key));
+    assert_eq!(fs::read(&tarball_path).unwrap(), tarball_content);
+}
+
+#[test]
+fn test_ruby_install_resumes_partial_download() {
+    let mut test = RvTest::new();
+
+    let tarball_content = test.create_mock_tarball("3.4.5");
+    let (downloaded, rest) = tarball_content.split_at(tarball_content.len() / 2);
+    let download_path = test.ruby_tarball_download_path("3.4.5");
+    let ruby_mock = test
+        .mock_request("GET", download_path.as_str())
+        .match_header("range", format!("bytes={}-", downloaded.len()).as_str())
+        .with_status(206)
+        .with_header(
+            "content-range",
+            &format!(
+                "bytes {}-{}/{}",
+                downloaded.len(),
+                tarball_content.len() - 1,
+                tarball_content.len()
+            ),
+        )
+        .with_body(rest)
+        .create();
+
+    let cache_dir = test.enable_cache();
+
+    // An earlier download was interrupted halfway through.
+    let cache_key = rv_cache::cache_digest(test.ruby_tarball_url("3.4.5"));
+    let tarballs_dir = cache_dir.join("ruby-v0").join("tarballs");
+    fs::create_dir_all(&tarballs_dir).unwrap();
+    let temp_path = tarballs_dir.join(format!("{}.tar.gz.tmp", cache_key));
+    fs::write(&temp_path, downloaded).unwrap();
+
+    let output = test.rv(&["ruby", "install", "3.4.5"]);
+
+    ruby_mock.assert();
+    output.assert_success();
+
+    let tarball_path = tarballs_dir.join(format!("{}.tar.gz", cache_key));
+    assert_eq!(fs::read(&tarball_path).unwrap(), tarball_content);
+    assert!(!temp_path.exists());
+}
+
 // The mock tarball contains a bash script (bin/ruby) that can't execute on Windows.
 #[test]
 fn test_ruby_install_from_tarball() {
@@ -111,6 +187,65 @@ fn test_ruby_install_from_tarball() {
     output.assert_stdout_contains("ruby\n3.4.5");
 }
 
+#[test]
+fn test_ruby_install_force_keeps_old_ruby_when_new_one_is_broken() {
+    use flate2::{Compression, write::GzEncoder};
+
+    let mut test = RvTest::new();
+
+    let tarball_file = test.mock_tarball_on_disk("3.4.5");
+    test.rv(&[
+        "ruby",
+        "install",
+        "--tarball-path",
+        tarball_file.as_str(),
+        "3.4.5",
+    ])
+    .assert_success();
+
+    // An archive with no Ruby executable in it.
+    let broken_tarball = test.temp_root().join("tmp/broken.tar.gz");
+    {
+        let file = fs::File::create(&broken_tarball).unwrap();
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        let mut dir_header = tar::Header::new_gnu();
+        dir_header.set_path("ruby-3.4.5/").unwrap();
+        dir_header.set_size(0);
+        dir_header.set_mode(0o755);
+        dir_header.set_entry_type(tar::EntryType::Directory);
+        dir_header.set_cksum();
+        builder.append(&dir_header, std::io::empty()).unwrap();
+
+        let mut header = tar::Header::new_gnu();
+        header.set_path("ruby-3.4.5/README").unwrap();
+        header.set_size(0);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, std::io::empty()).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    let output = test.rv(&[
+        "ruby",
+        "install",
+        "--force",
+        "--tarball-path",
+        broken_tarball.as_str(),
+        "3.4.5",
+    ]);
+    output.assert_failure();
+    output.assert_stderr_contains("InvalidRuby");
+
+    // The Ruby that was there before still works, and nothing half-installed is left behind.
+    let output = test.rv(&["run", "ruby"]);
+    output.assert_stdout_contains("ruby\n3.4.5");
+    let entries: Vec<_> = fs::read_dir(test.rubies_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(entries, ["ruby-3.4.5"]);
+}
+
 #[test]
 fn test_ruby_install_http_failure_no_empty_file() {
     let mut test = RvTest::new();
@@ -210,6 +345,87 @@ fn test_ruby_install_cached_file_reused() {
     mock.assert();
 }
 
+#[test]
+fn test_ruby_install_verifies_published_checksum() {
+    use sha2::{Digest, Sha256};
+
+    let mut test = RvTest::new();
+
+    let tarball_content = test.create_mock_tarball("3.4.5");
+    let download_path = test.ruby_tarball_download_path("3.4.5");
+    let ruby_mock = test
+        .mock_tarball_download(&download_path, &tarball_content)
+        .create();
+    let file_name = download_path.rsplit('/').next().unwrap();
+    let sums = format!(
+        "{}  {file_name}\n",
+        hex::encode(Sha256::digest(&tarball_content))
+    );
+    let sums_mock = test
+        .mock_request("GET", "latest/download/SHA256SUMS")
+        .with_status(200)
+        .with_body(sums)
+        .create();
+
+    let output = test.rv(&["ruby", "install", "3.4.5"]);
+
+    ruby_mock.assert();
+    sums_mock.assert();
+    output.assert_success();
+}
+
+#[test]
+fn test_ruby_install_checksum_mismatch_evicts_cached_archive() {
+    let mut test = RvTest::new();
+
+    let mock = test.mock_ruby_download("3.4.5").expect(1).create();
+    let cache_dir = test.enable_cache();
+    test.rv(&["ruby", "install", "3.4.5"]).assert_success();
+
+    let download_path = test.ruby_tarball_download_path("3.4.5");
+    let file_name = download_path.rsplit('/').next().unwrap();
+    let sums_mock = test
+        .mock_request("GET", "latest/download/SHA256SUMS")
+        .with_status(200)
+        .with_body(format!("{}  {file_name}\n", "0".repeat(64)))
+        .create();
+
+    let output = test.rv(&["ruby", "install", "3.4.5", "--force"]);
+    output.assert_failure();
+    output.assert_stderr_contains("ChecksumMismatch");
+
+    mock.assert();
+    sums_mock.assert();
+    let cache_key = rv_cache::cache_digest(test.ruby_tarball_url("3.4.5"));
+    let tarball_path = cache_dir
+        .join("ruby-v0")
+        .join("tarballs")
+        .join(format!("{}.tar.gz", cache_key));
+    assert!(!tarball_path.exists(), "Bad tarball should be evicted");
+}
+
+#[test]
+fn test_ruby_install_offline_uses_cached_archive() {
+    let mut test = RvTest::new();
+
+    let mock = test.mock_ruby_download("3.4.5").expect(1).create();
+
+    let _cache_dir = test.enable_cache();
+
+    // Nothing is cached yet, so there's nothing to install.
+    let output = test.rv(&["--offline", "ruby", "install", "3.4.5"]);
+    output.assert_failure();
+    output.assert_stderr_contains("NotCached");
+
+    test.rv(&["ruby", "install", "3.4.5"]).assert_success();
+
+    let output = test.rv(&["--offline", "ruby", "install", "3.4.5", "--force"]);
+    output.assert_success();
+    output.assert_stdout_contains("already exists, skipping download");
+
+    mock.assert();
+}
+
 #[test]
 fn test_ruby_install_skips_existing_version_and_suggests_force_flag() {
     let mut test = RvTest::new();
@@ -363,3 +579,228 @@ fn test_ruby_install_with_dev() {
         .join(format!("{}.tar.gz", cache_key));
     assert!(tarball_path.exists(), "Tarball should be cached");
 }
+
+#[cfg(unix)]
+#[test]
+fn test_ruby_install_jruby() {
+    let mut test = RvTest::new();
+
+    let jruby_mock = test.mock_jruby_download("10.0.0.0").create();
+
+    let output = test.rv(&["ruby", "install", "jruby-10.0.0.0"]);
+
+    jruby_mock.assert();
+    output.assert_success();
+    output.assert_stdout_contains(
+        "Installed Ruby version jruby-10.0.0.0 to /tmp/home/.local/share/rv/rubies",
+    );
+
+    // JRuby only ships `bin/jruby`, so `ruby` is linked to it.
+    let ruby = test.rubies_dir().join("jruby-10.0.0.0/bin/ruby");
+    assert!(
+        ruby.is_symlink(),
+        "{ruby} should link to the jruby launcher"
+    );
+    assert_eq!(fs::read_link(&ruby).unwrap(), std::path::Path::new("jruby"));
+
+    let output = test.rv(&["run", "--ruby", "jruby-10.0.0.0", "ruby"]);
+    output.assert_success();
+    output.assert_stdout_contains("jruby\n10.0.0.0");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ruby_install_jruby_partial_version() {
+    let mut test = RvTest::new();
+
+    let releases_mock = test.mock_jruby_releases(vec!["9.4.14.0", "10.0.0.0", "10.0.2.0"]);
+    let jruby_mock = test.mock_jruby_download("10.0.2.0").create();
+
+    let output = test.rv(&["ruby", "install", "jruby-10"]);
+
+    releases_mock.assert();
+    jruby_mock.assert();
+    output.assert_success();
+    output.assert_stdout_contains(
+        "Installed Ruby version jruby-10.0.2.0 to /tmp/home/.local/share/rv/rubies",
+    );
+
+    let entries: Vec<_> = fs::read_dir(test.rubies_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(entries, ["jruby-10.0.2.0"]);
+}
+
+#[test]
+fn test_ruby_install_partial_version_only_fetches_cruby_releases() {
+    let mut test = RvTest::new();
+
+    let releases_mock = test.mock_releases(["3.4.5"].to_vec());
+    let ruby_mock = test.mock_ruby_download("3.4.5").create();
+
+    test.env.insert(
+        "RV_JRUBY_LIST_URL".into(),
+        format!("{}/repos/jruby/jruby/releases", test.server_url()),
+    );
+    let jruby_releases_mock = test
+        .mock_request("GET", "repos/jruby/jruby/releases")
+        .expect(0)
+        .create();
+
+    let output = test.rv(&["ruby", "install", "3.4"]);
+
+    releases_mock.assert();
+    ruby_mock.assert();
+    jruby_releases_mock.assert();
+    output.assert_success();
+    output
+        .assert_stdout_contains("Installed Ruby version 3.4.5 to /tmp/home/.local/share/rv/rubies");
+}
+
+#[test]
+fn test_ruby_install_partial_version_skips_truffleruby_releases() {
+    let mut test = RvTest::new();
+    test.set_platform(HostPlatform::LinuxX86_64);
+
+    let releases_mock = test.mock_releases(["3.4.5"].to_vec());
+    let ruby_mock = test.mock_ruby_download("3.4.5").create();
+
+    test.env.insert(
+        "RV_TRUFFLERUBY_LIST_URL".into(),
+        format!(
+            "{}/repos/truffleruby/truffleruby/releases",
+            test.server_url()
+        ),
+    );
+    let truffleruby_releases_mock = test
+        .mock_request("GET", "repos/truffleruby/truffleruby/releases")
+        .expect(0)
+        .create();
+
+    let output = test.rv(&["ruby", "install", "3.4"]);
+
+    releases_mock.assert();
+    ruby_mock.assert();
+    truffleruby_releases_mock.assert();
+    output.assert_success();
+    output
+        .assert_stdout_contains("Installed Ruby version 3.4.5 to /tmp/home/.local/share/rv/rubies");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ruby_install_truffleruby() {
+    let mut test = RvTest::new();
+    test.set_platform(HostPlatform::LinuxX86_64);
+
+    let releases_mock = test.mock_truffleruby_releases(vec!["24.1.2", "24.2.1"]);
+    let truffleruby_mock = test.mock_truffleruby_download("24.2.1").create();
+
+    let output = test.rv(&["ruby", "install", "truffleruby-24"]);
+
+    releases_mock.assert();
+    truffleruby_mock.assert();
+    output.assert_success();
+    output.assert_stdout_contains(
+        "Installed Ruby version truffleruby-24.2.1 to /tmp/home/.local/share/rv/rubies",
+    );
+
+    let entries: Vec<_> = fs::read_dir(test.rubies_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(entries, ["truffleruby-24.2.1"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ruby_install_build_from_source() {
+    use sha2::{Digest, Sha256};
+
+    let mut test = RvTest::new();
+    let cache_dir = test.enable_cache();
+    test.env.insert(
+        "RV_RUBY_SOURCE_MIRROR".into(),
+        format!("{}/ruby-source", test.server_url()),
+    );
+
+    // There's no precompiled build of this Ruby.
+    let download_path = test.ruby_tarball_download_path("3.2.1");
+    let binary_mock = test
+        .mock_request("GET", &download_path)
+        .with_status(404)
+        .expect(3)
+        .create();
+
+    let source = test.create_mock_ruby_source_tarball("3.2.1");
+    let index = format!(
+        "name\turl\tsha1\tsha256\tsha512\nruby-3.2.1.tar.gz\thttps://example.com\tx\t{}\ty\n",
+        hex::encode(Sha256::digest(&source))
+    );
+    let source_mock = test
+        .mock_tarball_download("ruby-source/3.2/ruby-3.2.1.tar.gz", &source)
+        .expect(1)
+        .create();
+    let index_mock = test
+        .mock_request("GET", "ruby-source/index.txt")
+        .with_status(200)
+        .with_body(index)
+        .expect(1)
+        .create();
+
+    let output = test.rv(&["ruby", "install", "3.2.1"]);
+    output.assert_failure();
+
+    let output = test.rv(&["ruby", "install", "--build-from-source", "3.2.1"]);
+    output.assert_success();
+    output.assert_stdout_contains("Building Ruby 3.2.1 from source");
+    output.assert_stdout_contains("Installed Ruby version 3.2.1");
+    assert!(cache_dir.join("ruby-v0/builds/ruby-3.2.1.log").exists());
+
+    let output = test.rv(&["run", "--ruby", "3.2.1", "ruby"]);
+    output.assert_stdout_contains("ruby\n3.2.1");
+
+    // The built Ruby is cached, so it's installed again without building it.
+    fs::remove_dir_all(test.rubies_dir().join("ruby-3.2.1")).unwrap();
+    let output = test.rv(&["ruby", "install", "--build-from-source", "3.2.1"]);
+    output.assert_success();
+    output.assert_stdout_contains("was already built from source");
+
+    // The source was verified when it was downloaded, so it can be built again offline.
+    fs::remove_dir_all(test.rubies_dir().join("ruby-3.2.1")).unwrap();
+    for entry in fs::read_dir(cache_dir.join("ruby-v0/builds")).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "gz") {
+            fs::remove_file(path).unwrap();
+        }
+    }
+    let output = test.rv(&[
+        "--offline",
+        "ruby",
+        "install",
+        "--build-from-source",
+        "3.2.1",
+    ]);
+    output.assert_success();
+    output.assert_stdout_contains("Building Ruby 3.2.1 from source");
+
+    binary_mock.assert();
+    source_mock.assert();
+    index_mock.assert();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ruby_install_build_from_source_partial_version() {
+    let mut test = RvTest::new();
+
+    // There's no precompiled Ruby 3.2, so there's nothing to find its exact version in.
+    let releases_mock = test.mock_releases(["3.4.5"].to_vec());
+
+    let output = test.rv(&["ruby", "install", "--build-from-source", "3.2"]);
+
+    releases_mock.assert();
+    output.assert_failure();
+    output.assert_stderr_contains("SourceBuildNeedsExactVersion");
+}
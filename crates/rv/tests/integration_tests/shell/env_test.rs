@@ -105,6 +105,7 @@ fn test_shell_env_clears_ruby_and_gem_vars_when_no_rubies_available() {
         "GEM_PATH".into(),
         "/tmp/root/.gems/bin:/tmp/ruby/gems".into(),
     );
+    test.env.insert("JRUBY_HOME".into(), "/tmp/jruby".into());
     let output = test.rv(&["shell", "env", "zsh"]);
 
     assert_snapshot!(output.normalized_stdout());
@@ -186,7 +187,7 @@ fn test_shell_env_with_ruby_and_xdg_compatible_gem_path() {
 
     #[cfg(unix)]
     assert_snapshot!(stdout, @r"
-    unset RUBYOPT
+    unset RUBYOPT JRUBY_HOME
     export RUBY_ROOT=/tmp/home/.local/share/rv/rubies/ruby-3.3.5
     export RUBY_ENGINE=ruby
     export RUBY_VERSION=3.3.5
@@ -203,7 +204,7 @@ fn test_shell_env_with_ruby_and_xdg_compatible_gem_path() {
     // but the quotes remain. This is semantically correct for bash.
     #[cfg(windows)]
     assert_snapshot!(stdout, @r"
-    unset RUBYOPT
+    unset RUBYOPT JRUBY_HOME
     export RUBY_ROOT='/tmp/home/.local/share/rv/rubies/ruby-3.3.5'
     export RUBY_ENGINE=ruby
     export RUBY_VERSION=3.3.5
@@ -240,7 +241,7 @@ fn test_shell_env_with_ruby_and_legacy_gem_path() {
 
     #[cfg(unix)]
     assert_snapshot!(stdout, @r"
-    unset RUBYOPT
+    unset RUBYOPT JRUBY_HOME
     export RUBY_ROOT=/tmp/home/.local/share/rv/rubies/ruby-3.3.5
     export RUBY_ENGINE=ruby
     export RUBY_VERSION=3.3.5
@@ -253,7 +254,7 @@ fn test_shell_env_with_ruby_and_legacy_gem_path() {
 
     #[cfg(windows)]
     assert_snapshot!(stdout, @r"
-    unset RUBYOPT
+    unset RUBYOPT JRUBY_HOME
     export RUBY_ROOT='/tmp/home/.local/share/rv/rubies/ruby-3.3.5'
     export RUBY_ENGINE=ruby
     export RUBY_VERSION=3.3.5
@@ -282,6 +283,7 @@ fn test_powershell_env_with_ruby() {
     #[cfg(unix)]
     assert_snapshot!(stdout, @r#"
     Remove-Item Env:\RUBYOPT -ErrorAction SilentlyContinue
+    Remove-Item Env:\JRUBY_HOME -ErrorAction SilentlyContinue
     $env:RUBY_ROOT = "/tmp/home/.local/share/rv/rubies/ruby-3.3.5"
     $env:RUBY_ENGINE = "ruby"
     $env:RUBY_VERSION = "3.3.5"
@@ -294,6 +296,7 @@ fn test_powershell_env_with_ruby() {
     #[cfg(windows)]
     assert_snapshot!(stdout, @r#"
     Remove-Item Env:\RUBYOPT -ErrorAction SilentlyContinue
+    Remove-Item Env:\JRUBY_HOME -ErrorAction SilentlyContinue
     $env:RUBY_ROOT = "/tmp/home/.local/share/rv/rubies/ruby-3.3.5"
     $env:RUBY_ENGINE = "ruby"
     $env:RUBY_VERSION = "3.3.5"